@@ -89,11 +89,8 @@ fn main() -> Result<(), Error> {
 
     let userns = Userns::builder().context("failed to prepare user namespace")?;
     userns
-        .map_gids(&gid_mappings)
-        .context("failed to map group ids")?;
-    userns
-        .map_uids(&uid_mappings)
-        .context("failed to map user ids")?;
+        .map(&uid_mappings, &gid_mappings)
+        .context("failed to map user and group ids")?;
     let userns = userns
         .into_fd()
         .context("failed to finish creating user namespace")?;
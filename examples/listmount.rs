@@ -1,9 +1,7 @@
 use std::io;
 
-use anyhow::{Context as _, Error, bail};
+use anyhow::{Context as _, Error};
 
-use lisy::mount::ListMounts;
-use lisy::mount::MountId;
 use lisy::mount::StatMount;
 
 fn usage(mut out: impl io::Write, status: i32) -> ! {
@@ -78,7 +76,7 @@ fn main() -> Result<(), Error> {
         );
     }
 
-    for id in ListMounts::here() {
+    for id in mnt_ns.list_mounts().context("failed to list mounts")? {
         let id = id.context("listmount failed")?;
         println!("\x1b[48;5;238mid: {id:?}\x1b[0K\x1b[0m");
 
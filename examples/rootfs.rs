@@ -0,0 +1,83 @@
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use anyhow::{Context as _, Error};
+
+use lisy::mount::{Builder, MountAttr, MountStep};
+use lisy::userns::{IdMapping, Userns};
+
+fn usage(mut out: impl io::Write, status: i32) -> ! {
+    let _ = writeln!(
+        out,
+        "\
+usage: rootfs [options...] destination
+options:
+  -m host:ns:count      idmap the bind mounts with this uid and gid range
+"
+    );
+
+    std::process::exit(status);
+}
+
+fn main() -> Result<(), Error> {
+    let mut args = std::env::args_os().skip(1);
+
+    let mut mapping = None;
+    let mut dest = None;
+    while let Some(arg_os) = args.next() {
+        let arg = arg_os.as_bytes();
+
+        if arg == b"-h" || arg == b"--help" {
+            usage(std::io::stdout(), 0);
+        }
+
+        if arg == b"-m" {
+            let Some(value) = args.next() else {
+                usage(std::io::stderr(), 1);
+            };
+            let value = value.to_str().context("non-utf8 id mapping")?;
+            mapping = Some(IdMapping::parse_common(value)?);
+        } else if arg.starts_with(b"-") {
+            usage(std::io::stderr(), 1);
+        } else {
+            dest = Some(PathBuf::from(arg_os));
+        }
+    }
+
+    let Some(dest) = dest else {
+        usage(std::io::stderr(), 1);
+    };
+
+    let userns = match mapping {
+        Some(mapping) => {
+            let userns = Userns::builder().context("failed to prepare user namespace")?;
+            userns.map(&[mapping], &[mapping])?;
+            Some(
+                userns
+                    .into_fd()
+                    .context("failed to create user namespace")?,
+            )
+        }
+        None => None,
+    };
+
+    let bind = |source: &str| {
+        let step = MountStep::bind(source, source)
+            .set(MountAttr::RDONLY | MountAttr::NODEV)
+            .create_target(true);
+        match &userns {
+            Some(userns) => step.idmap(userns),
+            None => step,
+        }
+    };
+
+    Builder::new(&dest)
+        .step(MountStep::fs("tmpfs", "mode=0755", "/").set(MountAttr::NODEV | MountAttr::NOSUID))
+        .step(bind("/usr"))
+        .step(bind("/etc"))
+        .step(MountStep::fs("proc", "", "/proc").create_target(true))
+        .execute()?;
+
+    Ok(())
+}
@@ -3,6 +3,135 @@
 #![allow(unused_macros)]
 #![allow(unused_imports)]
 
+use std::error::Error as StdError;
+use std::ffi::c_int;
+use std::fmt;
+use std::io;
+
+/// An error from a system call, carrying the name of the system call which failed.
+///
+/// With the `syscall-errors` feature enabled, errors from system calls are wrapped in this type,
+/// which is then turned into an [`io::Error`] of the same [`kind`](io::Error::kind()), displayed
+/// as eg. `openat2 failed: Operation not permitted`.
+///
+/// Note that such an [`io::Error`]'s [`raw_os_error`](io::Error::raw_os_error()) is `None`, use
+/// [`raw_os_error`](crate::raw_os_error()) or [`SyscallError::from_io_error`] to get to the
+/// `errno` value instead.
+#[derive(Debug)]
+pub struct SyscallError {
+    syscall: &'static str,
+    error: io::Error,
+}
+
+impl SyscallError {
+    /// Wrap an error returned by a system call.
+    pub fn new(syscall: &'static str, error: io::Error) -> Self {
+        Self { syscall, error }
+    }
+
+    /// Create an error from `errno` for a system call which just failed.
+    pub fn last_os_error(syscall: &'static str) -> Self {
+        Self::new(syscall, io::Error::last_os_error())
+    }
+
+    /// The name of the system call which failed.
+    pub fn syscall(&self) -> &'static str {
+        self.syscall
+    }
+
+    /// The `errno` value returned by the system call.
+    pub fn raw_os_error(&self) -> Option<c_int> {
+        self.error.raw_os_error()
+    }
+
+    /// Get the original error.
+    pub fn into_inner(self) -> io::Error {
+        self.error
+    }
+
+    /// Get the `SyscallError` an [`io::Error`] was created from, if any.
+    pub fn from_io_error(err: &io::Error) -> Option<&Self> {
+        err.get_ref()?.downcast_ref()
+    }
+}
+
+impl fmt::Display for SyscallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} failed: {}", self.syscall, self.error)
+    }
+}
+
+impl StdError for SyscallError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<SyscallError> for io::Error {
+    fn from(err: SyscallError) -> io::Error {
+        io::Error::new(err.error.kind(), err)
+    }
+}
+
+/// An error with a message describing what failed, keeping the original error as its source.
+#[derive(Debug)]
+struct ContextError {
+    context: String,
+    error: io::Error,
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.context, self.error)
+    }
+}
+
+impl StdError for ContextError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Prefix an error with `context`, keeping its [`kind`](io::Error::kind()) and `errno` value.
+#[allow(dead_code)]
+pub(crate) fn context<C>(err: io::Error, context: C) -> io::Error
+where
+    C: fmt::Display,
+{
+    let kind = err.kind();
+    let context = ContextError {
+        context: context.to_string(),
+        error: err,
+    };
+    io::Error::new(kind, context)
+}
+
+/// Get the `errno` value of an error, looking through [`SyscallError`]s and added context.
+///
+/// Use this instead of [`io::Error::raw_os_error`] for errors returned by this crate, as the latter
+/// returns `None` for errors with a system call name or context attached.
+pub fn raw_os_error(err: &io::Error) -> Option<c_int> {
+    if let Some(err) = SyscallError::from_io_error(err) {
+        return err.raw_os_error();
+    }
+    match err
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<ContextError>())
+    {
+        Some(inner) => raw_os_error(&inner.error),
+        None => err.raw_os_error(),
+    }
+}
+
+/// Attach the system call name to an error if the `syscall-errors` feature is enabled.
+pub(crate) fn syscall_error(syscall: &'static str, err: io::Error) -> io::Error {
+    if cfg!(feature = "syscall-errors") {
+        SyscallError::new(syscall, err).into()
+    } else {
+        err
+    }
+}
+
 /// Like failure's `format_err` but producing a `std::io::Error`.
 macro_rules! io_format_err {
     ($($msg:tt)+) => {
@@ -22,20 +151,32 @@ pub(crate) use io_bail;
 /// Shortcut to return an `io::Error::last_os_error`.
 ///
 /// This is effectively `return Err(::std::io::Error::last_os_error().into());`.
+///
+/// The name of the failed system call can be passed for the `syscall-errors` feature.
 macro_rules! io_bail_last {
     () => {
         return Err(::std::io::Error::last_os_error().into());
     };
+    ($syscall:literal) => {
+        return Err(
+            $crate::error::syscall_error($syscall, ::std::io::Error::last_os_error()).into(),
+        );
+    };
 }
 pub(crate) use io_bail_last;
 
 /// Non-panicking assertion: shortcut for returning an `io::Error` if the condition is not met.
-/// Essentially: `if !expr { io_bail_last!() }`.
+/// Essentially: `if !expr { io_bail_last!() }`, optionally with the system call's name.
 macro_rules! io_assert {
     ($value:expr) => {
         if !$value {
             $crate::error::io_bail_last!();
         }
     };
+    ($value:expr, $syscall:literal) => {
+        if !$value {
+            $crate::error::io_bail_last!($syscall);
+        }
+    };
 }
 pub(crate) use io_assert;
@@ -0,0 +1,232 @@
+//! Publishing files atomically via `O_TMPFILE` and `linkat(2)`.
+
+use std::error::Error as StdError;
+use std::ffi::CStr;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+
+use crate::CPath;
+use crate::error::io_assert;
+use crate::open::OpenHow;
+
+/// A file which only appears in its directory once it is complete.
+///
+/// The data is written to an anonymous `O_TMPFILE` in the target directory, which has no name
+/// and is removed automatically if the `AtomicFile` is dropped (or the process dies) before
+/// [`publish`](AtomicFile::publish()) links it into place. Readers therefore either see no file
+/// at all or the full contents, never a partially written one.
+///
+/// ``` rust, no_run
+/// # fn code() -> std::io::Result<()> {
+/// use std::io::Write;
+///
+/// use lisy::fs::AtomicFile;
+///
+/// let mut file = AtomicFile::create("/etc/app", 0o644)?;
+/// file.write_all(b"key = value\n")?;
+/// file.file().sync_all()?;
+/// file.publish("app.conf")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The file system has to support `O_TMPFILE`, which most local ones (ext4, xfs, btrfs, tmpfs)
+/// do, otherwise creating the file fails with `EOPNOTSUPP`.
+#[derive(Debug)]
+pub struct AtomicFile {
+    dir: OwnedFd,
+    file: File,
+}
+
+impl AtomicFile {
+    /// Create an anonymous file in the directory at `dir`, to be created with `mode` (subject to
+    /// the umask) once published.
+    pub fn create<P>(dir: &P, mode: u32) -> io::Result<Self>
+    where
+        P: ?Sized + CPath,
+    {
+        Self::new_in(&OpenHow::new_directory().open(dir)?, mode)
+    }
+
+    /// Create an anonymous file in the directory referred to by `dir`.
+    ///
+    /// The file descriptor is duplicated, as it is needed again when publishing the file.
+    pub fn new_in<D>(dir: &D, mode: u32) -> io::Result<Self>
+    where
+        D: ?Sized + AsFd,
+    {
+        let dir = dir.as_fd().try_clone_to_owned()?;
+        let file = OpenHow::new_rw()
+            .flags(libc::O_TMPFILE as u64)
+            .mode(u64::from(mode))
+            .at_fd(&dir)
+            .open_file(c".")?;
+        Ok(Self { dir, file })
+    }
+
+    /// Access the underlying file, for instance to `sync_all` it or set its permissions before
+    /// publishing.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Mutably access the underlying file.
+    pub fn file_mut(&mut self) -> &mut File {
+        &mut self.file
+    }
+
+    /// Give the file the name `name` in its directory, returning the now visible file.
+    ///
+    /// `name` is relative to the directory the file was created in. This never replaces an
+    /// existing file, instead failing with `EEXIST`. On failure the `AtomicFile` is returned
+    /// inside the [`PublishError`], so it can still be published under a different name. The
+    /// error converts into an [`io::Error`] (dropping the file) for use with `?`.
+    ///
+    /// The file is linked via `linkat(2)` with `AT_EMPTY_PATH`, which requires
+    /// `CAP_DAC_READ_SEARCH` on kernels before 6.10. If that is refused, it is linked through its
+    /// `/proc/self/fd/<fd>` magic link with `AT_SYMLINK_FOLLOW` instead, which requires procfs.
+    ///
+    /// This makes the file's *name* appear atomically. For the contents to survive a crash, call
+    /// [`sync_all`](File::sync_all()) on the [`file`](AtomicFile::file()) before publishing, and
+    /// sync the directory afterwards.
+    pub fn publish<P>(self, name: &P) -> Result<File, PublishError>
+    where
+        P: ?Sized + CPath,
+    {
+        match name.c_path(|name| self.publish_do(name)) {
+            Ok(Ok(())) => Ok(self.file),
+            Ok(Err(error)) | Err(error) => Err(PublishError { error, file: self }),
+        }
+    }
+
+    fn publish_do(&self, name: &CStr) -> io::Result<()> {
+        let rc = unsafe {
+            libc::linkat(
+                self.file.as_raw_fd(),
+                c"".as_ptr(),
+                self.dir.as_raw_fd(),
+                name.as_ptr(),
+                libc::AT_EMPTY_PATH,
+            )
+        };
+        if rc == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if !matches!(err.raw_os_error(), Some(libc::ENOENT | libc::EPERM)) {
+            return Err(crate::error::syscall_error("linkat", err));
+        }
+
+        let magic = format!("/proc/self/fd/{}\0", self.file.as_raw_fd());
+        let rc = unsafe {
+            libc::linkat(
+                libc::AT_FDCWD,
+                magic.as_ptr() as *const libc::c_char,
+                self.dir.as_raw_fd(),
+                name.as_ptr(),
+                libc::AT_SYMLINK_FOLLOW,
+            )
+        };
+        io_assert!(rc == 0, "linkat");
+        Ok(())
+    }
+}
+
+/// The error returned by [`AtomicFile::publish`], holding on to the unpublished file.
+#[derive(Debug)]
+pub struct PublishError {
+    error: io::Error,
+    file: AtomicFile,
+}
+
+impl PublishError {
+    /// The error which occurred.
+    pub fn error(&self) -> &io::Error {
+        &self.error
+    }
+
+    /// Get the unpublished file back, for instance to retry with a different name.
+    pub fn into_inner(self) -> AtomicFile {
+        self.file
+    }
+}
+
+impl fmt::Display for PublishError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to publish file: {}", self.error)
+    }
+}
+
+impl StdError for PublishError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<PublishError> for io::Error {
+    fn from(err: PublishError) -> io::Error {
+        err.error
+    }
+}
+
+impl io::Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl io::Read for AtomicFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl io::Seek for AtomicFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl AsRawFd for AtomicFile {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for AtomicFile {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn publish() {
+        let tmp = TempDir::new().unwrap();
+        std::fs::write(tmp.path().join("existing"), b"old").unwrap();
+
+        let mut file = AtomicFile::create(tmp.path(), 0o644).unwrap();
+        file.write_all(b"new").unwrap();
+        let entries = std::fs::read_dir(tmp.path()).unwrap().count();
+        assert_eq!(entries, 1);
+
+        let err = file.publish("existing").unwrap_err();
+        assert_eq!(crate::raw_os_error(err.error()), Some(libc::EEXIST));
+        assert_eq!(std::fs::read(tmp.path().join("existing")).unwrap(), b"old");
+
+        err.into_inner().publish("published").unwrap();
+        assert_eq!(std::fs::read(tmp.path().join("published")).unwrap(), b"new");
+    }
+}
@@ -0,0 +1,144 @@
+//! Checking whether paths stay beneath a directory.
+
+use std::ffi::CStr;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd};
+use std::path::PathBuf;
+
+use crate::CPath;
+use crate::open::OpenHow;
+
+/// The result of [`is_confined`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Confinement {
+    /// The path resolves to an existing file beneath the root.
+    Confined,
+    /// Resolving the path leaves the root, via `..`, an absolute path or a symlink.
+    Escapes,
+    /// A component of the path does not exist.
+    ///
+    /// Since resolution stops at the missing component, nothing is known about the rest of the
+    /// path.
+    NotFound,
+}
+
+/// Check whether `path` stays beneath the directory `root` when resolved relative to it.
+///
+/// The path is opened with `O_PATH` and `RESOLVE_BENEATH`, so the kernel refuses absolute paths,
+/// as well as `..` components and symlinks (including absolute ones) which would leave `root`,
+/// without following them. Symlinks which stay inside are fine. Magic links, such as the ones
+/// in `/proc/<pid>/fd`, cannot be verified and therefore count as escaping.
+///
+/// Keep in mind that the result only describes the file system at the time of the call. To
+/// actually access the file, open it with the same restriction, for example via
+/// [`OpenHow::sandbox_beneath`], rather than checking first and opening by path afterwards.
+///
+/// Errors other than escaping or a missing component, such as `EACCES`, are returned as such.
+pub fn is_confined<D, P>(root: &D, path: &P) -> io::Result<Confinement>
+where
+    D: ?Sized + AsFd,
+    P: ?Sized + CPath,
+{
+    let result = OpenHow::new()
+        .flags(libc::O_PATH as u64)
+        .resolve_beneath(true)
+        .at_fd(root)
+        .open(path);
+
+    match result {
+        Ok(_) => Ok(Confinement::Confined),
+        Err(err) => match crate::raw_os_error(&err) {
+            Some(libc::EXDEV) => Ok(Confinement::Escapes),
+            Some(libc::ENOENT) => Ok(Confinement::NotFound),
+            _ => Err(err),
+        },
+    }
+}
+
+/// Read the target of the symlink at `path` inside the directory `root`, for resolving symlinks
+/// in userspace one hop at a time.
+///
+/// The link is looked up with `RESOLVE_IN_ROOT`, treating `root` as the root directory, and its
+/// final component is not followed. What happens with the target depends on its kind:
+///
+/// * A relative target is returned as is. It is relative to the directory containing the link,
+///   not to `root`.
+/// * An absolute target is rebased onto `root`: the returned path still starts with `/`, but that
+///   now stands for `root`. It is resolved with `RESOLVE_IN_ROOT` before returning, so following
+///   it cannot leave `root` (`..` components stop at `root`, and magic links are refused). A target
+///   which does not exist is fine, since dangling links are common in container images.
+///
+/// Only this one link is read. The target may itself contain symlinks, so callers walking a path
+/// have to loop, and should limit the number of hops like the kernel does (40), to bail out of
+/// symlink cycles.
+///
+/// If `path` is not a symlink, this fails with an [`InvalidInput`](io::ErrorKind::InvalidInput)
+/// error.
+pub fn readlink_confined<D, P>(root: &D, path: &P) -> io::Result<PathBuf>
+where
+    D: ?Sized + AsFd,
+    P: ?Sized + CPath,
+{
+    path.c_path(|path| readlink_confined_do(root.as_fd(), path))?
+}
+
+fn readlink_confined_do(root: BorrowedFd, path: &CStr) -> io::Result<PathBuf> {
+    let link = OpenHow::new()
+        .flags(libc::O_PATH as u64)
+        .no_final_symlink(true)
+        .resolve_in_root(true)
+        .at_fd(&root)
+        .open(path)?;
+
+    let target = match crate::open::read_link_fd(link.as_raw_fd()) {
+        Err(err) if crate::raw_os_error(&err) == Some(libc::ENOENT) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{path:?} is not a symlink"),
+            ));
+        }
+        other => other?,
+    };
+
+    if target.has_root() {
+        let result = OpenHow::new()
+            .flags(libc::O_PATH as u64)
+            .resolve_in_root(true)
+            .at_fd(&root)
+            .open(&target);
+        match result {
+            Err(err) if crate::raw_os_error(&err) != Some(libc::ENOENT) => return Err(err),
+            _ => (),
+        }
+    }
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn readlink_confined_targets() {
+        let tmp = TempDir::new().unwrap();
+        let root = std::fs::File::open(tmp.path()).unwrap();
+        std::fs::write(tmp.path().join("file"), b"").unwrap();
+        std::os::unix::fs::symlink("file", tmp.path().join("relative")).unwrap();
+        std::os::unix::fs::symlink("/../etc/passwd", tmp.path().join("absolute")).unwrap();
+
+        assert_eq!(
+            readlink_confined(&root, "relative").unwrap(),
+            Path::new("file")
+        );
+        assert_eq!(
+            readlink_confined(&root, "/absolute").unwrap(),
+            Path::new("/../etc/passwd")
+        );
+        let err = readlink_confined(&root, "file").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{err}");
+    }
+}
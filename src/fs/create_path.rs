@@ -17,6 +17,7 @@ pub struct CreatePath {
     mode: libc::mode_t,
     allow_symlinks: bool,
     resolve_in_root: bool,
+    no_xdev: bool,
 }
 
 impl Default for CreatePath {
@@ -33,6 +34,7 @@ impl CreatePath {
             mode: 0o777,
             allow_symlinks: false,
             resolve_in_root: false,
+            no_xdev: false,
         }
     }
 
@@ -57,6 +59,17 @@ impl CreatePath {
         self
     }
 
+    /// Refuse to cross into other mounts (including bind mounts) while walking the path.
+    ///
+    /// This sets `RESOLVE_NO_XDEV` when opening each directory on the way. Since `mkdirat(2)` has
+    /// no such flag, the check happens when the walk reaches a mount point, so creation is
+    /// aborted with an `EXDEV` error midway, with the directories up to that point already
+    /// created.
+    pub const fn no_xdev(mut self, no_xdev: bool) -> Self {
+        self.no_xdev = no_xdev;
+        self
+    }
+
     /// Perform the path creation starting a the directory `dfd`.
     pub fn create_at<D, P>(&self, dfd: &D, path: P) -> io::Result<OwnedFd>
     where
@@ -82,6 +95,7 @@ impl CreatePath {
                     let next = OpenHow::new_directory()
                         .no_final_symlink(!self.allow_symlinks)
                         .resolve_in_root(self.resolve_in_root)
+                        .resolve_no_xdev(self.no_xdev)
                         .open_at_raw(at_fd, &name)?;
                     at_fd = next.as_raw_fd();
                     at_owned = Some(next);
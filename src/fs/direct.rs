@@ -0,0 +1,247 @@
+//! Reading files opened with `O_DIRECT`.
+//!
+//! Direct I/O bypasses the page cache, but in exchange the kernel requires the memory buffer, the
+//! file offset and the length of each request to be suitably aligned. The required alignments
+//! can be queried via `statx(2)` with `STATX_DIOALIGN` (kernel 6.1).
+
+use std::io;
+use std::os::fd::{AsFd, AsRawFd};
+
+use crate::error::{io_assert, io_bail};
+use crate::fs::Stat;
+
+/// The default amount of data read from the file at once.
+const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// A zeroed heap buffer of `len` bytes starting at a specific alignment.
+///
+/// This over-allocates a `Vec` by the alignment and uses the aligned part of it.
+struct AlignedBuf {
+    data: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl AlignedBuf {
+    fn new(len: usize, align: usize) -> io::Result<Self> {
+        let data = vec![0u8; len.saturating_add(align - 1)];
+        let start = data.as_ptr().align_offset(align);
+        if start >= align || data.len() - start < len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot allocate an aligned direct I/O buffer",
+            ));
+        }
+        Ok(Self { data, start, len })
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.data[self.start..][..self.len]
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.data[self.start..][..self.len]
+    }
+}
+
+/// A reader for a file opened with `O_DIRECT`, taking care of the alignment requirements.
+///
+/// Data is read into an internal buffer allocated with the memory alignment reported by the
+/// kernel, in chunks starting at aligned file offsets, and then copied out. This means arbitrary
+/// offsets and lengths can be used with [`read_at`](DirectReader::read_at()) and the
+/// [`Read`](io::Read) implementation, at the cost of reading some surrounding data for unaligned
+/// requests.
+///
+/// ``` rust, no_run
+/// # fn code() -> std::io::Result<()> {
+/// use std::io::Read;
+///
+/// use lisy::fs::DirectReader;
+/// use lisy::open::OpenHow;
+///
+/// let fd = OpenHow::new_read()
+///     .flags(libc::O_DIRECT as u64)
+///     .open("/var/lib/images/disk.raw")?;
+/// let mut header = [0u8; 512];
+/// DirectReader::new(fd)?.read_exact(&mut header)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct DirectReader<F> {
+    fd: F,
+    buf: AlignedBuf,
+    offset_align: u64,
+    pos: u64,
+}
+
+impl<F: AsFd> DirectReader<F> {
+    /// Create a reader for `fd` with the default chunk size of 1 MiB.
+    ///
+    /// Fails with [`Unsupported`](io::ErrorKind::Unsupported) if the kernel or the file system
+    /// does not report direct I/O alignment for the file, and with
+    /// [`InvalidInput`](io::ErrorKind::InvalidInput) if `fd` was not opened with `O_DIRECT`.
+    pub fn new(fd: F) -> io::Result<Self> {
+        Self::with_chunk_size(fd, DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Create a reader for `fd` reading up to `chunk_size` bytes at once.
+    ///
+    /// The chunk size is rounded up to a multiple of the required offset alignment.
+    pub fn with_chunk_size(fd: F, chunk_size: usize) -> io::Result<Self> {
+        let flags = unsafe { libc::fcntl(fd.as_fd().as_raw_fd(), libc::F_GETFL) };
+        io_assert!(flags >= 0, "fcntl");
+        if flags & libc::O_DIRECT == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "file descriptor was not opened with O_DIRECT",
+            ));
+        }
+
+        let meta = Stat::new_empty().dio_align(true).at_fd(&fd).stat_fd()?;
+        let (mem_align, offset_align) = match (meta.dio_mem_align(), meta.dio_offset_align()) {
+            (Some(mem), Some(offset)) if mem != 0 && offset != 0 => (mem as usize, offset as usize),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "direct I/O is not supported for this file",
+                ));
+            }
+        };
+        if !mem_align.is_power_of_two() || !offset_align.is_power_of_two() {
+            io_bail!("unexpected direct I/O alignment ({mem_align}/{offset_align})");
+        }
+
+        let size = chunk_size.max(1).next_multiple_of(offset_align);
+        Ok(Self {
+            fd,
+            buf: AlignedBuf::new(size, mem_align)?,
+            offset_align: offset_align as u64,
+            pos: 0,
+        })
+    }
+
+    /// Read data at `offset` into `out`, returning the number of bytes read.
+    ///
+    /// Like `pread(2)`, this does not use or change the current position and may read fewer bytes
+    /// than requested, at most one chunk. `0` means the end of the file was reached.
+    pub fn read_at(&mut self, offset: u64, out: &mut [u8]) -> io::Result<usize> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+
+        let start = offset - offset % self.offset_align;
+        let skip = (offset - start) as usize;
+        let len = (skip + out.len())
+            .next_multiple_of(self.offset_align as usize)
+            .min(self.buf.len());
+
+        let got = loop {
+            let rc = unsafe {
+                libc::pread(
+                    self.fd.as_fd().as_raw_fd(),
+                    self.buf.as_mut_slice().as_mut_ptr() as *mut libc::c_void,
+                    len,
+                    start as libc::off_t,
+                )
+            };
+            if rc >= 0 {
+                break rc as usize;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(crate::error::syscall_error("pread", err));
+            }
+        };
+
+        if got <= skip {
+            return Ok(0);
+        }
+        let count = (got - skip).min(out.len());
+        out[..count].copy_from_slice(&self.buf.as_slice()[skip..skip + count]);
+        Ok(count)
+    }
+
+    /// The position the next [`read`](io::Read::read()) starts at.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Change the position the next [`read`](io::Read::read()) starts at.
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    /// Get the underlying file descriptor back.
+    pub fn into_inner(self) -> F {
+        self.fd
+    }
+}
+
+impl<F: AsFd> io::Read for DirectReader<F> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let count = self.read_at(self.pos, out)?;
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open::OpenHow;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn aligned_buf() {
+        for align in [1, 512, 4096] {
+            let buf = AlignedBuf::new(8192, align).unwrap();
+            assert_eq!(buf.as_slice().as_ptr() as usize % align, 0);
+            assert_eq!(buf.as_slice(), &[0u8; 8192][..]);
+        }
+    }
+
+    #[test]
+    fn read_direct() {
+        use std::io::Read;
+
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("file");
+        let data: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let fd = match OpenHow::new_read().flags(libc::O_DIRECT as u64).open(&path) {
+            Ok(fd) => fd,
+            // tmpfs and others do not support O_DIRECT
+            Err(err) if crate::raw_os_error(&err) == Some(libc::EINVAL) => {
+                eprintln!("skipping test, O_DIRECT is not supported: {err}");
+                return;
+            }
+            Err(err) => panic!("failed to open {path:?}: {err}"),
+        };
+        let mut reader = match DirectReader::with_chunk_size(fd, 4096) {
+            Ok(reader) => reader,
+            Err(err) if err.kind() == io::ErrorKind::Unsupported => {
+                eprintln!("skipping test: {err}");
+                return;
+            }
+            Err(err) => panic!("failed to create the reader: {err}"),
+        };
+
+        let mut out = [0u8; 100];
+        assert_eq!(reader.read_at(1000, &mut out).unwrap(), 100);
+        assert_eq!(out[..], data[1000..1100]);
+        assert_eq!(reader.read_at(9950, &mut out).unwrap(), 50);
+        assert_eq!(out[..50], data[9950..]);
+        assert_eq!(reader.read_at(10_000, &mut out).unwrap(), 0);
+
+        reader.set_position(3);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, data[3..]);
+        assert_eq!(reader.position(), 10_000);
+    }
+}
@@ -0,0 +1,106 @@
+//! `chattr(1)` style inode flags via the `FS_IOC_GETFLAGS` and `FS_IOC_SETFLAGS` ioctls.
+//!
+//! While [`Metadata`](crate::fs::stat::Metadata) can tell whether a file is eg. immutable or
+//! append-only, changing these attributes requires these ioctls.
+
+use std::ffi::{c_int, c_long, c_uint};
+use std::io;
+use std::os::fd::{AsFd, AsRawFd};
+
+use bitflags::bitflags;
+
+use crate::error::io_assert;
+use crate::ioctl::{ior, iow};
+
+// Note that while these are defined with a `long` parameter, the kernel actually uses an `int`.
+const FS_IOC_GETFLAGS: c_int = ior::<c_long>(b'f' as c_int, 1);
+const FS_IOC_SETFLAGS: c_int = iow::<c_long>(b'f' as c_int, 2);
+
+bitflags! {
+    /// Inode flags as used by `chattr(1)` and `lsattr(1)`.
+    ///
+    /// Which flags are supported depends on the file system.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+    #[repr(transparent)]
+    pub struct InodeFlags: c_uint {
+        /// Secure deletion (`s`).
+        const SECRM        = 0x0000_0001;
+        /// Undelete (`u`).
+        const UNRM         = 0x0000_0002;
+        /// Compress file (`c`).
+        const COMPR        = 0x0000_0004;
+        /// Synchronous updates (`S`).
+        const SYNC         = 0x0000_0008;
+        /// Immutable file (`i`).
+        const IMMUTABLE    = 0x0000_0010;
+        /// Writes to the file may only append (`a`).
+        const APPEND       = 0x0000_0020;
+        /// Do not dump the file (`d`).
+        const NODUMP       = 0x0000_0040;
+        /// Do not update the access time (`A`).
+        const NOATIME      = 0x0000_0080;
+        /// Encrypted file (`E`, read-only).
+        const ENCRYPT      = 0x0000_0800;
+        /// Hash-indexed directory (`I`, read-only).
+        const INDEX        = 0x0000_1000;
+        /// Data journaling (`j`).
+        const JOURNAL_DATA = 0x0000_4000;
+        /// No tail merging (`t`).
+        const NOTAIL       = 0x0000_8000;
+        /// Synchronous directory updates (`D`).
+        const DIRSYNC      = 0x0001_0000;
+        /// Top of directory hierarchies (`T`).
+        const TOPDIR       = 0x0002_0000;
+        /// Extent mapped file (`e`, read-only).
+        const EXTENT       = 0x0008_0000;
+        /// Verity protected file (`V`, read-only).
+        const VERITY       = 0x0010_0000;
+        /// No copy-on-write (`C`).
+        const NOCOW        = 0x0080_0000;
+        /// Direct access (`x`).
+        const DAX          = 0x0200_0000;
+        /// Inline data (`N`, read-only).
+        const INLINE_DATA  = 0x1000_0000;
+        /// Inherit the project id (`P`).
+        const PROJINHERIT  = 0x2000_0000;
+        /// Case-insensitive directory lookups (`F`).
+        const CASEFOLD     = 0x4000_0000;
+    }
+}
+
+/// Get the inode flags of a file.
+///
+/// Flags unknown to [`InodeFlags`] are retained, so the result can safely be modified and passed
+/// to [`set_inode_flags`].
+pub fn get_inode_flags<F>(fd: &F) -> io::Result<InodeFlags>
+where
+    F: ?Sized + AsFd,
+{
+    let mut flags: c_int = 0;
+    let rc = unsafe { libc::ioctl(fd.as_fd().as_raw_fd(), FS_IOC_GETFLAGS as _, &raw mut flags) };
+    io_assert!(rc == 0, "ioctl");
+    Ok(InodeFlags::from_bits_retain(flags as c_uint))
+}
+
+/// Set the inode flags of a file.
+///
+/// This *replaces* all flags, so to change a single flag, the current flags should be queried via
+/// [`get_inode_flags`] first.
+///
+/// Note that changing the `IMMUTABLE` or `APPEND` flags requires the `CAP_LINUX_IMMUTABLE`
+/// capability.
+pub fn set_inode_flags<F>(fd: &F, flags: InodeFlags) -> io::Result<()>
+where
+    F: ?Sized + AsFd,
+{
+    let flags = flags.bits() as c_int;
+    let rc = unsafe {
+        libc::ioctl(
+            fd.as_fd().as_raw_fd(),
+            FS_IOC_SETFLAGS as _,
+            &raw const flags,
+        )
+    };
+    io_assert!(rc == 0, "ioctl");
+    Ok(())
+}
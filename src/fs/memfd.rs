@@ -0,0 +1,136 @@
+//! Anonymous in-memory files via `memfd_create(2)`.
+//!
+//! A memfd behaves like a regular file on `tmpfs`, but it is not linked anywhere in the file
+//! system and is released once the last reference to it is gone. With sealing it can be made
+//! immutable, which makes it a good source for in-memory file system images:
+//!
+//! ``` rust, no_run
+//! # fn code(image: &[u8]) -> std::io::Result<()> {
+//! use std::fs::File;
+//! use std::io::Write;
+//! use std::os::fd::AsRawFd;
+//!
+//! use lisy::fs::{MemfdFlags, memfd};
+//! use lisy::mount::{Fs, FsMount, FsOpen, MountAttr};
+//!
+//! let mut file = File::from(memfd("image", MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING)?);
+//! file.write_all(image)?;
+//!
+//! // Forbid any further modification, as well as changing the seals themselves.
+//! let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+//! if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } != 0 {
+//!     return Err(std::io::Error::last_os_error());
+//! }
+//!
+//! // The `source` option is always a string, file backed `erofs` mounts (Linux 6.12) open it
+//! // as a path while creating the superblock, so `file` needs to stay open until then.
+//! let fs = Fs::open("erofs", FsOpen::CLOEXEC)?;
+//! fs.set_string("source", format!("/proc/self/fd/{}", file.as_raw_fd()))?;
+//! let mount = fs.create()?.mount(FsMount::CLOEXEC, MountAttr::RDONLY)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Note that `F_SEAL_WRITE` fails with `EBUSY` while writable shared mappings of the file exist.
+
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::raw::c_uint;
+
+use bitflags::bitflags;
+
+use crate::error::{io_assert, io_format_err};
+
+bitflags! {
+    /// Flags for [`memfd`].
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+    #[repr(transparent)]
+    pub struct MemfdFlags: c_uint {
+        /// Set the close-on-exec flag on the file descriptor.
+        const CLOEXEC       = 0x0001;
+        /// Allow seals to be added via `fcntl(F_ADD_SEALS)`. Without this, the file starts out
+        /// with `F_SEAL_SEAL` set.
+        const ALLOW_SEALING = 0x0002;
+    }
+}
+
+/// Create an anonymous in-memory file.
+///
+/// The `name` is only used for display purposes, it shows up as the target of the
+/// `/proc/self/fd` symlink, prefixed with `memfd:`. The kernel limits it to 249 bytes.
+pub fn memfd(name: &str, flags: MemfdFlags) -> io::Result<OwnedFd> {
+    let name = CString::new(name).map_err(|_| io_format_err!("null byte in memfd name"))?;
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), flags.bits()) };
+    io_assert!(fd >= 0, "memfd_create");
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Read, Seek, Write};
+    use std::os::fd::AsRawFd;
+
+    use super::*;
+
+    fn seals(file: &File) -> i32 {
+        let seals = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GET_SEALS) };
+        assert!(seals >= 0, "{}", io::Error::last_os_error());
+        seals
+    }
+
+    fn add_seals(file: &File, seals: i32) -> io::Result<()> {
+        match unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    #[test]
+    fn sealing() {
+        let mut file =
+            File::from(memfd("test", MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING).unwrap());
+        assert_eq!(seals(&file), 0);
+        file.write_all(b"data").unwrap();
+
+        add_seals(
+            &file,
+            libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE,
+        )
+        .unwrap();
+        assert_eq!(
+            file.write(b"more").unwrap_err().raw_os_error(),
+            Some(libc::EPERM)
+        );
+        assert_eq!(
+            file.set_len(0).unwrap_err().raw_os_error(),
+            Some(libc::EPERM)
+        );
+
+        file.rewind().unwrap();
+        let mut data = String::new();
+        file.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "data");
+
+        add_seals(&file, libc::F_SEAL_SEAL).unwrap();
+        assert_eq!(
+            add_seals(&file, libc::F_SEAL_FUTURE_WRITE)
+                .unwrap_err()
+                .raw_os_error(),
+            Some(libc::EPERM)
+        );
+    }
+
+    #[test]
+    fn sealing_not_allowed() {
+        let file = File::from(memfd("test", MemfdFlags::CLOEXEC).unwrap());
+        assert_eq!(seals(&file), libc::F_SEAL_SEAL);
+        assert_eq!(
+            add_seals(&file, libc::F_SEAL_WRITE)
+                .unwrap_err()
+                .raw_os_error(),
+            Some(libc::EPERM)
+        );
+    }
+}
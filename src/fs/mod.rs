@@ -1,13 +1,50 @@
 //! Some higher-level file system operations found in `std::fs` with file descriptor support, such
 //! as `create_dir_all` but with a file descriptor as first parameter.
 
+#[doc(inline)]
+pub use crate::types::Device;
+
+pub mod types;
+#[doc(inline)]
+pub use types::EntryType;
+
 mod create_path;
 pub use create_path::CreatePath;
 
+pub mod realpath;
+#[doc(inline)]
+pub use realpath::realpath_no_symlinks;
+
+mod atomic_file;
+pub use atomic_file::{AtomicFile, PublishError};
+
+mod confine;
+pub use confine::{Confinement, is_confined, readlink_confined};
+
 pub mod read_dir;
 #[doc(inline)]
-pub use read_dir::{ReadDir, read_dir};
+pub use read_dir::{ReadDir, read_dir, read_dir_full};
 
 pub mod stat;
 #[doc(inline)]
-pub use stat::Stat;
+pub use stat::{Stat, fd_is_mount_point, fd_mount_id, is_mount_point, same_file, same_subvolume};
+
+pub mod inode_flags;
+#[doc(inline)]
+pub use inode_flags::{InodeFlags, get_inode_flags, set_inode_flags};
+
+pub mod statfs;
+#[doc(inline)]
+pub use statfs::{FsStats, fs_label, fs_uuid, statfs, statfs_path};
+
+pub mod reflink;
+#[doc(inline)]
+pub use reflink::{reflink, reflink_range};
+
+pub mod direct;
+#[doc(inline)]
+pub use direct::DirectReader;
+
+pub mod memfd;
+#[doc(inline)]
+pub use memfd::{MemfdFlags, memfd};
@@ -1,5 +1,6 @@
 //! Read directories with `getdents64(2)`.
 
+use std::borrow::Cow;
 use std::ffi::{CStr, OsStr, OsString, c_char, c_uchar, c_ushort};
 use std::io;
 use std::mem::{align_of, offset_of};
@@ -7,8 +8,13 @@ use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
 
 use crate::CPath;
 use crate::error::{io_bail_last, io_format_err};
+use crate::fs::Stat;
+use crate::fs::stat::Metadata;
 use crate::open::OpenHow;
 
+#[doc(inline)]
+pub use crate::fs::types::EntryType;
+
 /// Iterate through the contents of a directory, see [`ReadDir`].
 ///
 /// Before opening the directory, the `O_RDWR | O_WRONLY | O_CREAT` flags are dropped
@@ -24,6 +30,47 @@ pub fn read_dir<P: ?Sized + CPath>(how: OpenHow, path: &P) -> io::Result<ReadDir
     .map(ReadDir::new)
 }
 
+/// Iterate through the contents of a directory along with each entry's metadata, like `ls -l`.
+///
+/// Each entry is stat'ed with the [basic stats](crate::fs::Stat::new()) relative to the directory's
+/// file descriptor when it is yielded, so the path never has to be resolved again, and only the
+/// consumed entries are stat'ed. Symlinks are not followed, so their own metadata is returned.
+///
+/// Entries removed between reading the directory and the `statx(2)` call produce an `ENOENT`
+/// error for that entry, after which iteration can continue.
+pub fn read_dir_full<P: ?Sized + CPath>(path: &P) -> io::Result<ReadDirFull> {
+    Ok(ReadDirFull {
+        inner: ReadDir::read(path)?,
+    })
+}
+
+/// An iterator through the contents of a directory and their metadata, see [`read_dir_full`].
+pub struct ReadDirFull {
+    inner: ReadDir,
+}
+
+impl Iterator for ReadDirFull {
+    type Item = io::Result<(DirEnt, Metadata)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = match self.inner.next()? {
+            Ok(entry) => entry,
+            Err(err) => return Some(Err(err)),
+        };
+        let meta = Stat::new()
+            .no_final_symlink(true)
+            .at_fd(&self.inner)
+            .stat(entry.name());
+        Some(meta.map(|meta| (entry, meta)))
+    }
+}
+
+impl AsFd for ReadDirFull {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
 /// An iterator through the contents of a directory (skipping `.` and `..` automatically).
 pub struct ReadDir {
     inner: GetDEnts,
@@ -58,6 +105,21 @@ impl ReadDir {
         ))
     }
 
+    /// Read all remaining entries and sort them by name, for output which does not depend on the
+    /// order the file system returns entries in.
+    ///
+    /// Names are compared byte-wise. Should the directory be modified while it is being read, the
+    /// same name may be returned more than once; only the first such entry is kept.
+    ///
+    /// This buffers the whole directory in memory. For huge directories, iterate over the
+    /// `ReadDir` directly instead.
+    pub fn collect_sorted(self) -> io::Result<Vec<DirEnt>> {
+        let mut entries = self.collect::<io::Result<Vec<DirEnt>>>()?;
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        entries.dedup_by(|a, b| a.name == b.name);
+        Ok(entries)
+    }
+
     fn new(fd: OwnedFd) -> ReadDir {
         Self {
             inner: GetDEnts::new(fd),
@@ -80,7 +142,7 @@ impl AsRawFd for ReadDir {
 }
 
 impl AsFd for ReadDir {
-    fn as_fd(&self) -> BorrowedFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
         self.inner.as_fd()
     }
 }
@@ -98,6 +160,12 @@ impl DirEnt {
         &self.name
     }
 
+    /// Get the name of this entry as UTF-8 for display purposes, replacing invalid sequences with
+    /// `U+FFFD`.
+    pub fn name_lossy(&self) -> Cow<'_, str> {
+        self.name.to_string_lossy()
+    }
+
     /// If only the name is of interest, we can move out the allocated string.
     pub fn into_name(self) -> OsString {
         self.name
@@ -110,77 +178,6 @@ impl DirEnt {
     }
 }
 
-/// The type of an entry in a directory listing.
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
-pub enum EntryType {
-    /// A block device.
-    Block,
-    /// A character device.
-    Char,
-    /// A directory.
-    Dir,
-    /// A FIFO.
-    Fifo,
-    /// A symlink.
-    Link,
-    /// A regular file.
-    Regular,
-    /// A unix socket.
-    Sock,
-}
-
-impl EntryType {
-    fn from_raw(d_type: c_uchar) -> Option<Self> {
-        Some(match d_type {
-            libc::DT_UNKNOWN => return None,
-            libc::DT_BLK => Self::Block,
-            libc::DT_CHR => Self::Char,
-            libc::DT_DIR => Self::Dir,
-            libc::DT_FIFO => Self::Fifo,
-            libc::DT_LNK => Self::Link,
-            libc::DT_REG => Self::Regular,
-            libc::DT_SOCK => Self::Sock,
-            // Should this error?
-            _ => return None,
-        })
-    }
-
-    /// Convenience method to check for `EntryType::Block`.
-    pub const fn is_block(self) -> bool {
-        matches!(self, Self::Block)
-    }
-
-    /// Convenience method to check for `EntryType::Char`.
-    pub const fn is_char(self) -> bool {
-        matches!(self, Self::Char)
-    }
-
-    /// Convenience method to check for `EntryType::Dir`.
-    pub const fn is_dir(self) -> bool {
-        matches!(self, Self::Dir)
-    }
-
-    /// Convenience method to check for `EntryType::Fifo`.
-    pub const fn is_fifo(self) -> bool {
-        matches!(self, Self::Fifo)
-    }
-
-    /// Convenience method to check for `EntryType::Link`.
-    pub const fn is_link(self) -> bool {
-        matches!(self, Self::Link)
-    }
-
-    /// Convenience method to check for `EntryType::Regular`.
-    pub const fn is_regular(self) -> bool {
-        matches!(self, Self::Regular)
-    }
-
-    /// Convenience method to check for `EntryType::Sock`.
-    pub const fn is_sock(self) -> bool {
-        matches!(self, Self::Sock)
-    }
-}
-
 struct GetDEnts {
     fd: OwnedFd,
     buf: Box<[u8]>,
@@ -223,7 +220,7 @@ impl GetDEnts {
             )
         };
         if rc < 0 {
-            io_bail_last!();
+            io_bail_last!("getdents64");
         }
         self.have = rc as usize;
         if self.have == 0 {
@@ -307,7 +304,55 @@ impl AsRawFd for GetDEnts {
 }
 
 impl AsFd for GetDEnts {
-    fn as_fd(&self) -> BorrowedFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
         self.fd.as_fd()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn read_dir_full_mixed() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path();
+        std::fs::write(dir.join("file"), b"data").unwrap();
+        std::fs::create_dir(dir.join("dir")).unwrap();
+        std::os::unix::fs::symlink("missing-target", dir.join("link")).unwrap();
+        let fifo =
+            std::ffi::CString::new(dir.join("fifo").into_os_string().into_encoded_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o600) }, 0);
+
+        let entries: BTreeMap<OsString, (Option<EntryType>, Metadata)> = read_dir_full(dir)
+            .unwrap()
+            .map(|entry| {
+                let (entry, meta) = entry.unwrap();
+                (entry.name().to_owned(), (entry.entry_type(), meta))
+            })
+            .collect();
+        assert_eq!(entries.len(), 4);
+
+        for (name, ty) in [
+            ("file", EntryType::Regular),
+            ("dir", EntryType::Dir),
+            ("link", EntryType::Link),
+            ("fifo", EntryType::Fifo),
+        ] {
+            let (entry_type, meta) = &entries[OsStr::new(name)];
+            // file systems may not report the type in the directory entry
+            assert!(
+                entry_type.is_none_or(|entry_type| entry_type == ty),
+                "{name}"
+            );
+            assert_eq!(meta.entry_type(), Some(ty), "{name}");
+        }
+        assert_eq!(entries[OsStr::new("file")].1.size(), Some(4));
+        // the symlink itself is stat'ed, not its (missing) target
+        let target_len = "missing-target".len() as u64;
+        assert_eq!(entries[OsStr::new("link")].1.size(), Some(target_len));
+    }
+}
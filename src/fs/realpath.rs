@@ -0,0 +1,92 @@
+//! Canonicalizing paths while refusing symlinks.
+
+use std::ffi::{CStr, OsStr};
+use std::io;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::CPath;
+use crate::error::io_bail;
+use crate::open::OpenHow;
+
+/// Get the canonical, absolute form of `path`, failing if any of its components is a symlink.
+///
+/// Contrary to [`std::fs::canonicalize`], which follows symlinks and returns the path of their
+/// target, this is meant to *detect* them: a path which only resolves to its location through a
+/// symlink may have been tampered with. Each component is opened with `openat2(2)` and
+/// `RESOLVE_NO_SYMLINKS` relative to the previous one, so the check is race free with respect to
+/// the walk itself, and a symlink anywhere in `path`, including the final component, results in
+/// an [`InvalidInput`](io::ErrorKind::InvalidInput) error naming the offending prefix.
+///
+/// `.` components are dropped and `..` moves up to the parent directory. Since no symlinks are
+/// involved, this matches what the kernel resolves. Relative paths are resolved against the
+/// current working directory, whose (already canonical) path is used as the starting point.
+pub fn realpath_no_symlinks<P>(path: &P) -> io::Result<PathBuf>
+where
+    P: ?Sized + CPath,
+{
+    path.c_path(realpath_no_symlinks_do)?
+}
+
+fn realpath_no_symlinks_do(path: &CStr) -> io::Result<PathBuf> {
+    let path = Path::new(OsStr::from_bytes(path.to_bytes()));
+    let how = OpenHow::new()
+        .flags(libc::O_PATH as u64)
+        .resolve_no_symlinks(true);
+
+    let (mut result, mut current): (PathBuf, OwnedFd) = if path.has_root() {
+        (PathBuf::from("/"), how.open_raw(c"/")?)
+    } else {
+        (std::env::current_dir()?, how.open_raw(c".")?)
+    };
+
+    for component in path.components() {
+        let name = match component {
+            Component::RootDir | Component::CurDir => continue,
+            Component::ParentDir => c"..".into(),
+            Component::Normal(name) => crate::c_path::io_c_os_str(name)?,
+            Component::Prefix(_) => io_bail!("invalid path component ({component:?})"),
+        };
+        current = match how.open_at_raw(current.as_raw_fd(), &name) {
+            Ok(fd) => fd,
+            Err(err) if crate::raw_os_error(&err) == Some(libc::ELOOP) => {
+                result.push(component);
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("path contains a symlink at {result:?}"),
+                ));
+            }
+            Err(err) => return Err(err),
+        };
+
+        if component == Component::ParentDir {
+            result.pop();
+        } else {
+            result.push(component);
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn symlinks_rejected() {
+        let tmp = TempDir::new().unwrap();
+        let root = realpath_no_symlinks(tmp.path()).unwrap();
+        std::fs::create_dir(root.join("dir")).unwrap();
+        std::os::unix::fs::symlink("dir", root.join("link")).unwrap();
+
+        assert_eq!(
+            realpath_no_symlinks(&root.join("dir/./../dir")).unwrap(),
+            root.join("dir")
+        );
+        let err = realpath_no_symlinks(&root.join("link/..")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{err}");
+    }
+}
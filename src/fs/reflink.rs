@@ -0,0 +1,103 @@
+//! Sharing data between files via the `FICLONE` and `FICLONERANGE` ioctls.
+//!
+//! These are supported by copy-on-write capable file systems such as btrfs, xfs and bcachefs. On
+//! other file systems they fail with `EOPNOTSUPP` (or `EXDEV` when the files are on different
+//! file systems), in which case callers usually fall back to copying the data.
+
+use std::ffi::c_int;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd};
+
+use crate::error::io_assert;
+use crate::ioctl::iow;
+
+#[repr(C)]
+struct FileCloneRange {
+    src_fd: i64,
+    src_offset: u64,
+    src_length: u64,
+    dest_offset: u64,
+}
+
+const FICLONE: c_int = iow::<c_int>(0x94, 9);
+const FICLONERANGE: c_int = iow::<FileCloneRange>(0x94, 13);
+
+/// Make `dst` share all of `src`'s data, like `cp --reflink=always`.
+///
+/// `dst` must be opened for writing and its previous contents are replaced.
+pub fn reflink<S, D>(src: &S, dst: &D) -> io::Result<()>
+where
+    S: ?Sized + AsFd,
+    D: ?Sized + AsFd,
+{
+    let rc = unsafe {
+        libc::ioctl(
+            dst.as_fd().as_raw_fd(),
+            FICLONE as _,
+            src.as_fd().as_raw_fd(),
+        )
+    };
+    io_assert!(rc == 0, "ioctl");
+    Ok(())
+}
+
+/// Make the range at `dst_offset` in `dst` share the `len` bytes at `src_offset` in `src`.
+///
+/// A `len` of zero means "up to the end of `src`". The offsets and length usually need to be
+/// aligned to the file system's block size, except for a range ending at the end of `src`.
+pub fn reflink_range<S, D>(
+    src: &S,
+    src_offset: u64,
+    dst: &D,
+    dst_offset: u64,
+    len: u64,
+) -> io::Result<()>
+where
+    S: ?Sized + AsFd,
+    D: ?Sized + AsFd,
+{
+    let range = FileCloneRange {
+        src_fd: i64::from(src.as_fd().as_raw_fd()),
+        src_offset,
+        src_length: len,
+        dest_offset: dst_offset,
+    };
+    let rc = unsafe { libc::ioctl(dst.as_fd().as_raw_fd(), FICLONERANGE as _, &raw const range) };
+    io_assert!(rc == 0, "ioctl");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn reflink_files() {
+        let tmp = TempDir::new().unwrap();
+        let data: Vec<u8> = (0..2 * 4096).map(|i| (i % 251) as u8).collect();
+        std::fs::write(tmp.path().join("src"), &data).unwrap();
+        let src = std::fs::File::open(tmp.path().join("src")).unwrap();
+        let dst = std::fs::File::create(tmp.path().join("dst")).unwrap();
+
+        match reflink(&src, &dst) {
+            Ok(()) => (),
+            Err(err)
+                if matches!(
+                    crate::raw_os_error(&err),
+                    Some(libc::EOPNOTSUPP | libc::EXDEV)
+                ) =>
+            {
+                eprintln!("skipping test, file system does not support reflinks: {err}");
+                return;
+            }
+            Err(err) => panic!("failed to reflink: {err}"),
+        }
+        assert_eq!(std::fs::read(tmp.path().join("dst")).unwrap(), data);
+
+        // share the second block at the start, the rest stays as is
+        reflink_range(&src, 4096, &dst, 0, 4096).unwrap();
+        let expected = [&data[4096..], &data[4096..]].concat();
+        assert_eq!(std::fs::read(tmp.path().join("dst")).unwrap(), expected);
+    }
+}
@@ -67,7 +67,7 @@ macro_rules! impl_mask {
 
 impl Stat<'_> {
     /// Set the root/beneath file descriptor the stat call should be relative to.
-    pub fn at_fd<F>(self, fd: &F) -> Stat
+    pub fn at_fd<F>(self, fd: &F) -> Stat<'_>
     where
         F: ?Sized + AsFd,
     {
@@ -78,6 +78,30 @@ impl Stat<'_> {
         }
     }
 
+    /// Create a `Stat` request for an open [`File`](std::fs::File) with the "basic" stats set.
+    ///
+    /// This sets the file as the file descriptor and the `AT_EMPTY_PATH` flag, so that an empty
+    /// path refers to the file itself:
+    ///
+    /// ``` rust, no_run
+    /// # fn code() -> std::io::Result<()> {
+    /// use lisy::fs::Stat;
+    ///
+    /// let file = std::fs::File::open("/some/file")?;
+    /// let meta = Stat::of_file(&file).btime(true).stat("")?;
+    /// println!("created at {:?}", meta.btime());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Since all the handles in this crate implement [`AsFd`], [`at_fd`](Stat::at_fd()) combined
+    /// with [`stat_fd`](Stat::stat_fd()) can be used for anything else.
+    pub fn of_file(file: &std::fs::File) -> Stat<'_> {
+        Stat::new()
+            .at_fd(file)
+            .set_at_flags(true, libc::AT_EMPTY_PATH)
+    }
+
     /// Update the request mask.
     fn set_mask(mut self, on: bool, value: c_uint) -> Self {
         if on {
@@ -184,6 +208,25 @@ impl Stat<'_> {
         self.set_at_flags(on, libc::AT_STATX_DONT_SYNC)
     }
 
+    /// Get the metadata as cheaply as possible, from whatever the kernel has cached.
+    ///
+    /// This selects `AT_STATX_DONT_SYNC`, replacing a previously chosen [`force_sync`] or
+    /// [`sync_as_stat`], since the kernel accepts only one synchronization mode. Network and
+    /// FUSE file systems then skip the round-trip to the server, which makes this the metadata
+    /// counterpart of opening with [`resolve_cached_only`].
+    ///
+    /// Contrary to that, the call does not fail if the data is not cached: the returned metadata
+    /// may simply be stale, for instance missing a change made by another NFS client. Local file
+    /// systems are always up to date and ignore the flag.
+    ///
+    /// [`force_sync`]: Self::force_sync()
+    /// [`sync_as_stat`]: Self::sync_as_stat()
+    /// [`resolve_cached_only`]: crate::open::OpenHow::resolve_cached_only()
+    pub fn cached_only(self) -> Self {
+        self.set_at_flags(false, libc::AT_STATX_SYNC_TYPE)
+            .set_at_flags(true, libc::AT_STATX_DONT_SYNC)
+    }
+
     /// Perform a stat on the currently selected file descriptor *itself*.
     pub fn stat_fd(self) -> io::Result<Metadata> {
         self.set_at_flags(true, libc::AT_EMPTY_PATH).stat("")
@@ -209,12 +252,103 @@ impl Stat<'_> {
             )
         };
         if rc != 0 {
-            io_bail_last!();
+            io_bail_last!("statx");
         }
         Ok(Metadata::from(data))
     }
 }
 
+/// Check whether `path` is the root of a mount, like `mountpoint(1)`.
+///
+/// Symlinks are followed, but automounts are not triggered. This requires kernel 5.8 for
+/// `STATX_ATTR_MOUNT_ROOT`, older kernels produce an [`Unsupported`](io::ErrorKind::Unsupported)
+/// error.
+pub fn is_mount_point<P>(path: &P) -> io::Result<bool>
+where
+    P: ?Sized + CPath,
+{
+    Stat::new_empty()
+        .no_auto_mount(true)
+        .stat(path)?
+        .is_mount_root()
+        .ok_or_else(no_mount_root_attr)
+}
+
+/// Check whether the file descriptor `fd` refers to the root of a mount.
+///
+/// This is the fd based variant of [`is_mount_point`], useful to check whether an already opened
+/// directory is a submount before descending into it.
+pub fn fd_is_mount_point<F>(fd: &F) -> io::Result<bool>
+where
+    F: ?Sized + AsFd,
+{
+    Stat::new_empty()
+        .at_fd(fd)
+        .stat_fd()?
+        .is_mount_root()
+        .ok_or_else(no_mount_root_attr)
+}
+
+fn no_mount_root_attr() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "kernel does not report STATX_ATTR_MOUNT_ROOT",
+    )
+}
+
+/// Get the unique [`MountId`] of the mount an open file descriptor lives on.
+///
+/// The id can be used with `statmount(2)` and `listmount(2)` to find out more about the mount.
+/// Requires kernel 6.9, older kernels produce an [`Unsupported`](io::ErrorKind::Unsupported)
+/// error.
+pub fn fd_mount_id<F>(fd: &F) -> io::Result<MountId>
+where
+    F: ?Sized + AsFd,
+{
+    Stat::new_empty()
+        .unique_mount_id(true)
+        .at_fd(fd)
+        .stat_fd()?
+        .unique_mount_id()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "kernel does not support unique mount ids",
+            )
+        })
+}
+
+/// Check whether two paths are in the same subvolume, such as a `btrfs` subvolume or snapshot.
+///
+/// Returns `None` if the kernel (before 6.11) or the file system does not report subvolume ids.
+/// Only the ids are compared, which are not unique across different file systems, so this is
+/// meant for paths already known to be on the same file system, such as when recursing through
+/// a directory tree.
+pub fn same_subvolume<P, Q>(a: &P, b: &Q) -> io::Result<Option<bool>>
+where
+    P: ?Sized + CPath,
+    Q: ?Sized + CPath,
+{
+    let stat = Stat::new_empty().subvol(true);
+    let a = stat.stat(a)?.subvolume_id();
+    let b = stat.stat(b)?.subvolume_id();
+    Ok(a.zip(b).map(|(a, b)| a == b))
+}
+
+/// Check whether two paths refer to the same file, for instance because they are hard links to it
+/// or one of them is a symlink to the other.
+///
+/// Symlinks are followed, and only the inode number is requested, see
+/// [`Metadata::is_same_file`].
+pub fn same_file<P, Q>(a: &P, b: &Q) -> io::Result<bool>
+where
+    P: ?Sized + CPath,
+    Q: ?Sized + CPath,
+{
+    let stat = Stat::new_empty().inode(true);
+    Ok(stat.stat(a)?.is_same_file(&stat.stat(b)?))
+}
+
 /// The result of a `statx(2)` operation via [`Stat`].
 #[derive(Clone)]
 pub struct Metadata {
@@ -369,6 +503,12 @@ impl Metadata {
         self.maybe(libc::STATX_MODE, self.data.stx_mode & (libc::S_IFMT as u16))
     }
 
+    /// The file type as an [`EntryType`](crate::fs::EntryType).
+    pub fn entry_type(&self) -> Option<crate::fs::EntryType> {
+        self.maybe(libc::STATX_TYPE, self.data.stx_mode)
+            .and_then(crate::fs::EntryType::from_mode)
+    }
+
     /// The mode bits *without* the file type.
     pub fn file_mode(&self) -> Option<u16> {
         self.maybe(
@@ -426,6 +566,9 @@ impl Metadata {
     }
 
     /// This is the device this file resides on.
+    ///
+    /// This can be compared against a mount's
+    /// [`StatMount::device`](crate::mount::StatMount::device()), see its documentation for caveats.
     pub fn fs_device(&self) -> Device {
         Device {
             major: self.data.stx_dev_major,
@@ -433,6 +576,19 @@ impl Metadata {
         }
     }
 
+    /// Check whether both results describe the same file, by comparing the device they reside on
+    /// and their inode numbers, like `dev()` and `ino()` of [`std::os::unix::fs::MetadataExt`].
+    ///
+    /// If either result lacks the inode number, `false` is returned, so make sure to request it
+    /// via [`Stat::inode`]. (The kernel often reports it anyway, but is not required to.) Hard
+    /// links to the same file compare equal.
+    pub fn is_same_file(&self, other: &Metadata) -> bool {
+        match (self.inode(), other.inode()) {
+            (Some(a), Some(b)) => a == b && self.fs_device() == other.fs_device(),
+            _ => false,
+        }
+    }
+
     /// Get the *reused* mount id this file resides on, this *fails* if the *unique* mount ID was
     /// also requested, or the kernel was too old.
     ///
@@ -559,3 +715,79 @@ struct CStatx {
     stx_dio_read_offset_align: u32,
     __spare3: [u64; 9],
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn same_subvolume_without_subvolumes() {
+        const BTRFS_SUPER_MAGIC: u64 = 0x9123_683e;
+        const BCACHEFS_SUPER_MAGIC: u64 = 0xca45_1a4e;
+
+        let tmp = TempDir::new().unwrap();
+        let a = tmp.path().join("a");
+        let b = tmp.path().join("b");
+        std::fs::write(&a, b"a").unwrap();
+        std::fs::write(&b, b"b").unwrap();
+
+        let result = same_subvolume(&a, &b).unwrap();
+        match crate::fs::statfs_path(tmp.path()).unwrap().fs_type() {
+            // Subvolume ids are only reported by kernel 6.11 and later.
+            BTRFS_SUPER_MAGIC | BCACHEFS_SUPER_MAGIC => assert_ne!(result, Some(false)),
+            _ => assert_eq!(result, None),
+        }
+    }
+
+    #[test]
+    fn same_file_hardlinks() {
+        let tmp = TempDir::new().unwrap();
+        let file = tmp.path().join("file");
+        let link = tmp.path().join("link");
+        let other = tmp.path().join("other");
+        let symlink = tmp.path().join("symlink");
+        std::fs::write(&file, b"data").unwrap();
+        std::fs::write(&other, b"data").unwrap();
+        std::fs::hard_link(&file, &link).unwrap();
+        std::os::unix::fs::symlink(&file, &symlink).unwrap();
+
+        assert!(same_file(&file, &file).unwrap());
+        assert!(same_file(&file, &link).unwrap());
+        assert!(same_file(&link, &symlink).unwrap());
+        assert!(!same_file(&file, &other).unwrap());
+        assert!(!same_file(&file, tmp.path()).unwrap());
+
+        let stat = Stat::new_empty().inode(true);
+        let file = stat.stat(&file).unwrap();
+        assert!(file.is_same_file(&stat.stat(&link).unwrap()));
+        assert!(!file.is_same_file(&stat.stat(&other).unwrap()));
+    }
+
+    #[test]
+    fn fd_mount_id_matches_statx() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("file");
+        std::fs::write(&path, b"data").unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+
+        let id = match fd_mount_id(&file) {
+            Ok(id) => id,
+            Err(err) if err.kind() == io::ErrorKind::Unsupported => {
+                eprintln!("skipping test: {err}");
+                return;
+            }
+            Err(err) => panic!("failed to get the mount id: {err}"),
+        };
+        let stat = Stat::new_empty().unique_mount_id(true);
+        assert_eq!(stat.stat(tmp.path()).unwrap().unique_mount_id(), Some(id));
+
+        #[cfg(feature = "mount")]
+        assert!(
+            crate::mount::list()
+                .collect::<io::Result<Vec<_>>>()
+                .unwrap()
+                .contains(&id)
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! File system statistics via `statfs(2)`, such as the amount of free space.
+
+use std::ffi::{OsString, c_int};
+use std::fmt;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::fd::{AsFd, AsRawFd};
+use std::os::unix::ffi::OsStringExt;
+
+use crate::CPath;
+use crate::error::io_assert;
+use crate::ioctl::ior;
+
+const FSLABEL_MAX: usize = 256;
+const FS_IOC_GETFSLABEL: c_int = ior::<[u8; FSLABEL_MAX]>(0x94, 49);
+const FS_IOC_GETFSUUID: c_int = ior::<FsUuid2>(0x15, 0);
+
+/// The kernel's `struct fsuuid2`.
+#[repr(C)]
+struct FsUuid2 {
+    len: u8,
+    uuid: [u8; 16],
+}
+
+/// Statistics about a mounted file system, see [`statfs`] and [`statfs_path`].
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct FsStats(libc::statfs);
+
+impl fmt::Debug for FsStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FsStats")
+            .field("fs_type", &format_args!("{:#x}", self.fs_type()))
+            .field("block_size", &self.block_size())
+            .field("fragment_size", &self.fragment_size())
+            .field("blocks", &self.blocks())
+            .field("blocks_free", &self.blocks_free())
+            .field("blocks_available", &self.blocks_available())
+            .field("files", &self.files())
+            .field("files_free", &self.files_free())
+            .field("fsid", &self.fsid())
+            .field("name_max", &self.name_max())
+            .finish()
+    }
+}
+
+// The counters are only 32 bit wide on some architectures.
+#[allow(clippy::useless_conversion)]
+impl FsStats {
+    /// The file system's magic number, such as `0x01021994` for `tmpfs`.
+    pub fn fs_type(&self) -> u64 {
+        self.0.f_type as u64
+    }
+
+    /// The optimal transfer block size.
+    pub fn block_size(&self) -> u64 {
+        self.0.f_bsize as u64
+    }
+
+    /// The fragment size, which is the unit of the block counts.
+    pub fn fragment_size(&self) -> u64 {
+        self.0.f_frsize as u64
+    }
+
+    /// The total number of blocks, in units of [`fragment_size`](Self::fragment_size()).
+    pub fn blocks(&self) -> u64 {
+        u64::from(self.0.f_blocks)
+    }
+
+    /// The number of free blocks.
+    pub fn blocks_free(&self) -> u64 {
+        u64::from(self.0.f_bfree)
+    }
+
+    /// The number of blocks available to unprivileged users.
+    ///
+    /// This is less than [`blocks_free`](Self::blocks_free()) on file systems reserving space
+    /// for the super user, such as ext4.
+    pub fn blocks_available(&self) -> u64 {
+        u64::from(self.0.f_bavail)
+    }
+
+    /// The total number of inodes. File systems without an inode limit may report 0.
+    pub fn files(&self) -> u64 {
+        u64::from(self.0.f_files)
+    }
+
+    /// The number of free inodes.
+    pub fn files_free(&self) -> u64 {
+        u64::from(self.0.f_ffree)
+    }
+
+    /// The raw file system id.
+    ///
+    /// What this contains depends on the file system, and is often just derived from the device
+    /// number.
+    pub fn fsid(&self) -> [c_int; 2] {
+        // `fsid_t`'s members are not public in `libc`
+        unsafe { std::mem::transmute::<libc::fsid_t, [c_int; 2]>(self.0.f_fsid) }
+    }
+
+    /// The maximum length of file names.
+    pub fn name_max(&self) -> u64 {
+        self.0.f_namelen as u64
+    }
+
+    /// Access the raw `statfs` struct.
+    pub fn as_raw(&self) -> &libc::statfs {
+        &self.0
+    }
+}
+
+impl From<libc::statfs> for FsStats {
+    fn from(raw: libc::statfs) -> Self {
+        Self(raw)
+    }
+}
+
+/// Get the statistics of the file system an open file descriptor is on via `fstatfs(2)`.
+pub fn statfs<F>(fd: &F) -> io::Result<FsStats>
+where
+    F: ?Sized + AsFd,
+{
+    let mut buf = MaybeUninit::<libc::statfs>::uninit();
+    let rc = unsafe { libc::fstatfs(fd.as_fd().as_raw_fd(), buf.as_mut_ptr()) };
+    io_assert!(rc == 0, "fstatfs");
+    Ok(FsStats(unsafe { buf.assume_init() }))
+}
+
+/// Get the statistics of the file system a path is on via `statfs(2)`.
+pub fn statfs_path<P>(path: &P) -> io::Result<FsStats>
+where
+    P: ?Sized + CPath,
+{
+    path.c_path(|path| {
+        let mut buf = MaybeUninit::<libc::statfs>::uninit();
+        let rc = unsafe { libc::statfs(path.as_ptr(), buf.as_mut_ptr()) };
+        io_assert!(rc == 0, "statfs");
+        Ok(FsStats(unsafe { buf.assume_init() }))
+    })?
+}
+
+/// Get the UUID of the file system an open file descriptor is on, via the `FS_IOC_GETFSUUID`
+/// ioctl.
+///
+/// This is the identifier `blkid(8)` reports for the block device, and is meant for correlating
+/// mounts with their devices. The ioctl was added in kernel 6.5 and is answered from the
+/// superblock for file systems which record a UUID there, such as ext4, xfs and btrfs, while
+/// tmpfs makes up a random one for each instance. Other file systems and older kernels produce
+/// `None`. The UUID is usually 16 bytes long, but the kernel allows shorter ones.
+///
+/// Note that `statmount(2)` does not report the UUID, so a file on the mount has to be opened
+/// first, for instance via
+/// [`StatMount::open_mount_point`](crate::mount::StatMount::open_mount_point()). Since this is an
+/// ioctl, an `O_PATH` file descriptor fails with `EBADF`.
+pub fn fs_uuid<F>(fd: &F) -> io::Result<Option<Vec<u8>>>
+where
+    F: ?Sized + AsFd,
+{
+    let mut raw = FsUuid2 {
+        len: 0,
+        uuid: [0; 16],
+    };
+    let rc = unsafe { libc::ioctl(fd.as_fd().as_raw_fd(), FS_IOC_GETFSUUID as _, &raw mut raw) };
+    if rc != 0 {
+        return unsupported_to_none(io::Error::last_os_error());
+    }
+    let len = usize::from(raw.len).min(raw.uuid.len());
+    Ok(Some(raw.uuid[..len].to_vec()))
+}
+
+/// Get the label of the file system an open file descriptor is on, via the `FS_IOC_GETFSLABEL`
+/// ioctl (kernel 4.18).
+///
+/// Only some file systems support this, including btrfs, ext4, xfs and f2fs. `None` is returned
+/// for the others, as well as when no label is set.
+pub fn fs_label<F>(fd: &F) -> io::Result<Option<OsString>>
+where
+    F: ?Sized + AsFd,
+{
+    let mut buf = [0u8; FSLABEL_MAX];
+    let rc = unsafe {
+        libc::ioctl(
+            fd.as_fd().as_raw_fd(),
+            FS_IOC_GETFSLABEL as _,
+            buf.as_mut_ptr(),
+        )
+    };
+    if rc != 0 {
+        return unsupported_to_none(io::Error::last_os_error());
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Ok((len != 0).then(|| OsString::from_vec(buf[..len].to_vec())))
+}
+
+/// Map the errors of ioctls the file system does not implement to `None`.
+fn unsupported_to_none<T>(err: io::Error) -> io::Result<Option<T>> {
+    match err.raw_os_error() {
+        Some(libc::ENOTTY | libc::EOPNOTSUPP) => Ok(None),
+        _ => Err(crate::error::syscall_error("ioctl", err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "mount")]
+    #[test]
+    fn tmpfs_uuid_and_label() {
+        use std::os::fd::AsRawFd;
+
+        crate::test_util::in_mount_namespace(|| {
+            let mounts = [crate::test_util::tmpfs(), crate::test_util::tmpfs()];
+            let [a, b] = mounts.map(|mount| {
+                let root = std::fs::File::open(format!("/proc/self/fd/{}", mount.as_raw_fd()));
+                root.unwrap()
+            });
+
+            assert_eq!(fs_label(&a).unwrap(), None);
+
+            let Some(uuid) = fs_uuid(&a).unwrap() else {
+                // `FS_IOC_GETFSUUID` was added in 6.5
+                assert!(crate::test_util::kernel_version() < (6, 5));
+                return;
+            };
+            assert_eq!(uuid.len(), 16);
+            // every tmpfs instance gets a random one
+            assert_ne!(fs_uuid(&b).unwrap(), Some(uuid));
+        });
+    }
+}
@@ -0,0 +1,91 @@
+//! File type representation shared by directory listings and `statx(2)`.
+
+use std::ffi::c_uchar;
+
+/// The type of a file, as found in a directory listing or the mode of a `stat(2)` call.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum EntryType {
+    /// A block device.
+    Block,
+    /// A character device.
+    Char,
+    /// A directory.
+    Dir,
+    /// A FIFO.
+    Fifo,
+    /// A symlink.
+    Link,
+    /// A regular file.
+    Regular,
+    /// A unix socket.
+    Sock,
+}
+
+impl EntryType {
+    /// Convert a `d_type` value from a directory entry. Returns `None` for `DT_UNKNOWN`.
+    pub(crate) fn from_raw(d_type: c_uchar) -> Option<Self> {
+        Some(match d_type {
+            libc::DT_UNKNOWN => return None,
+            libc::DT_BLK => Self::Block,
+            libc::DT_CHR => Self::Char,
+            libc::DT_DIR => Self::Dir,
+            libc::DT_FIFO => Self::Fifo,
+            libc::DT_LNK => Self::Link,
+            libc::DT_REG => Self::Regular,
+            libc::DT_SOCK => Self::Sock,
+            // Should this error?
+            _ => return None,
+        })
+    }
+
+    /// Get the type from the `S_IFMT` bits of a file mode, such as
+    /// [`Metadata::file_type`](crate::fs::stat::Metadata::file_type()). Any other bits are
+    /// ignored.
+    pub const fn from_mode(mode: u16) -> Option<Self> {
+        Some(match mode as libc::mode_t & libc::S_IFMT {
+            libc::S_IFBLK => Self::Block,
+            libc::S_IFCHR => Self::Char,
+            libc::S_IFDIR => Self::Dir,
+            libc::S_IFIFO => Self::Fifo,
+            libc::S_IFLNK => Self::Link,
+            libc::S_IFREG => Self::Regular,
+            libc::S_IFSOCK => Self::Sock,
+            _ => return None,
+        })
+    }
+
+    /// Convenience method to check for `EntryType::Block`.
+    pub const fn is_block(self) -> bool {
+        matches!(self, Self::Block)
+    }
+
+    /// Convenience method to check for `EntryType::Char`.
+    pub const fn is_char(self) -> bool {
+        matches!(self, Self::Char)
+    }
+
+    /// Convenience method to check for `EntryType::Dir`.
+    pub const fn is_dir(self) -> bool {
+        matches!(self, Self::Dir)
+    }
+
+    /// Convenience method to check for `EntryType::Fifo`.
+    pub const fn is_fifo(self) -> bool {
+        matches!(self, Self::Fifo)
+    }
+
+    /// Convenience method to check for `EntryType::Link`.
+    pub const fn is_link(self) -> bool {
+        matches!(self, Self::Link)
+    }
+
+    /// Convenience method to check for `EntryType::Regular`.
+    pub const fn is_regular(self) -> bool {
+        matches!(self, Self::Regular)
+    }
+
+    /// Convenience method to check for `EntryType::Sock`.
+    pub const fn is_sock(self) -> bool {
+        matches!(self, Self::Sock)
+    }
+}
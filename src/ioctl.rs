@@ -1,5 +1,8 @@
 //! ioctl helpers
 
+// Which of these are used depends on the enabled features.
+#![allow(dead_code)]
+
 use std::ffi::c_int;
 
 pub const IOC_NONE: c_int = 0;
@@ -24,6 +27,10 @@ pub const fn ior<T: Sized>(ty: c_int, nr: c_int) -> c_int {
     ioc(IOC_READ, ty, nr, std::mem::size_of::<T>() as c_int)
 }
 
+pub const fn iow<T: Sized>(ty: c_int, nr: c_int) -> c_int {
+    ioc(IOC_WRITE, ty, nr, std::mem::size_of::<T>() as c_int)
+}
+
 pub const fn iowr<T: Sized>(ty: c_int, nr: c_int) -> c_int {
     ioc(
         IOC_READ | IOC_WRITE,
@@ -100,6 +100,17 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! # Errors
+//!
+//! All functions return [`std::io::Error`]s, but some of them carry more information than just the
+//! `errno` value, like the name of the failed system call with the `syscall-errors` feature, or a
+//! message describing which step of a larger operation failed. The
+//! [`raw_os_error`](std::io::Error::raw_os_error()) method returns `None` for such errors.
+//!
+//! **Since features are unified across a dependency graph, any crate enabling `syscall-errors`
+//! changes this for all users of this crate.** To check for specific `errno` values, always use
+//! [`lisy::raw_os_error`](crate::raw_os_error()), which looks through the added information.
 
 #![deny(unsafe_op_in_unsafe_fn)]
 #![deny(missing_docs)]
@@ -115,11 +126,13 @@ use c_path::CPath;
 
 #[cfg(any(feature = "open", feature = "mount"))]
 pub(crate) mod error;
+#[cfg(any(feature = "open", feature = "mount"))]
+pub use error::{SyscallError, raw_os_error};
 
 #[cfg(feature = "fs")]
 pub(crate) mod bytes;
 
-#[cfg(any(feature = "ns", feature = "pidfd"))]
+#[cfg(any(feature = "ns", feature = "pidfd", feature = "fs"))]
 pub(crate) mod ioctl;
 
 #[cfg(any(feature = "mount", feature = "fs"))]
@@ -128,6 +141,9 @@ pub(crate) mod mount_types;
 #[cfg(any(feature = "mount", feature = "fs"))]
 pub(crate) mod types;
 
+#[cfg(test)]
+pub(crate) mod test_util;
+
 // END internal helpers
 
 #[cfg(feature = "fs")]
@@ -139,6 +155,9 @@ pub mod mount;
 #[cfg(feature = "open")]
 pub mod open;
 
+#[cfg(feature = "open")]
+pub mod proc;
+
 #[cfg(feature = "userns")]
 pub mod userns;
 
@@ -147,3 +166,8 @@ pub mod pidfd;
 
 #[cfg(feature = "ns")]
 pub mod ns;
+
+#[cfg(feature = "mount")]
+mod probe;
+#[cfg(feature = "mount")]
+pub use probe::{KernelSupport, probe};
@@ -0,0 +1,357 @@
+//! Assembling a tree out of several mounts, undoing them on failure.
+
+use std::fmt;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd};
+use std::path::{Path, PathBuf};
+
+use crate::error::context;
+use crate::fs::CreatePath;
+use crate::mount::{Fs, FsMount, FsOpen, Mount, MountAttr, MountSetAttr, MoveMount, OpenTree};
+use crate::open::OpenHow;
+
+/// Where the mount of a [`MountStep`] comes from.
+#[derive(Clone, Debug)]
+enum Source {
+    /// A new instance of a file system.
+    Fs { fs_type: String, options: String },
+
+    /// A bind mount of an existing path.
+    Bind { path: PathBuf, recursive: bool },
+}
+
+/// A single mount to be performed by a [`Builder`].
+///
+/// The mount is first created detached, then the attributes and the idmapping are applied, and
+/// finally it is moved onto its target. The target is resolved inside the [`Builder`]'s root
+/// directory, as if that was the root file system, so an absolute target such as `/usr` refers to
+/// `usr` in the root, and `/` to the root itself.
+#[derive(Clone, Debug)]
+pub struct MountStep<'a> {
+    source: Source,
+    target: PathBuf,
+    set: MountAttr,
+    clear: MountAttr,
+    idmap: Option<BorrowedFd<'a>>,
+    create_target: bool,
+}
+
+impl MountStep<'static> {
+    /// Mount a new instance of a file system, configured via a `mount -o` style `options` string
+    /// (see [`apply_mount_options`](crate::mount::superblock::SuperblockRef::apply_mount_options())).
+    pub fn fs<P>(fs_type: &str, options: &str, target: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self::new(
+            Source::Fs {
+                fs_type: fs_type.to_string(),
+                options: options.to_string(),
+            },
+            target.into(),
+        )
+    }
+
+    /// Bind mount `source` onto `target`. This is recursive by default, like `mount --rbind`.
+    pub fn bind<S, P>(source: S, target: P) -> Self
+    where
+        S: Into<PathBuf>,
+        P: Into<PathBuf>,
+    {
+        Self::new(
+            Source::Bind {
+                path: source.into(),
+                recursive: true,
+            },
+            target.into(),
+        )
+    }
+
+    fn new(source: Source, target: PathBuf) -> Self {
+        Self {
+            source,
+            target,
+            set: MountAttr::empty(),
+            clear: MountAttr::empty(),
+            idmap: None,
+            create_target: false,
+        }
+    }
+}
+
+impl<'a> MountStep<'a> {
+    /// For bind mounts, choose whether the mounts below `source` should be included, and the
+    /// attributes applied to all of them. Has no effect on new file systems.
+    pub fn recursive(mut self, on: bool) -> Self {
+        if let Source::Bind { recursive, .. } = &mut self.source {
+            *recursive = on;
+        }
+        self
+    }
+
+    /// Set mount attributes, such as [`MountAttr::RDONLY`].
+    pub fn set(mut self, attr: MountAttr) -> Self {
+        self.set |= attr;
+        self.clear &= !attr;
+        self
+    }
+
+    /// Clear mount attributes.
+    pub fn clear(mut self, attr: MountAttr) -> Self {
+        self.clear |= attr;
+        self.set &= !attr;
+        self
+    }
+
+    /// Idmap the mount with the user namespace `userns`.
+    pub fn idmap<'b, F>(self, userns: &'b F) -> MountStep<'b>
+    where
+        'a: 'b,
+        F: ?Sized + AsFd,
+    {
+        MountStep {
+            idmap: Some(userns.as_fd()),
+            ..self
+        }
+    }
+
+    /// Create the target directory including its parents if it does not exist.
+    ///
+    /// This is useful to create mount points inside a file system mounted by a previous step. The
+    /// directories are created via [`CreatePath`] with
+    /// [`resolve_in_root`](CreatePath::resolve_in_root()), so symlinks on the way cannot lead out
+    /// of the root.
+    pub fn create_target(mut self, on: bool) -> Self {
+        self.create_target = on;
+        self
+    }
+
+    /// Create the detached mount, apply its attributes and move it onto the target inside `root`.
+    fn execute(&self, root: &Path) -> io::Result<Mount> {
+        let mount = match &self.source {
+            Source::Fs { fs_type, options } => {
+                let fs = Fs::open(fs_type, FsOpen::CLOEXEC)
+                    .map_err(|err| context(err, "failed to open file system"))?;
+                fs.apply_mount_options(options)?;
+                fs.create()
+                    .map_err(|err| context(err, "failed to create superblock"))?
+                    .mount(FsMount::CLOEXEC, MountAttr::empty())
+                    .map_err(|err| context(err, "failed to create mount"))?
+            }
+            Source::Bind { path, recursive } => {
+                let mut flags = OpenTree::CLOEXEC | OpenTree::CLONE;
+                if *recursive {
+                    flags |= OpenTree::RECURSIVE;
+                }
+                Mount::open_tree(path, flags, 0)
+                    .map_err(|err| context(err, "failed to clone mount tree"))?
+            }
+        };
+
+        if !self.set.is_empty() || !self.clear.is_empty() || self.idmap.is_some() {
+            let attr = match &self.idmap {
+                Some(userns) => MountSetAttr::new().idmap(userns),
+                None => MountSetAttr::new(),
+            };
+            let at_flags = match self.source {
+                Source::Bind {
+                    recursive: true, ..
+                } => libc::AT_RECURSIVE,
+                _ => 0,
+            };
+            mount
+                .setattr(&attr.set(self.set).clear(self.clear), at_flags)
+                .map_err(|err| context(err, "failed to set mount attributes"))?;
+        }
+
+        let target = self
+            .open_target(root)
+            .map_err(|err| context(err, "failed to open target"))?;
+        mount
+            .move_mount_to_fd(&target, MoveMount::empty())
+            .map_err(|err| context(err, "failed to move mount into place"))?;
+        Ok(mount)
+    }
+
+    /// Open the target inside `root`, creating it if requested.
+    ///
+    /// The root is opened again for every step, so that it refers to a file system mounted onto
+    /// it by a previous step.
+    fn open_target(&self, root: &Path) -> io::Result<OwnedFd> {
+        let root = OpenHow::new_directory().open(root)?;
+        let mut target = self.target.strip_prefix("/").unwrap_or(&self.target);
+        if target.as_os_str().is_empty() {
+            target = Path::new(".");
+        } else if self.create_target {
+            return CreatePath::new()
+                .allow_symlinks(true)
+                .resolve_in_root(true)
+                .create_at(&root, target);
+        }
+        OpenHow::new()
+            .flags(libc::O_PATH as u64)
+            .resolve_in_root(true)
+            .at_fd(&root)
+            .open(target)
+    }
+}
+
+impl fmt::Display for MountStep<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.source {
+            Source::Fs { fs_type, .. } => write!(f, "{fs_type} mount")?,
+            Source::Bind { path, .. } => write!(f, "bind mount of {path:?}")?,
+        }
+        write!(f, " onto {:?}", self.target)
+    }
+}
+
+/// Perform a sequence of mounts inside a root directory, such as for assembling a container's
+/// root file system, undoing them if one fails.
+///
+/// ``` rust, no_run
+/// # fn code() -> std::io::Result<()> {
+/// use lisy::mount::{Builder, MountAttr, MountStep};
+///
+/// Builder::new("/run/ct/root")
+///     .step(MountStep::fs("tmpfs", "size=64M,mode=0755", "/"))
+///     .step(
+///         MountStep::bind("/usr", "/usr")
+///             .set(MountAttr::RDONLY | MountAttr::NODEV)
+///             .create_target(true),
+///     )
+///     .execute()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The targets of the steps are resolved inside the root directory as if it was the root file
+/// system (via `RESOLVE_IN_ROOT`), so symlinks in a file system mounted by an earlier step cannot
+/// redirect a later mount outside of it. The sources of bind mounts are regular paths.
+///
+/// Should a step fail, the mounts of the steps completed so far are unmounted in reverse order
+/// (lazily, via `MNT_DETACH`), and an error naming the failed step is returned, which keeps the
+/// [`kind`](io::Error::kind()) and `errno` value (see [`raw_os_error`](crate::raw_os_error())) of
+/// the original error. The unmounting happens via `/proc/thread-self/fd`, which requires procfs.
+/// Directories created via [`create_target`](MountStep::create_target()) are not removed.
+#[derive(Clone, Debug)]
+pub struct Builder<'a> {
+    root: PathBuf,
+    steps: Vec<MountStep<'a>>,
+}
+
+impl<'a> Builder<'a> {
+    /// Create an empty builder mounting inside `root`.
+    pub fn new<P>(root: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            root: root.into(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Add a mount to perform.
+    pub fn step(mut self, step: MountStep<'a>) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Perform all the mounts in order, rolling back on failure.
+    pub fn execute(&self) -> io::Result<()> {
+        let mut done = Vec::with_capacity(self.steps.len());
+        for (index, step) in self.steps.iter().enumerate() {
+            match step.execute(&self.root) {
+                Ok(mount) => done.push(mount),
+                Err(err) => {
+                    let mut msg = format!("step {} ({step}) failed", index + 1);
+                    if let Err(rollback) = rollback(&done) {
+                        msg = format!("{msg} (rollback failed: {rollback})");
+                    }
+                    return Err(context(err, msg));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lazily unmount the mounts in `done` in reverse order.
+fn rollback(done: &[Mount]) -> io::Result<()> {
+    let mut result = Ok(());
+    for mount in done.iter().rev() {
+        let path = format!("/proc/thread-self/fd/{}", mount.as_raw_fd());
+        if let Err(err) = crate::mount::umount(&path, libc::MNT_DETACH) {
+            result = Err(context(err, "failed to unmount"));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+    use crate::test_util::{TempDir, in_mount_namespace};
+
+    #[test]
+    fn targets_stay_inside_root() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("root");
+        let outside = tmp.path().join("outside");
+        let source = tmp.path().join("source");
+        for dir in [&root, &outside, &source] {
+            std::fs::create_dir(dir).unwrap();
+        }
+        std::fs::write(source.join("marker"), b"").unwrap();
+        symlink(&outside, root.join("link")).unwrap();
+
+        in_mount_namespace(|| {
+            for (target, create) in [("/link", false), ("/link/sub", true)] {
+                let err = Builder::new(&root)
+                    .step(MountStep::bind(&source, target).create_target(create))
+                    .execute()
+                    .unwrap_err();
+                assert_eq!(crate::raw_os_error(&err), Some(libc::ENOENT), "{err}");
+            }
+            assert_eq!(std::fs::read_dir(&outside).unwrap().count(), 0);
+        });
+    }
+
+    #[test]
+    fn mounts_and_rolls_back() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("root");
+        let source = tmp.path().join("source");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::create_dir(&source).unwrap();
+        std::fs::write(root.join("original"), b"").unwrap();
+        std::fs::write(source.join("marker"), b"").unwrap();
+
+        in_mount_namespace(|| {
+            Builder::new(&root)
+                .step(MountStep::fs("tmpfs", "mode=0755", "/"))
+                .step(MountStep::bind(&source, "/a/b").create_target(true))
+                .execute()
+                .unwrap();
+            assert!(root.join("a/b/marker").exists());
+            assert!(!root.join("original").exists());
+            crate::mount::umount(&root, libc::MNT_DETACH).unwrap();
+
+            let err = Builder::new(&root)
+                .step(MountStep::fs("tmpfs", "mode=0755", "/"))
+                .step(MountStep::bind(tmp.path().join("missing"), "/"))
+                .execute()
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+            assert_eq!(crate::raw_os_error(&err), Some(libc::ENOENT));
+            assert!(
+                err.to_string().starts_with("step 2 (bind mount of "),
+                "{err}"
+            );
+            assert!(root.join("original").exists());
+        });
+    }
+}
@@ -4,7 +4,7 @@
 //! system type. See the `Fs` documentation for details.
 
 use std::io;
-use std::os::fd::{AsFd, BorrowedFd};
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
 use std::os::raw::c_uint;
 use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
 use std::ptr;
@@ -18,12 +18,16 @@ use crate::mount::{Superblock, sys};
 
 bitflags! {
     /// Flags for `Fs::open`.
+    ///
+    /// Note that `FsOpen::default()` includes `CLOEXEC`, while `FsOpen::empty()` does not.
     pub struct FsOpen: c_uint {
         /// Set the close-on-exec flag on the file descriptor.
         const CLOEXEC = 0x0000_0001;
     }
 }
 
+crate::mount::cloexec_flags!(FsOpen);
+
 /// Represents a handle to a file system.
 ///
 /// This is the first step to mounting a new file system. First, a file system handle is acquired,
@@ -38,7 +42,7 @@ pub struct Fs {
 
 impl AsFd for Fs {
     fn as_fd(&self) -> BorrowedFd<'_> {
-        unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
+        self.sb_ref.as_fd()
     }
 }
 
@@ -64,6 +68,12 @@ impl FromRawFd for Fs {
     }
 }
 
+impl From<Fs> for OwnedFd {
+    fn from(this: Fs) -> OwnedFd {
+        this.sb_ref.into()
+    }
+}
+
 impl std::ops::Deref for Fs {
     type Target = SuperblockRef;
 
@@ -84,13 +94,19 @@ impl Fs {
         let fs_type = io_c_string(fs_type)?;
 
         let rc = unsafe { libc::syscall(sys::SYS_fsopen, fs_type.as_ptr(), flags.bits()) };
-        io_assert!(rc >= 0);
+        io_assert!(rc >= 0, "fsopen");
 
         Ok(Self {
             sb_ref: unsafe { SuperblockRef::from_raw_fd(rc as RawFd) },
         })
     }
 
+    /// Close the file system context without creating a superblock, reporting errors from
+    /// `close(2)`.
+    pub fn close(self) -> io::Result<()> {
+        crate::mount::close_fd(self.sb_ref.fd)
+    }
+
     /// Create a [`Superblock`] with the current configuration.
     pub fn create(self) -> io::Result<Superblock> {
         let rc = unsafe {
@@ -102,9 +118,22 @@ impl Fs {
                 0,
             )
         };
-        io_assert!(rc == 0);
+        io_assert!(rc == 0, "fsconfig");
         Ok(Superblock {
             sb_ref: self.sb_ref,
         })
     }
+
+    /// Duplicate the handle.
+    ///
+    /// The file system context itself is shared, so configuration done via either handle is
+    /// visible to both, and only one of them can be used to [`create`](Self::create()) the
+    /// superblock.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            sb_ref: SuperblockRef {
+                fd: self.sb_ref.fd.try_clone()?,
+            },
+        })
+    }
 }
@@ -0,0 +1,53 @@
+//! Unmounting a mount point when going out of scope.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A mount point which is unmounted when the guard is dropped, see
+/// [`Mount::move_mount_guarded`](crate::mount::Mount::move_mount_guarded()).
+///
+/// The unmount is lazy (`MNT_DETACH`), so it succeeds even while the mount is still busy, and any
+/// error is ignored when dropping. Use [`unmount`](MountGuard::unmount()) to handle errors, or
+/// [`leak`](MountGuard::leak()) to keep the mount.
+///
+/// Note that the guard refers to the mount point by path, so if something else gets mounted on top
+/// of it in the meantime, that is what will be unmounted.
+#[derive(Debug)]
+#[must_use = "the mount is detached again when the guard is dropped"]
+pub struct MountGuard {
+    path: Option<PathBuf>,
+}
+
+impl MountGuard {
+    /// Take responsibility for unmounting `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: Some(path.into()),
+        }
+    }
+
+    /// The path of the guarded mount point.
+    pub fn path(&self) -> &Path {
+        // unwrap: only taken out by methods consuming `self`
+        self.path.as_deref().unwrap()
+    }
+
+    /// Disarm the guard, keeping the mount in place, and return its path.
+    pub fn leak(mut self) -> PathBuf {
+        self.path.take().unwrap()
+    }
+
+    /// Lazily unmount now, returning any error.
+    pub fn unmount(mut self) -> io::Result<()> {
+        let path = self.path.take().unwrap();
+        crate::mount::umount(&path, libc::MNT_DETACH)
+    }
+}
+
+impl Drop for MountGuard {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            let _ = crate::mount::umount(&path, libc::MNT_DETACH);
+        }
+    }
+}
@@ -1,14 +1,19 @@
 //! `listmount(2)` and `statmount(2)` implementation.
 
-use std::ffi::CStr;
+use std::borrow::Cow;
+use std::ffi::{CStr, OsStr};
 use std::io;
+#[cfg(feature = "open")]
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 
-use crate::error::io_assert;
+use crate::error::{io_assert, io_bail, io_format_err};
 use crate::types::Device;
 
 use super::sys::{MountAttr, MountPropagation, StatMountFlags, SuperblockFlags};
 use super::sys::{SYS_listmount, SYS_statmount};
-use super::{MountId, MountNsId, ReusedMountId};
+use super::{AtimeMode, MountId, MountNsId, ReusedMountId};
 
 /// Structure for passing mount ID and miscellaneous parameters to `statmount(2)` and
 /// `listmount(2)`.
@@ -114,9 +119,13 @@ impl MountIdRequest {
     }
 }
 
+/// `listmount(2)` flag to list the mounts in reverse order, newest first (kernel 6.11).
+const LISTMOUNT_REVERSE: u64 = 1 << 0;
+
 /// An iterator over the mount IDs inside a mount namespace.
 pub struct ListMounts {
     request: MountIdRequest,
+    flags: u64,
     buf: Box<[MountId; 64]>,
     at: usize,
     capacity: usize,
@@ -129,6 +138,20 @@ pub fn list() -> ListMounts {
     ListMounts::here()
 }
 
+/// Get the mount point of `child` relative to the mount point of `parent`, as shown in a tree
+/// view like the one of `findmnt`.
+///
+/// Both need to have been stat'ed with [`StatMountFlags::MNT_POINT`]. The prefix is stripped by
+/// whole path components, and a mount stacked directly on top of its parent yields an empty
+/// path. Returns `None` if either mount point is missing or if `child` does not lie below
+/// `parent`, which can happen when the mounts were queried in different namespaces or with
+/// different roots.
+pub fn relative_mount_point(child: &StatMount, parent: &StatMount) -> Option<PathBuf> {
+    let child = Path::new(OsStr::from_bytes(child.mount_point()?.to_bytes()));
+    let parent = Path::new(OsStr::from_bytes(parent.mount_point()?.to_bytes()));
+    child.strip_prefix(parent).ok().map(Path::to_path_buf)
+}
+
 impl ListMounts {
     /// Create an iterator over mount points.
     ///
@@ -144,6 +167,7 @@ impl ListMounts {
         }
         Self {
             request,
+            flags: 0,
             buf: Box::new([MountId::from_raw(0); 64]),
             at: 0,
             capacity: 0,
@@ -157,18 +181,46 @@ impl ListMounts {
         Self::new(MountId::root(), None)
     }
 
-    /// If we're at the end of the current list but not yet done, query the next set of mounts...
-    fn list_more(&mut self) -> io::Result<()> {
-        if self.done || self.at < self.capacity {
-            return Ok(());
+    /// List the mounts in reverse order, that is, the most recently mounted ones first.
+    ///
+    /// This uses `LISTMOUNT_REVERSE`, which requires kernel 6.11. Older kernels reject the flag
+    /// with `EINVAL` once the first batch is queried. Changing this after iteration started
+    /// leads to skipped or repeated ids.
+    pub fn reverse(mut self, on: bool) -> Self {
+        if on {
+            self.flags |= LISTMOUNT_REVERSE;
+        } else {
+            self.flags &= !LISTMOUNT_REVERSE;
+        }
+        self
+    }
+
+    /// Query the next set of mounts if all buffered ids have been consumed.
+    ///
+    /// This is the only method issuing a `listmount(2)` call. It does nothing if there are still
+    /// buffered ids, or once the end of the list was reached. Returns `false` when there are no
+    /// more ids to be returned.
+    pub fn fill(&mut self) -> io::Result<bool> {
+        if self.done {
+            return Ok(false);
+        }
+        if self.at < self.capacity {
+            return Ok(true);
         }
 
         let req = self
             .request
             .finalize(self.mnt_id.map_or(0, MountId::as_raw_id));
-        let rc =
-            unsafe { libc::syscall(SYS_listmount, req, self.buf.as_mut_ptr(), self.buf.len(), 0) };
-        io_assert!(rc >= 0);
+        let rc = unsafe {
+            libc::syscall(
+                SYS_listmount,
+                req,
+                self.buf.as_mut_ptr(),
+                self.buf.len(),
+                self.flags,
+            )
+        };
+        io_assert!(rc >= 0, "listmount");
 
         self.capacity = rc as usize;
         self.at = 0;
@@ -177,7 +229,55 @@ impl ListMounts {
         } else {
             self.mnt_id = Some(self.buf[self.capacity - 1]);
         }
-        Ok(())
+        Ok(!self.done)
+    }
+
+    /// Take all currently buffered ids without issuing a syscall.
+    ///
+    /// An empty slice means that [`fill`](Self::fill()) needs to be called (or that the end was
+    /// reached, see [`is_done`](Self::is_done())).
+    ///
+    /// Together, the two methods separate the blocking part from the iteration, so the syscall can
+    /// be scheduled elsewhere, for example on the blocking thread pool of an async runtime: move
+    /// the `ListMounts` into the blocking task to call `fill()`, get it back when the task
+    /// completes, and drain it with `next_batch()` from the async context.
+    ///
+    /// ``` rust, no_run
+    /// # fn code() -> std::io::Result<()> {
+    /// use lisy::mount::ListMounts;
+    ///
+    /// let mut list = ListMounts::here();
+    /// loop {
+    ///     // Stand-in for something like `tokio::task::spawn_blocking`.
+    ///     let (returned, more) = std::thread::spawn(move || {
+    ///         let more = list.fill();
+    ///         (list, more)
+    ///     })
+    ///     .join()
+    ///     .unwrap();
+    ///     list = returned;
+    ///     if !more? {
+    ///         break;
+    ///     }
+    ///
+    ///     for id in list.next_batch() {
+    ///         println!("{id:?}");
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn next_batch(&mut self) -> &[MountId] {
+        let at = self.at;
+        self.at = self.capacity;
+        &self.buf[at..self.capacity]
+    }
+
+    /// Check whether the end of the list was reached.
+    ///
+    /// Buffered ids may still be available from [`next_batch`](Self::next_batch()).
+    pub fn is_done(&self) -> bool {
+        self.done
     }
 }
 
@@ -185,12 +285,10 @@ impl Iterator for ListMounts {
     type Item = io::Result<MountId>;
 
     fn next(&mut self) -> Option<io::Result<MountId>> {
-        if let Err(err) = self.list_more() {
-            return Some(Err(err));
-        }
-
-        if self.done {
-            return None;
+        match self.fill() {
+            Err(err) => return Some(Err(err)),
+            Ok(false) => return None,
+            Ok(true) => (),
         }
 
         let at = self.at;
@@ -213,6 +311,31 @@ impl MountId {
             .stat()
     }
 
+    /// Look up the mount and the id of the mount namespace it belongs to.
+    ///
+    /// This performs a `statmount(2)` call requesting only [`StatMountFlags::MNT_BASIC`] and
+    /// [`StatMountFlags::MNT_NS_ID`], which is all that is needed to correlate mounts across
+    /// namespaces. Kernels which do not report the namespace id (before 6.11) produce an
+    /// [`Unsupported`](io::ErrorKind::Unsupported) error.
+    pub fn locate(self) -> io::Result<(MountId, MountNsId)> {
+        let stat = StatMount::builder()
+            .basic_superblock_info(false)
+            .basic_mount_info(true)
+            .mount_ns_id(true)
+            .mount_id(self)
+            .stat()?;
+        let id = stat
+            .id()
+            .ok_or_else(|| io_format_err!("statmount did not return the mount id"))?;
+        let ns = stat.mount_namespace_id().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "kernel does not report the mount namespace id via statmount",
+            )
+        })?;
+        Ok((id, ns))
+    }
+
     /// Stat a mount id in a specific namespace.
     pub fn stat_ns(self, what: StatMountFlags, namespace: MountNsId) -> io::Result<Box<StatMount>> {
         StatMount::builder()
@@ -424,6 +547,21 @@ impl StatMount {
         Self::builder().all(true).mount_id(mount_id).stat()
     }
 
+    /// Probe which `statmount(2)` information the running kernel supports.
+    ///
+    /// This performs a `statmount(2)` call requesting [`StatMountFlags::all()`] on the first mount
+    /// of the current mount namespace and returns the [`mask`](StatMount::mask()) the kernel
+    /// filled in.
+    ///
+    /// Note that the kernel omits some string fields if they are empty for the probed mount (eg.
+    /// the file system subtype), so this should be treated as a lower bound.
+    pub fn supported_mask() -> io::Result<StatMountFlags> {
+        let mount_id = list()
+            .next()
+            .ok_or_else(|| io_format_err!("no mounts found to probe statmount with"))??;
+        Ok(Self::stat(mount_id)?.mask())
+    }
+
     /// Allocate a buffer for a `statmount(2)` call.
     fn with_capacity(size: usize) -> Box<Self> {
         let str_capacity = size - std::mem::size_of::<StatMountBase>();
@@ -476,7 +614,7 @@ impl StatMount {
 
             let err = io::Error::last_os_error();
             if err.raw_os_error() != Some(libc::EOVERFLOW) || capacity >= 0x1000_0000 {
-                return Err(err);
+                return Err(crate::error::syscall_error("statmount", err));
             }
 
             capacity <<= 1;
@@ -495,6 +633,14 @@ impl StatMount {
         (self.base.mask & flag.bits() == flag.bits()).then_some(value)
     }
 
+    /// Get the mask of the information which was actually filled in by the kernel.
+    ///
+    /// This may contain fewer flags than requested, either because the kernel does not support
+    /// them, or because the information was not available for this mount.
+    pub fn mask(&self) -> StatMountFlags {
+        StatMountFlags::from_bits_retain(self.base.mask)
+    }
+
     /// Get a string option as a `&CStr` if it is available.
     fn str_slice(&self, flag: StatMountFlags, value: u32) -> Option<&[u8]> {
         let value = self.option(flag, value)?;
@@ -520,6 +666,30 @@ impl StatMount {
 
     /// Get the device ID.
     ///
+    /// This is the superblock's device and can be compared against the device a file resides on as
+    /// reported by `statx(2)` via [`Metadata::fs_device`](crate::fs::stat::Metadata::fs_device()):
+    ///
+    /// ``` rust, no_run
+    /// # fn code() -> std::io::Result<()> {
+    /// use lisy::fs::Stat;
+    /// use lisy::mount::{ListMounts, StatMount};
+    ///
+    /// let meta = Stat::new().stat("/some/file")?;
+    /// for id in ListMounts::here() {
+    ///     let stat = StatMount::stat(id?)?;
+    ///     if stat.device() == Some(meta.fs_device()) {
+    ///         println!("file lives on {:?}", stat.mount_point());
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Note that this is not a unique mapping: bind mounts share the superblock and therefore the
+    /// device of the original mount. Also, some file systems (such as `btrfs` subvolumes) report a
+    /// different device via `statx(2)` than the superblock's. To find the exact mount, prefer
+    /// comparing against [`Metadata::unique_mount_id`](crate::fs::stat::Metadata::unique_mount_id()).
+    ///
     /// This is governed by [`StatMountFlags::SB_BASIC`].
     pub fn device(&self) -> Option<Device> {
         self.option(
@@ -582,6 +752,17 @@ impl StatMount {
         self.option(StatMountFlags::MNT_BASIC, self.base.mnt_attr)
     }
 
+    /// Get the atime update mode of the mount.
+    ///
+    /// The mode is a multi-bit field within the [`attr`](Self::attr()) flags which cannot be
+    /// tested for with `contains()` (`relatime` being zero), so this decodes it. This is the
+    /// counterpart to [`MountSetAttr::atime`](crate::mount::MountSetAttr::atime()).
+    ///
+    /// This is governed by [`StatMountFlags::MNT_BASIC`].
+    pub fn atime_mode(&self) -> Option<AtimeMode> {
+        self.attr().and_then(AtimeMode::from_attr)
+    }
+
     /// Get the mount propagation flags.
     ///
     /// This is governed by [`StatMountFlags::MNT_BASIC`].
@@ -610,6 +791,11 @@ impl StatMount {
         self.c_str(StatMountFlags::SB_SOURCE, self.base.sb_source)
     }
 
+    /// Get the source string as UTF-8 for display, see [`source`](Self::source()).
+    pub fn source_lossy(&self) -> Option<Cow<'_, str>> {
+        self.source().map(CStr::to_string_lossy)
+    }
+
     /// Get the propagate-from value.
     ///
     /// This is governed by [`StatMountFlags::PROPAGATE_FROM`].
@@ -624,6 +810,11 @@ impl StatMount {
         self.c_str(StatMountFlags::MNT_ROOT, self.base.mnt_root)
     }
 
+    /// Get the root of the mount as UTF-8 for display, see [`mount_root`](Self::mount_root()).
+    pub fn mount_root_lossy(&self) -> Option<Cow<'_, str>> {
+        self.mount_root().map(CStr::to_string_lossy)
+    }
+
     /// Get the mount point.
     ///
     /// This is governed by [`StatMountFlags::MNT_POINT`].
@@ -631,6 +822,27 @@ impl StatMount {
         self.c_str(StatMountFlags::MNT_POINT, self.base.mnt_point)
     }
 
+    /// Get the mount point as UTF-8 for display, see [`mount_point`](Self::mount_point()).
+    pub fn mount_point_lossy(&self) -> Option<Cow<'_, str>> {
+        self.mount_point().map(CStr::to_string_lossy)
+    }
+
+    /// Open the mount point with the provided `how`.
+    ///
+    /// This uses the [`mount_point`](Self::mount_point()) path, so it fails if
+    /// [`StatMountFlags::MNT_POINT`] was not requested.
+    ///
+    /// Note that the path is relative to the current process' root directory. If the mount was
+    /// queried for a different mount namespace, the path may not resolve, or resolve to something
+    /// else entirely.
+    #[cfg(feature = "open")]
+    pub fn open_mount_point(&self, how: crate::open::OpenHow) -> io::Result<OwnedFd> {
+        match self.mount_point() {
+            Some(path) => how.open(path),
+            None => io_bail!("mount point was not requested in statmount call"),
+        }
+    }
+
     /// Get the file system type.
     ///
     /// This is governed by [`StatMountFlags::FS_TYPE`].
@@ -655,7 +867,7 @@ impl StatMount {
     /// Get an iterator over the separate mount options.
     ///
     /// This is governed by [`StatMountFlags::OPT_ARRAY`].
-    pub fn options(&self) -> Option<OptionIter> {
+    pub fn options(&self) -> Option<OptionIter<'_>> {
         Some(OptionIter::new(
             usize::try_from(self.base.opt_num).unwrap(),
             self.str_slice(StatMountFlags::OPT_ARRAY, self.base.opt_array)?,
@@ -665,7 +877,7 @@ impl StatMount {
     /// Get an iterator over the separate security options.
     ///
     /// This is governed by [`StatMountFlags::OPT_SEC_ARRAY`].
-    pub fn security_options(&self) -> Option<OptionIter> {
+    pub fn security_options(&self) -> Option<OptionIter<'_>> {
         Some(OptionIter::new(
             usize::try_from(self.base.opt_sec_num).unwrap(),
             self.str_slice(StatMountFlags::OPT_SEC_ARRAY, self.base.opt_sec_array)?,
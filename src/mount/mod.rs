@@ -2,14 +2,43 @@
 //!
 //! This crate provides access to the kernel's new mount API.
 
-use std::ffi::{CStr, c_int};
+use std::ffi::{CStr, CString, c_int};
 use std::io;
+use std::os::fd::{IntoRawFd, OwnedFd, RawFd};
+use std::path::PathBuf;
+#[cfg(feature = "open")]
+use std::path::{Component, Path};
 
 use crate::CPath;
-use crate::error::io_assert;
+use crate::error::{io_assert, io_bail, io_format_err};
 
 #[doc(inline)]
 pub use crate::mount_types::{MountId, MountNsId, ReusedMountId};
+#[doc(inline)]
+pub use crate::types::Device;
+
+/// Implement `Default` as just `CLOEXEC` and add a `without_cloexec` method to the flags of a
+/// call returning a file descriptor.
+macro_rules! cloexec_flags {
+    ($name:ident) => {
+        impl Default for $name {
+            /// Just [`CLOEXEC`](Self::CLOEXEC), so the file descriptor is not inherited by
+            /// executed programs. Use [`empty()`](Self::empty()) to start without any flags.
+            fn default() -> Self {
+                Self::CLOEXEC
+            }
+        }
+
+        impl $name {
+            /// Remove [`CLOEXEC`](Self::CLOEXEC), for the rare case where the file descriptor is
+            /// meant to be inherited by an executed program.
+            pub const fn without_cloexec(self) -> Self {
+                self.difference(Self::CLOEXEC)
+            }
+        }
+    };
+}
+use cloexec_flags;
 
 pub mod sys;
 
@@ -19,17 +48,47 @@ pub use fs::{Fs, FsOpen};
 
 pub mod superblock;
 #[doc(inline)]
-pub use superblock::{FsMount, FsPick, MountAttr, Superblock};
+pub use superblock::{FsConfigTxn, FsMount, FsPick, MountAttr, Superblock};
 
+#[allow(clippy::module_inception)]
 pub mod mount;
 #[doc(inline)]
-pub use mount::{Mount, MountSetAttr, MoveMount, OpenTree};
+pub use mount::{AtimeMode, Mount, MountSetAttr, MoveMount, OpenTree};
 
 pub mod ns;
 
+pub mod mountinfo;
+#[doc(inline)]
+pub use mountinfo::MountInfoEntry;
+
+#[cfg(feature = "fs")]
+mod wait;
+#[cfg(feature = "fs")]
+pub use wait::{wait_for_mount, wait_for_unmount};
+
+#[cfg(feature = "fs")]
+mod builder;
+#[cfg(feature = "fs")]
+pub use builder::{Builder, MountStep};
+
+mod guard;
+pub use guard::MountGuard;
+
+mod overlay;
+pub use overlay::OverlayBuilder;
+
+mod propagation;
+pub use propagation::{PropagationGraph, propagation_graph};
+
+mod tree;
+pub use tree::iter_tree;
+
+mod unmount;
+pub use unmount::{UmountFlags, unmount_tree};
+
 mod list;
 pub use list::ListMounts;
-pub use list::{StatMount, StatMountBuilder, list};
+pub use list::{StatMount, StatMountBuilder, list, relative_mount_point};
 
 /// Wrapper for the `umount2(2)` system call.
 pub fn umount<P>(path: &P, flags: c_int) -> io::Result<()>
@@ -38,9 +97,372 @@ where
 {
     fn umount_do(path: &CStr, flags: c_int) -> io::Result<()> {
         let rc = unsafe { libc::umount2(path.as_ptr(), flags) };
-        io_assert!(rc == 0);
+        io_assert!(rc == 0, "umount2");
         Ok(())
     }
 
     path.c_path(|path| umount_do(path, flags))?
 }
+
+/// Clone the whole mount tree visible at `/` into a detached tree, like `mount --rbind / ...`.
+///
+/// This is [`Mount::open_tree`] of `/` with [`OpenTree::CLONE`] and [`OpenTree::RECURSIVE`]. The
+/// result is a snapshot of the current topology: later mount changes are not reflected in it
+/// (unless they propagate, see [`MountSetAttr::propagation`]), and it can be attached elsewhere
+/// or used as the root of a new namespace.
+///
+/// This requires `CAP_SYS_ADMIN` in the user namespace owning the current mount namespace.
+/// Mounts which were locked together when the namespace was created by an unprivileged user
+/// stay locked in the copy.
+///
+/// Note that every mount of the tree is duplicated in the kernel, and the copies are kept until
+/// the returned handle is dropped (or, once attached, unmounted). On systems with many mounts,
+/// such as container hosts, this takes a correspondingly large amount of kernel memory, and the
+/// kernel may fail with `ENOSPC` if the resulting tree would exceed the `fs.mount-max` limit.
+pub fn clone_namespace() -> io::Result<Mount> {
+    Mount::open_tree(
+        c"/",
+        OpenTree::CLOEXEC | OpenTree::CLONE | OpenTree::RECURSIVE,
+        0,
+    )
+}
+
+/// Find the mount point of the mount `path` resides on.
+///
+/// The unique mount id of `path` (following symlinks) is queried via `statx(2)` and its mount
+/// point via `statmount(2)`, so for a path deep inside a mount this returns the directory the
+/// mount is attached to, for example `/home` for `/home/user/.profile`. The mount point is relative
+/// to the current root, just like in `/proc/self/mountinfo`.
+///
+/// Returns `None` on kernels without `statmount(2)` or unique mount ids (before 6.9).
+pub fn containing_mount_point<P>(path: &P) -> io::Result<Option<PathBuf>>
+where
+    P: ?Sized + CPath,
+{
+    use std::os::unix::ffi::OsStrExt;
+
+    let id = match path.c_path(|path| unique_mount_id_at(libc::AT_FDCWD, path, 0))? {
+        Ok(id) => id,
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let stat = match StatMount::builder()
+        .basic_superblock_info(false)
+        .mount_point(true)
+        .mount_id(id)
+        .stat()
+    {
+        Ok(stat) => stat,
+        Err(err) if crate::raw_os_error(&err) == Some(libc::ENOSYS) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let point = stat
+        .mount_point()
+        .ok_or_else(|| io_format_err!("statmount did not return the mount point"))?;
+    Ok(Some(PathBuf::from(std::ffi::OsStr::from_bytes(
+        point.to_bytes(),
+    ))))
+}
+
+/// List all the mounts below the mount containing `path`, newest first.
+///
+/// The path is resolved (following symlinks) to find its mount, and the mounts below it are listed
+/// with [`ListMounts::reverse`]. `listmount(2)` yields all the descendants at once, not only the
+/// direct children, so there is no need to recurse. Since a mount can only be unmounted after the
+/// mounts on top of it, which are usually newer, this is generally the order to tear them down
+/// in. Mounts moved below older ones break this, see [`unmount_tree`] for a teardown following
+/// the actual tree.
+///
+/// This requires kernel 6.11 for the reverse listing, and 6.9 for the `statx(2)` unique mount id.
+pub fn list_children_reverse<P>(path: &P) -> io::Result<ListMounts>
+where
+    P: ?Sized + CPath,
+{
+    let id = path.c_path(|path| unique_mount_id_at(libc::AT_FDCWD, path, 0))??;
+    Ok(ListMounts::new(id, None).reverse(true))
+}
+
+/// Get the unique [`MountId`] of the mount at `/`.
+///
+/// This is the root as seen by the current process (so it takes `chroot(2)` into account) and can
+/// be used as the starting point to inspect the visible mounts via `statmount(2)`. To get a handle
+/// to the mount itself, see [`Mount::current_root`].
+///
+/// This uses `statx(2)` with `STATX_MNT_ID_UNIQUE` and therefore requires kernel 6.9.
+pub fn root_mount_id() -> io::Result<MountId> {
+    unique_mount_id_at(libc::AT_FDCWD, c"/", 0)
+}
+
+/// Get the options the root file system is mounted with.
+///
+/// These are the file system specific options, such as the last column of `/proc/self/mountinfo`,
+/// but without the generic `ro`/`rw` flag. The mount attributes can be found via
+/// [`StatMount::attr`](crate::mount::StatMount::attr()).
+///
+/// This looks up the mount id of `/` via `statx(2)` and queries its options via `statmount(2)`.
+/// Returns `None` if the kernel lacks support for either (`statmount(2)` needs 6.9, the
+/// options are only reported since 6.11).
+pub fn root_options() -> io::Result<Option<CString>> {
+    let id = match root_mount_id() {
+        Ok(id) => id,
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    match id.stat(sys::StatMountFlags::MNT_OPTS) {
+        Ok(stat) => Ok(stat.mount_options().map(CStr::to_owned)),
+        Err(err) if crate::raw_os_error(&err) == Some(libc::ENOSYS) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Check whether mounts of the file system `fs_type` can be idmapped.
+///
+/// This is a best-effort probe: after checking kernel support as for
+/// [`KernelSupport::idmapped_mounts`](crate::KernelSupport::idmapped_mounts), a new instance of
+/// the file system is created without any options and mounted *detached*, then an idmapping to a
+/// helper user namespace mapping only the current user and group ids is applied. The mount is
+/// never attached anywhere and is released afterwards. Returns `false` if the kernel or the file
+/// system does not support idmapped mounts (`EINVAL`, `EOPNOTSUPP` or `ENOSYS`).
+///
+/// Creating the file system and idmapping a mount require `CAP_SYS_ADMIN` (in the user namespace
+/// owning the current mount namespace, and in the initial user namespace for most file systems),
+/// so a lack of privileges results in an `EPERM` error rather than `false`. File systems which
+/// need a source device, such as `ext4`, cannot be created this way, and the resulting error is
+/// returned as well.
+#[cfg(feature = "userns")]
+pub fn idmapped_mounts_supported(fs_type: &str) -> io::Result<bool> {
+    use crate::userns::{IdMapping, Userns};
+
+    if !crate::probe::idmapped_mounts() {
+        return Ok(false);
+    }
+
+    let mount = Fs::open(fs_type, FsOpen::CLOEXEC)
+        .and_then(Fs::create)
+        .and_then(|sb| sb.mount(FsMount::CLOEXEC, MountAttr::empty()))
+        .map_err(|err| crate::error::context(err, format!("failed to create {fs_type}")))?;
+
+    let uid = unsafe { libc::getuid() };
+    let gid = unsafe { libc::getgid() };
+    let userns = Userns::builder()?;
+    userns.map(&[IdMapping::new(0..1, uid)], &[IdMapping::new(0..1, gid)])?;
+    let userns = userns.into_fd()?;
+
+    match mount.setattr(&MountSetAttr::new().idmap(&userns), 0) {
+        Ok(()) => Ok(true),
+        Err(err)
+            if matches!(
+                crate::raw_os_error(&err),
+                Some(libc::EINVAL | libc::EOPNOTSUPP | libc::ENOSYS)
+            ) =>
+        {
+            Ok(false)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Query the unique mount id via `statx(2)` with `STATX_MNT_ID_UNIQUE`.
+fn unique_mount_id_at(dirfd: RawFd, path: &CStr, at_flags: c_int) -> io::Result<MountId> {
+    mount_root_at(dirfd, path, at_flags).map(|(id, _)| id)
+}
+
+/// Like [`unique_mount_id_at`], but additionally report whether `path` is the root of its mount,
+/// if the kernel tells via `STATX_ATTR_MOUNT_ROOT`.
+fn mount_root_at(
+    dirfd: RawFd,
+    path: &CStr,
+    at_flags: c_int,
+) -> io::Result<(MountId, Option<bool>)> {
+    const STATX_MNT_ID_UNIQUE: u32 = 0x00004000;
+
+    let mut data: libc::statx = unsafe { std::mem::zeroed() };
+    let rc = unsafe {
+        libc::statx(
+            dirfd,
+            path.as_ptr(),
+            at_flags,
+            STATX_MNT_ID_UNIQUE,
+            &raw mut data,
+        )
+    };
+    io_assert!(rc == 0, "statx");
+    if data.stx_mask & STATX_MNT_ID_UNIQUE == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "kernel does not support unique mount ids",
+        ));
+    }
+    const MOUNT_ROOT: u64 = libc::STATX_ATTR_MOUNT_ROOT as u64;
+    let root = (data.stx_attributes_mask & MOUNT_ROOT != 0)
+        .then_some(data.stx_attributes & MOUNT_ROOT != 0);
+    Ok((MountId::from_raw(data.stx_mnt_id), root))
+}
+
+/// Walk `path` one component at a time and report where it crosses into a different mount.
+///
+/// The result starts with the starting point (`/` for absolute paths, `.` otherwise) and its
+/// mount, followed by each prefix of `path` which lives on a different mount than the previous
+/// one. For instance, `/var/lib/ct/rootfs/etc` may yield `/` and `/var/lib/ct/rootfs`.
+///
+/// Each component is opened with `openat2(2)` relative to the previous one, so the walk sees the
+/// same mounts the kernel's own path lookup does:
+///
+/// * Symlinks are followed, including in the final component. The reported prefix is still the
+///   lexical one, but the mount id is that of the symlink's target.
+/// * `..` is resolved by the kernel relative to the directory reached so far, that is, physically
+///   after following symlinks, and moves up out of a mount when leaving its root.
+/// * Automounts are not triggered.
+///
+/// The mount ids are queried with `statx(2)` and `STATX_MNT_ID_UNIQUE`, requiring kernel 6.9.
+#[cfg(feature = "open")]
+pub fn resolve_crossings<P>(path: &P) -> io::Result<Vec<(PathBuf, MountId)>>
+where
+    P: ?Sized + CPath,
+{
+    path.c_path(resolve_crossings_do)?
+}
+
+#[cfg(feature = "open")]
+fn resolve_crossings_do(path: &CStr) -> io::Result<Vec<(PathBuf, MountId)>> {
+    use std::ffi::OsStr;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::ffi::OsStrExt;
+
+    use crate::open::OpenHow;
+
+    let path = Path::new(OsStr::from_bytes(path.to_bytes()));
+    let how = OpenHow::new().flags(libc::O_PATH as u64);
+
+    let (mut prefix, mut current) = if path.has_root() {
+        (PathBuf::from("/"), how.open_raw(c"/")?)
+    } else {
+        (PathBuf::from("."), how.open_raw(c".")?)
+    };
+    let mut last_id = unique_mount_id_at(current.as_raw_fd(), c"", libc::AT_EMPTY_PATH)?;
+    let mut crossings = vec![(prefix.clone(), last_id)];
+
+    for component in path.components() {
+        let name = match component {
+            Component::RootDir | Component::CurDir => continue,
+            Component::ParentDir => c"..".into(),
+            Component::Normal(name) => crate::c_path::io_c_os_str(name)?,
+            Component::Prefix(_) => io_bail!("invalid path component ({component:?})"),
+        };
+        current = how.open_at_raw(current.as_raw_fd(), &name)?;
+        prefix.push(component);
+
+        let id = unique_mount_id_at(current.as_raw_fd(), c"", libc::AT_EMPTY_PATH)?;
+        if id != last_id {
+            crossings.push((prefix.clone(), id));
+            last_id = id;
+        }
+    }
+
+    Ok(crossings)
+}
+
+/// Change the attributes of the mount at `path` via `mount_setattr(2)`.
+///
+/// This is the path based equivalent of [`Mount::setattr`] and avoids having to
+/// [`open_tree`](Mount::open_tree()) the mount first, similar to `mount -o remount,...` for mount
+/// attributes. The path is resolved relative to the current working directory.
+///
+/// Contrary to the file descriptor based version, which always acts on the mount the file
+/// descriptor refers to (which may also be a detached mount tree), this acts on whatever is mounted
+/// at `path` at the time of the call. `at_flags` may contain `AT_RECURSIVE` to change the entire
+/// subtree, as well as `AT_SYMLINK_NOFOLLOW` and `AT_NO_AUTOMOUNT` to control how `path` is
+/// resolved.
+pub fn setattr_path<P>(path: &P, attr: &MountSetAttr, at_flags: c_int) -> io::Result<()>
+where
+    P: ?Sized + CPath,
+{
+    fn setattr_do(path: &CStr, attr: &MountSetAttr, at_flags: c_int) -> io::Result<()> {
+        let rc = unsafe {
+            libc::syscall(
+                sys::SYS_mount_setattr,
+                libc::AT_FDCWD,
+                path.as_ptr(),
+                at_flags,
+                attr,
+                std::mem::size_of_val(attr),
+            )
+        };
+        io_assert!(rc == 0, "mount_setattr");
+        Ok(())
+    }
+
+    path.c_path(|path| setattr_do(path, attr, at_flags))?
+}
+
+/// Run `f` on a separate thread in a new mount namespace in which all mounts are slaves, see
+/// [`crate::ns::with_mount_namespace`].
+pub(crate) fn in_new_namespace<F, T>(f: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send,
+    T: Send,
+{
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                let rc = unsafe { libc::unshare(libc::CLONE_NEWNS) };
+                io_assert!(rc == 0, "unshare");
+                setattr_path(
+                    "/",
+                    &MountSetAttr::new().propagation(libc::MS_SLAVE),
+                    libc::AT_RECURSIVE as _,
+                )?;
+                f()
+            })
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Explicitly close a file descriptor, returning any error from `close(2)`.
+///
+/// Note that on Linux the file descriptor is released even if an error is returned, so this must
+/// not be retried.
+fn close_fd(fd: OwnedFd) -> io::Result<()> {
+    let rc = unsafe { libc::close(fd.into_raw_fd()) };
+    io_assert!(rc == 0, "close");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{TempDir, in_mount_namespace, tmpfs};
+
+    #[test]
+    fn clone_namespace_snapshot() {
+        use std::os::fd::AsRawFd;
+
+        let tmp = TempDir::new().unwrap();
+        let a = tmp.path().join("a");
+        let b = tmp.path().join("b");
+        std::fs::create_dir(&a).unwrap();
+        std::fs::create_dir(&b).unwrap();
+        std::fs::write(b.join("file"), b"below").unwrap();
+
+        in_mount_namespace(|| {
+            tmpfs().move_mount(&a, MoveMount::empty()).unwrap();
+            std::fs::write(a.join("file"), b"tmpfs").unwrap();
+
+            let copy = clone_namespace().unwrap();
+            tmpfs().move_mount(&b, MoveMount::empty()).unwrap();
+            assert!(!b.join("file").exists());
+
+            let id = unique_mount_id_at(copy.as_raw_fd(), c"", libc::AT_EMPTY_PATH).unwrap();
+            assert_ne!(id, root_mount_id().unwrap());
+
+            // the submounts are part of the copy, later mounts are not
+            let root = PathBuf::from(format!("/proc/self/fd/{}", copy.as_raw_fd()));
+            let inside = |path: &std::path::Path| root.join(path.strip_prefix("/").unwrap());
+            assert_eq!(std::fs::read(inside(&a).join("file")).unwrap(), b"tmpfs");
+            assert_eq!(std::fs::read(inside(&b).join("file")).unwrap(), b"below");
+        });
+    }
+}
@@ -4,12 +4,14 @@ use std::ffi::{CStr, c_int, c_uint, c_void};
 use std::io;
 use std::marker::PhantomData;
 use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
 
 use bitflags::bitflags;
 
 use crate::CPath;
-use crate::error::{io_assert, io_bail};
-use crate::mount::sys;
+use crate::error::{io_assert, io_bail, io_format_err};
+use crate::mount::sys::{MountPropagation, StatMountFlags};
+use crate::mount::{ListMounts, MountGuard, MountId, StatMount, sys};
 
 #[cfg(feature = "open")]
 use crate::open::OpenHow;
@@ -69,6 +71,54 @@ bitflags! {
     }
 }
 
+/// The `MOUNT_ATTR__ATIME` mask covering the atime mode field of [`MountAttr`].
+const MOUNT_ATTR__ATIME: u64 = 0x0000_0070;
+
+/// The atime update mode of a mount.
+///
+/// In [`MountAttr`] the atime mode is a *field* masked by `MOUNT_ATTR__ATIME` rather than a set of
+/// independent flags, and [`MountAttr::RELATIME`] is zero. This means `set(MountAttr::RELATIME)`
+/// does nothing. Use [`MountSetAttr::atime`] to change the mode instead.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum AtimeMode {
+    /// Update the access time relative to the modification or change time (`relatime`).
+    Relatime,
+    /// Never update the access time (`noatime`).
+    Noatime,
+    /// Always update the access time (`strictatime`).
+    Strictatime,
+}
+
+impl AtimeMode {
+    /// Decode the atime field of a set of mount attributes, such as the ones reported by
+    /// [`StatMount::attr`](crate::mount::StatMount::attr()).
+    ///
+    /// Returns `None` if the field holds a value not known to correspond to any mode.
+    pub const fn from_attr(attr: MountAttr) -> Option<Self> {
+        match attr.bits() as u64 & MOUNT_ATTR__ATIME {
+            0 => Some(Self::Relatime),
+            bits if bits == MountAttr::NOATIME.bits() as u64 => Some(Self::Noatime),
+            bits if bits == MountAttr::STRICTATIME.bits() as u64 => Some(Self::Strictatime),
+            _ => None,
+        }
+    }
+
+    /// The value of the atime field.
+    const fn bits(self) -> u64 {
+        match self {
+            Self::Relatime => MountAttr::RELATIME.bits() as u64,
+            Self::Noatime => MountAttr::NOATIME.bits() as u64,
+            Self::Strictatime => MountAttr::STRICTATIME.bits() as u64,
+        }
+    }
+}
+
+impl From<AtimeMode> for MountAttr {
+    fn from(mode: AtimeMode) -> Self {
+        MountAttr::from_bits_retain(mode.bits() as c_uint)
+    }
+}
+
 /// The raw data we can use without lifetimes.
 #[derive(Clone, Debug)]
 #[repr(C)]
@@ -131,6 +181,29 @@ impl MountSetAttr<'_> {
         self
     }
 
+    /// Change the atime mode.
+    ///
+    /// This replaces the whole atime field, clearing it via `MOUNT_ATTR__ATIME` and setting the
+    /// new mode, as the kernel requires. The `nodiratime` modifier is a separate flag, see
+    /// [`nodiratime`](Self::nodiratime()).
+    ///
+    /// Note that a later [`keep`](Self::keep()) or [`set`](Self::set()) call with atime flags
+    /// modifies the masks produced here.
+    pub fn atime(mut self, mode: AtimeMode) -> Self {
+        self.attr.attr_set = (self.attr.attr_set & !MOUNT_ATTR__ATIME) | mode.bits();
+        self.attr.attr_clr |= MOUNT_ATTR__ATIME;
+        self
+    }
+
+    /// Set or clear the `nodiratime` modifier (do not update access times on directories).
+    pub fn nodiratime(self, on: bool) -> Self {
+        if on {
+            self.set(MountAttr::NODIRATIME)
+        } else {
+            self.clear(MountAttr::NODIRATIME)
+        }
+    }
+
     /// Set the idmap file descriptor.
     pub fn idmap<'new, T: AsRawFd + ?Sized + 'new>(self, fd: &'new T) -> MountSetAttr<'new> {
         MountSetAttr::<'new> {
@@ -163,6 +236,8 @@ impl MountSetAttr<'_> {
     }
 
     /// An `MS_` flag to set the propagation to. `0` leaves it unchagned.
+    ///
+    /// A [`MountPropagation`] as returned by `statmount(2)` can be converted via `into()`.
     pub fn propagation(mut self, propagation: u64) -> Self {
         self.attr.propagation = propagation;
         self
@@ -176,10 +251,64 @@ pub struct Mount {
 
 impl AsFd for Mount {
     fn as_fd(&self) -> BorrowedFd<'_> {
-        unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
+        self.fd.as_fd()
     }
 }
 
+/// A mount found by [`Mount::list_tree`].
+struct TreeEntry {
+    /// The mount point relative to the root of the tree.
+    path: PathBuf,
+    /// The mount's information.
+    stat: Box<StatMount>,
+    /// The id of the mount, or `None` if it was found in a copy of a detached tree.
+    id: Option<MountId>,
+    /// Whether another mount is stacked on top of this one.
+    covered: bool,
+}
+
+/// List the mounts of a tree visible to `listmount(2)`, `tree` being a handle to its root `root`.
+fn list_attached_tree(tree: &Mount, root: MountId, keep_ids: bool) -> io::Result<Vec<TreeEntry>> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut entries = Vec::new();
+    let mut root_point = None;
+    for (_, stat) in crate::mount::tree::list_subtree(root, StatMountFlags::all())? {
+        // unwrap: `list_subtree` only returns mounts with an id
+        let id = stat.id().unwrap();
+        let point = stat
+            .mount_point()
+            .ok_or_else(|| io_format_err!("statmount did not return a mount point"))?;
+        let point = Path::new(std::ffi::OsStr::from_bytes(point.to_bytes()));
+        let root_point = &*root_point.get_or_insert_with(|| point.to_path_buf());
+        let path = point
+            .strip_prefix(root_point)
+            .map_err(|_| io_format_err!("mount point {point:?} is not below {root_point:?}"))?
+            .to_path_buf();
+
+        let covered = if id == root {
+            false
+        } else {
+            let found = path.c_path(|path| {
+                crate::mount::unique_mount_id_at(
+                    tree.as_raw_fd(),
+                    path,
+                    libc::AT_SYMLINK_NOFOLLOW | libc::AT_NO_AUTOMOUNT,
+                )
+            })??;
+            found != id
+        };
+
+        entries.push(TreeEntry {
+            path,
+            stat,
+            id: keep_ids.then_some(id),
+            covered,
+        });
+    }
+    Ok(entries)
+}
+
 impl AsRawFd for Mount {
     fn as_raw_fd(&self) -> RawFd {
         self.fd.as_raw_fd()
@@ -202,6 +331,12 @@ impl FromRawFd for Mount {
     }
 }
 
+impl From<Mount> for OwnedFd {
+    fn from(this: Mount) -> OwnedFd {
+        this.fd
+    }
+}
+
 impl Mount {
     /// Open a mount tree from a given path.
     ///
@@ -214,6 +349,99 @@ impl Mount {
         path.c_path(move |path| Self::open_tree_at_raw(libc::AT_FDCWD, path, flags, at_flags))?
     }
 
+    /// Get a handle to the mount at `/` as seen by the current process.
+    ///
+    /// This does *not* clone the mount tree (no `OPEN_TREE_CLONE`), but merely opens the root
+    /// directory like `open(2)` with `O_PATH` would, and therefore requires no privileges. It is
+    /// meant as an entry point for inspection. Keep in mind that operations such as
+    /// [`setattr`](Self::setattr()) on this handle affect the actual root mount.
+    pub fn current_root() -> io::Result<Self> {
+        Self::open_tree_at_raw(libc::AT_FDCWD, c"/", OpenTree::CLOEXEC, 0)
+    }
+
+    /// Create a detached bind mount of the single file at `source`.
+    ///
+    /// This is [`open_tree`](Self::open_tree()) with [`OpenTree::CLONE`] but without
+    /// `AT_RECURSIVE`, as a file cannot have submounts. Directories are rejected with an
+    /// [`InvalidInput`](io::ErrorKind::InvalidInput) error.
+    ///
+    /// The kernel only allows mounting a file onto a file, so the target passed to
+    /// [`move_mount`](Self::move_mount()) must be an existing non-directory, such as an empty file
+    /// created as a placeholder. This is commonly used to inject configuration files like
+    /// `/etc/resolv.conf` into a container.
+    pub fn bind_file<P>(source: &P) -> io::Result<Self>
+    where
+        P: ?Sized + CPath,
+    {
+        let mount = Self::open_tree(source, OpenTree::CLOEXEC | OpenTree::CLONE, 0)?;
+
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        let rc = unsafe { libc::fstat(mount.as_raw_fd(), &raw mut stat) };
+        io_assert!(rc == 0, "fstat");
+        if stat.st_mode & libc::S_IFMT == libc::S_IFDIR {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "bind_file() used on a directory",
+            ));
+        }
+
+        Ok(mount)
+    }
+
+    /// Bind mount `source` onto `dest` read-only, like `mount --bind -o ro`.
+    ///
+    /// The tree is cloned and made read-only while still detached, and only then moved into
+    /// place, so it is never writable at `dest`, contrary to a bind mount followed by a remount.
+    /// The source itself does not need to be read-only and is left unchanged. With `recursive`,
+    /// the mounts below `source` are included and made read-only as well.
+    pub fn bind_readonly<P, Q>(source: &P, dest: &Q, recursive: bool) -> io::Result<()>
+    where
+        P: ?Sized + CPath,
+        Q: ?Sized + CPath,
+    {
+        let (flags, at_flags) = if recursive {
+            (OpenTree::RECURSIVE, libc::AT_RECURSIVE)
+        } else {
+            (OpenTree::empty(), 0)
+        };
+
+        let mount = Self::open_tree(source, OpenTree::CLOEXEC | OpenTree::CLONE | flags, 0)?;
+        mount.setattr(&MountSetAttr::new().set(MountAttr::RDONLY), at_flags)?;
+        mount.move_mount(dest, MoveMount::empty())
+    }
+
+    /// Clone the tree at `path` and additionally open an `O_PATH` directory handle to its root.
+    ///
+    /// The returned [`Mount`] is meant for changing attributes and attaching the tree via
+    /// [`move_mount`](Self::move_mount()), while the directory handle is meant for inspecting its
+    /// contents, for instance with [`OpenHow::sandbox_rooted`] to resolve paths as if the tree
+    /// was the root file system.
+    ///
+    /// Both refer to the same mount, but they are independent file descriptors: moving the tree
+    /// into place does not invalidate the directory handle, which then simply refers to the now
+    /// attached mount. If the `Mount` is dropped while still detached, the directory handle keeps
+    /// the mount alive and its contents accessible, but the tree can no longer be attached.
+    ///
+    /// With `recursive`, the mounts below `path` are included in the clone.
+    #[cfg(feature = "open")]
+    pub fn open_tree_rooted<P>(path: &P, recursive: bool) -> io::Result<(Self, OwnedFd)>
+    where
+        P: ?Sized + CPath,
+    {
+        let flags = if recursive {
+            OpenTree::RECURSIVE
+        } else {
+            OpenTree::empty()
+        };
+
+        let mount = Self::open_tree(path, OpenTree::CLOEXEC | OpenTree::CLONE | flags, 0)?;
+        let root = OpenHow::new_directory()
+            .flags(libc::O_PATH as u64)
+            .at_fd(&mount)
+            .open(c".")?;
+        Ok((mount, root))
+    }
+
     /// Open a mount tree from a given path relative to a directory file descriptor `dfd`.
     ///
     /// If `flags` contains [`OpenTree::CLONE`], this creates a file handle to a separate "bind"
@@ -247,7 +475,7 @@ impl Mount {
         at_flags: c_uint,
     ) -> io::Result<Self> {
         let rc = unsafe { Self::open_tree_at_raw_do(dfd, path, flags, at_flags) };
-        io_assert!(rc >= 0);
+        io_assert!(rc >= 0, "open_tree");
         let fd = unsafe { OwnedFd::from_raw_fd(rc as RawFd) };
         Ok(Self { fd })
     }
@@ -284,6 +512,89 @@ impl Mount {
         dest.c_path(move |dest| self.move_mount_at_raw(libc::AT_FDCWD, dest, move_flags))?
     }
 
+    /// Move this mount point to `dest` and confirm that it actually arrived there.
+    ///
+    /// After the move, `dest` is looked up again with `statx(2)`, which must report it as the root
+    /// of a mount, and that mount must be the one this handle refers to, rather than, say, one
+    /// which was mounted on top of it concurrently. On success the mount's unique [`MountId`] is
+    /// returned, otherwise an error describing what was found at `dest` instead.
+    ///
+    /// The lookup follows symlinks and triggers automounts only if `move_flags` allows the move
+    /// itself to do so. [`MoveMount::BENEATH`] and [`MoveMount::SET_GROUP`] are rejected, since
+    /// neither leaves this mount on top of `dest`.
+    ///
+    /// This requires kernel 6.9 for unique mount ids.
+    pub fn move_mount_verified<P>(&self, dest: &P, move_flags: MoveMount) -> io::Result<MountId>
+    where
+        P: ?Sized + CPath,
+    {
+        if move_flags.intersects(MoveMount::BENEATH | MoveMount::SET_GROUP) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot verify a move_mount beneath a mount or setting a propagation group",
+            ));
+        }
+
+        let mut at_flags = 0;
+        if !move_flags.contains(MoveMount::T_SYMLINKS) {
+            at_flags |= libc::AT_SYMLINK_NOFOLLOW;
+        }
+        if !move_flags.contains(MoveMount::T_AUTOMOUNTS) {
+            at_flags |= libc::AT_NO_AUTOMOUNT;
+        }
+        if move_flags.contains(MoveMount::T_EMPTY_PATH) {
+            at_flags |= libc::AT_EMPTY_PATH;
+        }
+
+        dest.c_path(move |dest| {
+            self.move_mount_at_raw(libc::AT_FDCWD, dest, move_flags)?;
+
+            let ours =
+                crate::mount::unique_mount_id_at(self.fd.as_raw_fd(), c"", libc::AT_EMPTY_PATH)?;
+            match crate::mount::mount_root_at(libc::AT_FDCWD, dest, at_flags)? {
+                (id, Some(true)) if id == ours => Ok(id),
+                (_, Some(false)) => {
+                    io_bail!("{dest:?} is not a mount point after moving mount {ours:?} there")
+                }
+                (id, Some(true)) => io_bail!(
+                    "{dest:?} is the root of mount {id:?} instead of the moved mount {ours:?}"
+                ),
+                (_, None) => Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "kernel does not report STATX_ATTR_MOUNT_ROOT",
+                )),
+            }
+        })?
+    }
+
+    /// Move this mount point to `dest` and return a [`MountGuard`] which unmounts it again when
+    /// dropped.
+    ///
+    /// ``` rust, no_run
+    /// # fn code() -> std::io::Result<()> {
+    /// use lisy::mount::{Mount, OpenTree};
+    ///
+    /// let tree = Mount::open_tree("/srv/data", OpenTree::CLONE | OpenTree::CLOEXEC, 0)?;
+    /// let guard = tree.move_mount_guarded("/mnt")?;
+    /// // ... use /mnt ...
+    /// guard.unmount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn move_mount_guarded<P>(&self, dest: &P) -> io::Result<MountGuard>
+    where
+        P: ?Sized + CPath,
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        dest.c_path(|dest| {
+            self.move_mount_at_raw(libc::AT_FDCWD, dest, MoveMount::empty())?;
+            Ok(MountGuard::new(std::ffi::OsStr::from_bytes(
+                dest.to_bytes(),
+            )))
+        })?
+    }
+
     /// Move this mount point to a new location, relative to a directory file descriptor.
     pub fn move_mount_at<D, P>(&self, dfd: &D, dest: &P, move_flags: MoveMount) -> io::Result<()>
     where
@@ -294,6 +605,57 @@ impl Mount {
         dest.c_path(move |dest| self.move_mount_at_raw(dfd, dest, move_flags))?
     }
 
+    /// Move this mount point onto the file or directory referred to by `target`.
+    ///
+    /// This uses `MOVE_MOUNT_T_EMPTY_PATH` so the destination is not resolved by path again,
+    /// which can be used to avoid races on the target side, eg. with an `O_PATH` file descriptor.
+    pub fn move_mount_to_fd<T>(&self, target: &T, move_flags: MoveMount) -> io::Result<()>
+    where
+        T: ?Sized + AsFd,
+    {
+        self.move_mount_at_raw(
+            target.as_fd().as_raw_fd(),
+            c"",
+            move_flags | MoveMount::T_EMPTY_PATH,
+        )
+    }
+
+    /// Make the mount `to` join the propagation setup of this mount, via `move_mount(2)` with
+    /// `MOVE_MOUNT_SET_GROUP` (kernel 5.15).
+    ///
+    /// If this mount is shared, `to` becomes a member of its peer group, and if this mount is a
+    /// slave, `to` becomes a slave of the same master. Nothing is moved. This is meant to replicate
+    /// the propagation topology of an existing tree (eg. for checkpoint/restore), which is not
+    /// possible via [`MountSetAttr::propagation`] alone, as that always creates new peer groups.
+    ///
+    /// The kernel requires that:
+    ///
+    /// - Both are attached mounts in the caller's mount namespace (obtained via
+    ///   [`open_tree`](Self::open_tree()) *without* [`OpenTree::CLONE`]) and refer to the roots of
+    ///   their mounts.
+    /// - Both belong to the same superblock, and the root of this mount is the same as or an
+    ///   ancestor of the root of `to`.
+    /// - `to` is private, that is, neither shared nor a slave.
+    /// - This mount is not private, that is, it is shared or a slave.
+    ///
+    /// Otherwise this fails with `EINVAL`.
+    pub fn set_sharing_group<T>(&self, to: &T) -> io::Result<()>
+    where
+        T: ?Sized + AsFd,
+    {
+        io_assert!(
+            unsafe {
+                self.move_mount_at_raw_do(
+                    to.as_fd().as_raw_fd(),
+                    c"",
+                    MoveMount::SET_GROUP | MoveMount::T_EMPTY_PATH,
+                )
+            },
+            "move_mount"
+        );
+        Ok(())
+    }
+
     /// Perform the move, raw parameters.
     pub fn move_mount_at_raw(
         &self,
@@ -304,7 +666,10 @@ impl Mount {
         if move_flags.intersects(MoveMount::F_MASK) {
             io_bail!("must not use source flags in move_mount()");
         }
-        io_assert!(unsafe { self.move_mount_at_raw_do(dfd, dest, move_flags) });
+        io_assert!(
+            unsafe { self.move_mount_at_raw_do(dfd, dest, move_flags) },
+            "move_mount"
+        );
         Ok(())
     }
 
@@ -336,6 +701,38 @@ impl Mount {
         }
     }
 
+    /// Check whether this handle refers to a detached mount, such as a tree cloned via
+    /// [`OpenTree::CLONE`] which has not been moved into place yet.
+    ///
+    /// A detached tree lives in an anonymous mount namespace which is kept alive by its file
+    /// descriptor, so it vanishes when the last handle is dropped. This is useful to assert the
+    /// state of a tree before calling [`move_mount`](Self::move_mount()), since moving an already
+    /// attached mount may not fail but move it away from its previous location.
+    ///
+    /// There is no direct way to query this, so this uses the following heuristic: the handle's
+    /// unique mount id is looked up via `statx(2)`, and then `statmount(2)` is asked about it in
+    /// the current mount namespace. Since only mounts in that namespace can be found there, an
+    /// `ENOENT` error is taken to mean that the mount is detached.
+    ///
+    /// This means that mounts of *other* mount namespaces (such as when the handle was passed from
+    /// another process) and mounts which have since been unmounted are also reported as detached.
+    /// It requires kernel 6.8 for `statmount(2)` and 6.9 for unique mount ids.
+    pub fn is_detached(&self) -> io::Result<bool> {
+        let id = crate::mount::unique_mount_id_at(self.fd.as_raw_fd(), c"", libc::AT_EMPTY_PATH)?;
+        match id.stat(sys::StatMountFlags::MNT_BASIC) {
+            Ok(_) => Ok(false),
+            Err(err) if crate::raw_os_error(&err) == Some(libc::ENOENT) => Ok(true),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Explicitly close the handle, returning any error `close(2)` produces.
+    ///
+    /// Dropping the handle also closes it, but silently ignores errors.
+    pub fn close(self) -> io::Result<()> {
+        crate::mount::close_fd(self.fd)
+    }
+
     /// Change attributes of the this mount point.
     pub fn setattr(&self, attr: &MountSetAttr, at_flags: c_int) -> io::Result<()> {
         let rc = unsafe {
@@ -348,10 +745,207 @@ impl Mount {
                 std::mem::size_of_val(attr),
             )
         };
-        io_assert!(rc == 0);
+        io_assert!(rc == 0, "mount_setattr");
+        Ok(())
+    }
+
+    /// Change attributes of this mount, and with `recursive` also of all the mounts below it.
+    ///
+    /// This is [`setattr`](Self::setattr()) with `AT_RECURSIVE` passed depending on `recursive`,
+    /// which is easy to forget when passing raw flags, silently leaving the submounts unchanged.
+    pub fn setattr_tree(&self, attr: &MountSetAttr, recursive: bool) -> io::Result<()> {
+        let at_flags = if recursive { libc::AT_RECURSIVE } else { 0 };
+        self.setattr(attr, at_flags)
+    }
+
+    /// Like [`setattr_tree`](Self::setattr_tree()), but refuses to idmap only the top of a tree.
+    ///
+    /// When `attr` sets [`MountAttr::IDMAP`] without `recursive` while there are mounts below this
+    /// one, the submounts would keep showing the unmapped ids, which is rarely intended. In this
+    /// case nothing is changed and an [`InvalidInput`](io::ErrorKind::InvalidInput) error is
+    /// returned instead.
+    ///
+    /// The submounts are looked up via `listmount(2)`, which requires kernel 6.9. Since the
+    /// kernel cannot enumerate detached trees, those are listed via a copy attached in a new mount
+    /// namespace, as for [`setattr_filtered`](Self::setattr_filtered()).
+    pub fn setattr_checked(&self, attr: &MountSetAttr, recursive: bool) -> io::Result<()> {
+        let idmap = u64::from(MountAttr::IDMAP.bits());
+        if !recursive && attr.attr.attr_set & idmap != 0 && self.has_submounts()? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "refusing to idmap a mount without AT_RECURSIVE while it has submounts",
+            ));
+        }
+        self.setattr_tree(attr, recursive)
+    }
+
+    /// Check whether there are mounts below this one.
+    fn has_submounts(&self) -> io::Result<bool> {
+        let id = crate::mount::unique_mount_id_at(self.as_raw_fd(), c"", libc::AT_EMPTY_PATH)?;
+        match ListMounts::new(id, None).next() {
+            None => Ok(false),
+            Some(Ok(_)) => Ok(true),
+            // detached trees cannot be listed directly
+            Some(Err(err)) if crate::raw_os_error(&err) == Some(libc::ENOENT) => {
+                Ok(self.list_tree()?.len() > 1)
+            }
+            Some(Err(err)) => Err(err),
+        }
+    }
+
+    /// Change the propagation type of this mount, and with `recursive` of all the mounts below it,
+    /// like `mount --make-[r]shared` and friends.
+    ///
+    /// With `mount_setattr(2)` recursion is requested via `AT_RECURSIVE`, which is what
+    /// `recursive` controls. The `MS_REC` flag of `mount(2)` has no meaning here and cannot be
+    /// expressed by [`MountPropagation`].
+    ///
+    /// Exactly one propagation type must be passed, combinations fail with `EINVAL`. Note that
+    /// making a mount a slave which is not shared and has no master makes it private instead,
+    /// see [`set_propagation_verified`](Self::set_propagation_verified()) to catch this.
+    pub fn set_propagation(
+        &self,
+        propagation: MountPropagation,
+        recursive: bool,
+    ) -> io::Result<()> {
+        self.setattr_tree(
+            &MountSetAttr::new().propagation(propagation.to_ms_flag()),
+            recursive,
+        )
+    }
+
+    /// Like [`set_propagation`](Self::set_propagation()), but check the result afterwards.
+    ///
+    /// Each affected mount is queried via `statmount(2)` and has to report `propagation` (in
+    /// addition to other types it may have, as a slave mount can still be shared). Otherwise an
+    /// error naming the first mismatching mount is returned, though the change itself is not
+    /// undone.
+    ///
+    /// The mounts below this one are enumerated via `listmount(2)`, so verification requires
+    /// kernel 6.9 and an attached mount tree, detached trees fail with `ENOENT`.
+    pub fn set_propagation_verified(
+        &self,
+        propagation: MountPropagation,
+        recursive: bool,
+    ) -> io::Result<()> {
+        self.set_propagation(propagation, recursive)?;
+
+        let root = crate::mount::unique_mount_id_at(self.as_raw_fd(), c"", libc::AT_EMPTY_PATH)?;
+        let mut ids = vec![root];
+        if recursive {
+            // `listmount(2)` yields all the descendants, not only the direct children
+            for id in ListMounts::new(root, None) {
+                ids.push(id?);
+            }
+        }
+
+        for id in ids {
+            let actual = id
+                .stat(StatMountFlags::MNT_BASIC)?
+                .propagation()
+                .ok_or_else(|| io_format_err!("statmount did not return the propagation"))?;
+            if !actual.contains(propagation) {
+                io_bail!("mount {id:?} has propagation {actual:?} instead of {propagation:?}");
+            }
+        }
+
         Ok(())
     }
 
+    /// Change attributes of the mounts in this tree selected by `predicate`.
+    ///
+    /// Contrary to `AT_RECURSIVE`, which affects every mount of the tree, this enumerates this
+    /// mount and all the mounts below it via `listmount(2)` and `statmount(2)` and applies `attr`
+    /// individually (non-recursively) to each mount for which `predicate` returns `true`. This
+    /// can be used to only change, say, the mounts of a specific file system type to read-only.
+    ///
+    /// Mounts are processed from the top down, and an error aborts the operation, leaving the
+    /// mounts processed so far changed. A mount which is entirely covered by another mount cannot
+    /// be reached by path, and results in an error if selected, before anything is changed.
+    ///
+    /// Since the kernel cannot enumerate detached trees, those are listed via a recursive copy
+    /// attached in a throwaway mount namespace on a helper thread (see
+    /// [`with_mount_namespace`](crate::ns::with_mount_namespace())), which requires
+    /// `CAP_SYS_ADMIN`. `predicate` therefore sees the copy: the mount ids differ from the ones in
+    /// the tree, and the mount points are relative to the root of the tree. The kernel also only
+    /// changes the attributes of the root mount of a detached tree, so selecting any other mount of
+    /// one is an error as well. Such a mount needs to be cloned into a tree of its own via
+    /// [`open_tree_at`](Self::open_tree_at()) first.
+    pub fn setattr_filtered<F>(&self, attr: &MountSetAttr, mut predicate: F) -> io::Result<()>
+    where
+        F: FnMut(&StatMount) -> bool,
+    {
+        let mut selected = self.list_tree()?;
+        selected.retain(|entry| predicate(&entry.stat));
+
+        for entry in &selected {
+            if entry.covered {
+                io_bail!("mount at {:?} is covered by another mount", entry.path);
+            }
+            if entry.id.is_none() && !entry.path.as_os_str().is_empty() {
+                io_bail!(
+                    "cannot change the mount at {:?} below the root of a detached tree",
+                    entry.path
+                );
+            }
+        }
+
+        for entry in selected {
+            let Some(id) = entry.id.filter(|_| !entry.path.as_os_str().is_empty()) else {
+                self.setattr(attr, 0)?;
+                continue;
+            };
+
+            let mount = Self::open_tree_at(
+                self,
+                &entry.path,
+                OpenTree::CLOEXEC,
+                libc::AT_SYMLINK_NOFOLLOW as c_uint,
+            )?;
+            let found =
+                crate::mount::unique_mount_id_at(mount.as_raw_fd(), c"", libc::AT_EMPTY_PATH)?;
+            if found != id {
+                io_bail!(
+                    "mount {id:?} at {:?} is covered by another mount",
+                    entry.path
+                );
+            }
+            mount.setattr(attr, 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// List this mount and all the mounts below it from the top down.
+    ///
+    /// Detached trees are listed via a copy attached in a new mount namespace, see
+    /// [`setattr_filtered`](Self::setattr_filtered()).
+    fn list_tree(&self) -> io::Result<Vec<TreeEntry>> {
+        let root = crate::mount::unique_mount_id_at(self.as_raw_fd(), c"", libc::AT_EMPTY_PATH)?;
+        match root.stat(StatMountFlags::MNT_BASIC) {
+            Ok(_) => return list_attached_tree(self, root, true),
+            Err(err) if crate::raw_os_error(&err) == Some(libc::ENOENT) => (),
+            Err(err) => return Err(err),
+        }
+
+        let copy = Self::open_tree_at(
+            self,
+            "",
+            OpenTree::CLOEXEC | OpenTree::CLONE | OpenTree::RECURSIVE,
+            libc::AT_EMPTY_PATH as c_uint,
+        )?;
+        crate::mount::in_new_namespace(move || {
+            copy.setattr(
+                &MountSetAttr::new().propagation(libc::MS_PRIVATE),
+                libc::AT_RECURSIVE,
+            )?;
+            copy.move_mount("/", MoveMount::empty())?;
+            let root =
+                crate::mount::unique_mount_id_at(copy.as_raw_fd(), c"", libc::AT_EMPTY_PATH)?;
+            list_attached_tree(&copy, root, false)
+        })
+    }
+
     /// Open something inside this mount point.
     ///
     /// This implies setting `RESOLVE_IN_ROOT` and using this file descriptor as root file system.
@@ -374,6 +968,63 @@ impl Mount {
         how.resolve_in_root(true).at_fd(self).open_file(path)
     }
 
+    /// Create a file inside this mount point, along with any missing parent directories, and
+    /// open it for writing.
+    ///
+    /// The parent directories are created with [`CreatePath`](crate::fs::CreatePath) using
+    /// `resolve_in_root`, and the file is then opened like with [`open_file`](Self::open_file()),
+    /// so neither step can leave the mount: `..` components are rejected, absolute paths are taken
+    /// relative to the mount's root, and already existing directories which are symlinks are
+    /// refused rather than followed. A symlink as the final component is followed, but resolved
+    /// with the mount as root file system.
+    ///
+    /// Directories are created with mode `0o777` and the file with `mode`, both subject to the
+    /// umask. An existing file is opened as is, without truncating it.
+    #[cfg(feature = "fs")]
+    pub fn create_file<P>(&self, path: &P, mode: u32) -> io::Result<std::fs::File>
+    where
+        P: ?Sized + CPath,
+    {
+        path.c_path(|path| self.create_file_do(path, mode))?
+    }
+
+    #[cfg(feature = "fs")]
+    fn create_file_do(&self, path: &CStr, mode: u32) -> io::Result<std::fs::File> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(std::ffi::OsStr::from_bytes(path.to_bytes()));
+        let path = path.strip_prefix("/").unwrap_or(path);
+        if path
+            .components()
+            .any(|component| component == std::path::Component::ParentDir)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{path:?} must not contain '..' components"),
+            ));
+        }
+        if path.file_name().is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{path:?} does not name a file"),
+            ));
+        }
+
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            crate::fs::CreatePath::new()
+                .resolve_in_root(true)
+                .create_at(self, parent)?;
+        }
+
+        self.open_file(
+            OpenHow::new_write().create(true).mode(u64::from(mode)),
+            path,
+        )
+    }
+
     /// Read the contents of a file in this mount point.
     #[cfg(feature = "open")]
     pub fn read<P>(&self, path: &P) -> io::Result<Vec<u8>>
@@ -388,6 +1039,29 @@ impl Mount {
         Ok(data)
     }
 
+    /// Read the target of a symlink inside this mount point.
+    ///
+    /// The path to the symlink is resolved with `RESOLVE_IN_ROOT` using this mount as root file
+    /// system, so intermediate symlinks cannot lead outside of it. The final component is *not*
+    /// followed, but opened via `O_PATH | O_NOFOLLOW` and read with `readlinkat(2)`.
+    ///
+    /// Note that the returned target is *not* resolved in any way and may point anywhere.
+    ///
+    /// If the path is not a symlink, this fails (`readlinkat(2)` reports `ENOENT` in this case).
+    #[cfg(feature = "open")]
+    pub fn read_link<P>(&self, path: &P) -> io::Result<PathBuf>
+    where
+        P: ?Sized + CPath,
+    {
+        let fd = self.open(
+            OpenHow::new()
+                .flags(libc::O_PATH as u64)
+                .no_final_symlink(true),
+            path,
+        )?;
+        crate::open::read_link_fd(fd.as_raw_fd())
+    }
+
     /// This is an unsafe way to force-open a subtree via `open_tree`.
     ///
     /// This first spawns a subprocess in a new slave-mount-namespace, mounts the parent mount at
@@ -403,6 +1077,18 @@ impl Mount {
     {
         path.c_path(move |path| open_subtree(self, path))?
     }
+
+    /// Duplicate the handle.
+    ///
+    /// Both handles refer to the same mount. For a detached tree this means the same anonymous
+    /// mount namespace, which stays alive until the last of them is dropped, and once one of them
+    /// was used to [`move_mount`](Self::move_mount()) the tree, the other one refers to the now
+    /// attached mount.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            fd: self.fd.try_clone()?,
+        })
+    }
 }
 
 struct Shared<'a> {
@@ -517,3 +1203,330 @@ extern "C" fn open_subtree_process(shared: *mut c_void) -> c_int {
 
     0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{TempDir, in_mount_namespace, tmpfs};
+
+    /// The `(attr_set, attr_clr)` masks of `attr`.
+    fn masks(attr: &MountSetAttr) -> (u64, u64) {
+        (attr.attr.attr_set, attr.attr.attr_clr)
+    }
+
+    #[test]
+    fn atime_masks() {
+        // `MOUNT_ATTR__ATIME` is 0x70, `MOUNT_ATTR_NOATIME` 0x10, `MOUNT_ATTR_STRICTATIME` 0x20.
+        let relatime = MountSetAttr::new().atime(AtimeMode::Relatime);
+        assert_eq!(masks(&relatime), (0x00, 0x70));
+        let noatime = MountSetAttr::new().atime(AtimeMode::Noatime);
+        assert_eq!(masks(&noatime), (0x10, 0x70));
+        let strictatime = MountSetAttr::new().atime(AtimeMode::Strictatime);
+        assert_eq!(masks(&strictatime), (0x20, 0x70));
+    }
+
+    #[test]
+    fn atime_replaces_previous_mode() {
+        let attr = MountSetAttr::new()
+            .atime(AtimeMode::Strictatime)
+            .atime(AtimeMode::Noatime);
+        assert_eq!(masks(&attr), (0x10, 0x70));
+
+        let attr = MountSetAttr::new()
+            .atime(AtimeMode::Noatime)
+            .atime(AtimeMode::Relatime);
+        assert_eq!(masks(&attr), (0x00, 0x70));
+    }
+
+    #[test]
+    fn atime_keeps_other_flags() {
+        let attr = MountSetAttr::new()
+            .set(MountAttr::RDONLY)
+            .clear(MountAttr::NOSUID)
+            .atime(AtimeMode::Noatime)
+            .nodiratime(true);
+        let rdonly = u64::from(MountAttr::RDONLY.bits());
+        let nosuid = u64::from(MountAttr::NOSUID.bits());
+        let nodiratime = u64::from(MountAttr::NODIRATIME.bits());
+        assert_eq!(masks(&attr), (rdonly | 0x10 | nodiratime, nosuid | 0x70));
+    }
+
+    #[test]
+    fn atime_round_trip() {
+        for mode in [
+            AtimeMode::Relatime,
+            AtimeMode::Noatime,
+            AtimeMode::Strictatime,
+        ] {
+            let attr = MountAttr::from_bits_retain(mode.bits() as c_uint);
+            assert_eq!(AtimeMode::from_attr(attr), Some(mode));
+            assert_eq!(AtimeMode::from_attr(MountAttr::from(mode)), Some(mode));
+            let attr = attr | MountAttr::RDONLY | MountAttr::NODIRATIME | MountAttr::NOSYMFOLLOW;
+            assert_eq!(AtimeMode::from_attr(attr), Some(mode));
+        }
+        assert_eq!(
+            AtimeMode::from_attr(MountAttr::from_bits_retain(0x30)),
+            None
+        );
+    }
+
+    #[test]
+    fn setattr_checked_sees_detached_submounts() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().to_path_buf();
+
+        in_mount_namespace(|| {
+            tmpfs().move_mount(&root, MoveMount::empty()).unwrap();
+            std::fs::create_dir(root.join("sub")).unwrap();
+            tmpfs()
+                .move_mount(&root.join("sub"), MoveMount::empty())
+                .unwrap();
+
+            let attached = Mount::open_tree(&root, OpenTree::CLOEXEC, 0).unwrap();
+            assert!(attached.has_submounts().unwrap());
+            let single = Mount::open_tree(&root, OpenTree::CLOEXEC | OpenTree::CLONE, 0).unwrap();
+            assert!(!single.has_submounts().unwrap());
+            let tree = Mount::open_tree(
+                &root,
+                OpenTree::CLOEXEC | OpenTree::CLONE | OpenTree::RECURSIVE,
+                0,
+            )
+            .unwrap();
+            assert!(tree.has_submounts().unwrap());
+
+            let userns = std::fs::File::open("/proc/self/ns/user").unwrap();
+            let err = tree
+                .setattr_checked(&MountSetAttr::new().idmap(&userns), false)
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{err}");
+            assert_eq!(crate::raw_os_error(&err), None, "{err}");
+        });
+    }
+
+    #[test]
+    fn setattr_filtered() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().to_path_buf();
+
+        in_mount_namespace(|| {
+            tmpfs().move_mount(&root, MoveMount::empty()).unwrap();
+            for dir in ["a", "a/c", "b"] {
+                std::fs::create_dir(root.join(dir)).unwrap();
+                tmpfs()
+                    .move_mount(&root.join(dir), MoveMount::empty())
+                    .unwrap();
+            }
+            let writable = |path: &Path| match std::fs::write(path.join("file"), b"data") {
+                Ok(()) => true,
+                Err(err) if err.raw_os_error() == Some(libc::EROFS) => false,
+                Err(err) => panic!("failed to write to {path:?}: {err}"),
+            };
+            let ends_with = |name: &'static str| {
+                move |stat: &StatMount| {
+                    stat.mount_point()
+                        .is_some_and(|point| point.to_bytes().ends_with(name.as_bytes()))
+                }
+            };
+            let rdonly = MountSetAttr::new().set(MountAttr::RDONLY);
+
+            let attached = Mount::open_tree(&root, OpenTree::CLOEXEC, 0).unwrap();
+            attached.setattr_filtered(&rdonly, ends_with("/a")).unwrap();
+            assert!(writable(&root));
+            assert!(!writable(&root.join("a")));
+            assert!(writable(&root.join("a/c")));
+            assert!(writable(&root.join("b")));
+
+            let tree = Mount::open_tree(
+                &root,
+                OpenTree::CLOEXEC | OpenTree::CLONE | OpenTree::RECURSIVE,
+                0,
+            )
+            .unwrap();
+            let err = tree.setattr_filtered(&rdonly, |_| true).unwrap_err();
+            assert!(err.to_string().contains("detached tree"), "{err}");
+            let tree_root = PathBuf::from(format!("/proc/self/fd/{}", tree.as_raw_fd()));
+            assert!(writable(&tree_root));
+
+            tree.setattr_filtered(&rdonly, ends_with("/")).unwrap();
+            assert!(!writable(&tree_root));
+            assert!(writable(&tree_root.join("b")));
+        });
+    }
+
+    #[test]
+    fn bind_file() {
+        let tmp = TempDir::new().unwrap();
+        let source = tmp.path().join("source");
+        let target = tmp.path().join("target");
+        std::fs::write(&source, b"data").unwrap();
+        std::fs::write(&target, b"").unwrap();
+
+        in_mount_namespace(|| {
+            match Mount::bind_file(tmp.path()) {
+                Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{err}"),
+                Ok(_) => panic!("bind_file() accepted a directory"),
+            }
+
+            Mount::bind_file(&source)
+                .unwrap()
+                .move_mount(&target, MoveMount::empty())
+                .unwrap();
+            assert_eq!(std::fs::read(&target).unwrap(), b"data");
+            std::fs::write(&target, b"changed").unwrap();
+            assert_eq!(std::fs::read(&source).unwrap(), b"changed");
+        });
+    }
+
+    #[test]
+    fn set_sharing_group() {
+        let tmp = TempDir::new().unwrap();
+        let a = tmp.path().join("a");
+        let b = tmp.path().join("b");
+        std::fs::create_dir(&a).unwrap();
+        std::fs::create_dir(&b).unwrap();
+
+        in_mount_namespace(|| {
+            tmpfs().move_mount(&a, MoveMount::empty()).unwrap();
+            Mount::open_tree(&a, OpenTree::CLOEXEC | OpenTree::CLONE, 0)
+                .unwrap()
+                .move_mount(&b, MoveMount::empty())
+                .unwrap();
+            let from = Mount::open_tree(&a, OpenTree::CLOEXEC, 0).unwrap();
+            let to = Mount::open_tree(&b, OpenTree::CLOEXEC, 0).unwrap();
+            let stat = |mount: &Mount| {
+                crate::mount::unique_mount_id_at(mount.as_raw_fd(), c"", libc::AT_EMPTY_PATH)
+                    .unwrap()
+                    .stat(StatMountFlags::MNT_BASIC)
+                    .unwrap()
+            };
+
+            // `from` must not be private
+            let err = from.set_sharing_group(&to).unwrap_err();
+            assert_eq!(crate::raw_os_error(&err), Some(libc::EINVAL), "{err}");
+
+            from.set_propagation(MountPropagation::SHARED, false)
+                .unwrap();
+            from.set_sharing_group(&to).unwrap();
+            let group = stat(&from).peer_group_id().unwrap();
+            assert_ne!(group, 0);
+            assert_eq!(stat(&to).peer_group_id(), Some(group));
+
+            // `to` must not be shared already
+            let err = from.set_sharing_group(&to).unwrap_err();
+            assert_eq!(crate::raw_os_error(&err), Some(libc::EINVAL), "{err}");
+        });
+    }
+
+    #[test]
+    fn try_clone() {
+        let tmp = TempDir::new().unwrap();
+        let target = tmp.path().to_path_buf();
+
+        let root = Mount::current_root().unwrap();
+        let clone = root.try_clone().unwrap();
+        assert_ne!(root.as_raw_fd(), clone.as_raw_fd());
+        let id = |mount: &Mount| {
+            crate::mount::unique_mount_id_at(mount.as_raw_fd(), c"", libc::AT_EMPTY_PATH).unwrap()
+        };
+        assert_eq!(id(&root), id(&clone));
+
+        in_mount_namespace(|| {
+            let mount = tmpfs();
+            let clone = mount.try_clone().unwrap();
+            mount.move_mount(&target, MoveMount::empty()).unwrap();
+
+            // the clone refers to the now attached mount
+            let path = format!("/proc/self/fd/{}/file", clone.as_raw_fd());
+            std::fs::write(path, b"data").unwrap();
+            assert_eq!(std::fs::read(target.join("file")).unwrap(), b"data");
+            assert_eq!(id(&mount), id(&clone));
+        });
+    }
+
+    #[test]
+    fn set_propagation_verified() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().to_path_buf();
+
+        in_mount_namespace(|| {
+            tmpfs().move_mount(&root, MoveMount::empty()).unwrap();
+            for dir in ["a", "a/b", "c"] {
+                std::fs::create_dir(root.join(dir)).unwrap();
+                tmpfs()
+                    .move_mount(&root.join(dir), MoveMount::empty())
+                    .unwrap();
+            }
+            let mount = Mount::open_tree(&root, OpenTree::CLOEXEC, 0).unwrap();
+
+            mount
+                .set_propagation_verified(MountPropagation::SHARED, true)
+                .unwrap();
+            let b = Mount::open_tree(&root.join("a/b"), OpenTree::CLOEXEC, 0).unwrap();
+            let id = crate::mount::unique_mount_id_at(b.as_raw_fd(), c"", libc::AT_EMPTY_PATH);
+            let stat = id.unwrap().stat(StatMountFlags::MNT_BASIC).unwrap();
+            assert!(
+                stat.propagation()
+                    .unwrap()
+                    .contains(MountPropagation::SHARED)
+            );
+
+            // without a master, the mounts become private rather than slaves
+            mount
+                .set_propagation(MountPropagation::PRIVATE, true)
+                .unwrap();
+            let err = mount
+                .set_propagation_verified(MountPropagation::SLAVE, true)
+                .unwrap_err();
+            assert!(err.to_string().contains("instead of"), "{err}");
+        });
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn create_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        std::fs::create_dir(root.join("dir")).unwrap();
+        std::os::unix::fs::symlink("/", root.join("link")).unwrap();
+        let mount = Mount::open_tree(root, OpenTree::CLOEXEC, 0).unwrap();
+
+        let file = mount.create_file("/a/b/file", 0o600).unwrap();
+        assert!(file.metadata().unwrap().is_file());
+        let meta = std::fs::metadata(root.join("a/b/file")).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o077, 0);
+        mount.create_file("dir/file", 0o644).unwrap();
+        assert!(root.join("dir/file").is_file());
+
+        for path in ["dir/../file", "../file", "dir/..", "/"] {
+            let err = mount.create_file(path, 0o644).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{path}: {err}");
+        }
+        // existing symlinks are not followed for the parent directories
+        mount.create_file("link/file", 0o644).unwrap_err();
+        assert!(!root.join("file").exists());
+    }
+
+    #[test]
+    fn move_mount_to_fd() {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let tmp = TempDir::new().unwrap();
+        let target = tmp.path().to_path_buf();
+        std::fs::write(target.join("file"), b"data").unwrap();
+
+        in_mount_namespace(|| {
+            let dir = std::fs::OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_PATH | libc::O_DIRECTORY)
+                .open(&target)
+                .unwrap();
+            tmpfs().move_mount_to_fd(&dir, MoveMount::empty()).unwrap();
+
+            // the new tmpfs now hides the directory's contents
+            assert!(!target.join("file").exists());
+            assert!(std::fs::read_dir(&target).unwrap().next().is_none());
+        });
+    }
+}
@@ -0,0 +1,236 @@
+//! Parser for the legacy `/proc/<pid>/mountinfo` format.
+//!
+//! On kernels without `statmount(2)` and `listmount(2)` this is the only way to enumerate mounts.
+
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+
+use crate::error::io_format_err;
+use crate::mount_types::ReusedMountId;
+use crate::types::Device;
+
+/// A line of a `/proc/<pid>/mountinfo` file.
+///
+/// All the strings have their octal escapes (such as `\040` for spaces) decoded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MountInfoEntry {
+    /// The mount id. Note that these are [`ReusedMountId`]s.
+    pub id: ReusedMountId,
+
+    /// The parent's mount id.
+    pub parent_id: ReusedMountId,
+
+    /// The device of the superblock.
+    pub device: Device,
+
+    /// The root of the mount relative to the root of the file system.
+    pub root: PathBuf,
+
+    /// The mount point relative to the process' root.
+    pub mount_point: PathBuf,
+
+    /// The per-mount options.
+    pub mount_options: OsString,
+
+    /// The optional fields, such as `shared:1` or `master:2`.
+    pub optional_fields: Vec<OsString>,
+
+    /// The file system type, possibly with a subtype as in `fuse.sshfs`.
+    pub fs_type: OsString,
+
+    /// The mount source.
+    pub source: OsString,
+
+    /// The per-superblock options.
+    pub super_options: OsString,
+}
+
+impl MountInfoEntry {
+    /// Parse a single line of a `mountinfo` file.
+    pub fn parse_line(line: &[u8]) -> io::Result<Self> {
+        let mut parts = line.split(|&b| b == b' ');
+        let mut next = |what: &str| {
+            parts
+                .next()
+                .ok_or_else(|| io_format_err!("missing {what} in mountinfo line"))
+        };
+
+        let id = parse_num(next("mount id")?, "mount id")?;
+        let parent_id = parse_num(next("parent id")?, "parent id")?;
+        let device = next("device")?;
+        let root = unescape(next("root")?)?;
+        let mount_point = unescape(next("mount point")?)?;
+        let mount_options = unescape(next("mount options")?)?;
+
+        let mut optional_fields = Vec::new();
+        loop {
+            match next("separator")? {
+                b"-" => break,
+                field => optional_fields.push(unescape(field)?),
+            }
+        }
+
+        let fs_type = unescape(next("file system type")?)?;
+        let source = unescape(next("mount source")?)?;
+        let super_options = unescape(next("super options")?)?;
+
+        let (major, minor) = device
+            .iter()
+            .position(|&b| b == b':')
+            .map(|pos| (&device[..pos], &device[(pos + 1)..]))
+            .ok_or_else(|| io_format_err!("invalid device in mountinfo line"))?;
+
+        Ok(Self {
+            id: ReusedMountId::from_raw(id),
+            parent_id: ReusedMountId::from_raw(parent_id),
+            device: Device {
+                major: parse_num(major, "device major")?,
+                minor: parse_num(minor, "device minor")?,
+            },
+            root: PathBuf::from(root),
+            mount_point: PathBuf::from(mount_point),
+            mount_options,
+            optional_fields,
+            fs_type,
+            source,
+            super_options,
+        })
+    }
+}
+
+/// Parse the contents of a `mountinfo` file.
+pub fn parse(data: &[u8]) -> io::Result<Vec<MountInfoEntry>> {
+    data.split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(MountInfoEntry::parse_line)
+        .collect()
+}
+
+fn parse_num(num: &[u8], what: &str) -> io::Result<u32> {
+    std::str::from_utf8(num)
+        .ok()
+        .and_then(|num| num.parse().ok())
+        .ok_or_else(|| io_format_err!("invalid {what} in mountinfo line"))
+}
+
+/// Decode the octal escapes (`\ooo`) used for whitespace and backslashes.
+fn unescape(data: &[u8]) -> io::Result<OsString> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] != b'\\' {
+            out.push(data[i]);
+            i += 1;
+            continue;
+        }
+
+        let value = data
+            .get((i + 1)..(i + 4))
+            .filter(|digits| digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+            .map(|digits| {
+                digits
+                    .iter()
+                    .fold(0u32, |value, d| (value << 3) | u32::from(d - b'0'))
+            })
+            .and_then(|value| u8::try_from(value).ok())
+            .ok_or_else(|| io_format_err!("invalid escape sequence in mountinfo line"))?;
+        out.push(value);
+        i += 4;
+    }
+    Ok(OsString::from_vec(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_plain_line() {
+        let entry = MountInfoEntry::parse_line(
+            b"36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue",
+        )
+        .unwrap();
+        assert_eq!(
+            entry,
+            MountInfoEntry {
+                id: ReusedMountId::from_raw(36),
+                parent_id: ReusedMountId::from_raw(35),
+                device: Device {
+                    major: 98,
+                    minor: 0
+                },
+                root: PathBuf::from("/mnt1"),
+                mount_point: PathBuf::from("/mnt2"),
+                mount_options: "rw,noatime".into(),
+                optional_fields: vec!["master:1".into()],
+                fs_type: "ext3".into(),
+                source: "/dev/root".into(),
+                super_options: "rw,errors=continue".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_optional_fields() {
+        let entry =
+            MountInfoEntry::parse_line(b"24 22 0:22 / /proc rw,nosuid,nodev,noexec - proc proc rw")
+                .unwrap();
+        assert!(entry.optional_fields.is_empty());
+        assert_eq!(entry.fs_type, "proc");
+
+        let entry = MountInfoEntry::parse_line(
+            b"445 28 0:45 / /run/user/1000/doc rw,nosuid,nodev,relatime shared:243 master:12 \
+              - fuse.portal portal rw,user_id=1000,group_id=1000",
+        )
+        .unwrap();
+        assert_eq!(entry.optional_fields, ["shared:243", "master:12"]);
+        assert_eq!(entry.fs_type, "fuse.portal");
+        assert_eq!(entry.source, "portal");
+    }
+
+    #[test]
+    fn parse_escapes() {
+        let entry = MountInfoEntry::parse_line(
+            b"97 22 0:50 /a\\011b /mnt/with\\040space rw,relatime shared:55 - tmpfs my\\134src rw",
+        )
+        .unwrap();
+        assert_eq!(entry.root, PathBuf::from("/a\tb"));
+        assert_eq!(entry.mount_point, PathBuf::from("/mnt/with space"));
+        assert_eq!(entry.source, "my\\src");
+    }
+
+    #[test]
+    fn parse_file() {
+        let data = b"22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+                     24 22 0:22 / /proc rw,nosuid,nodev,noexec,relatime shared:12 - proc proc rw\n";
+        let entries = parse(data).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].mount_point, PathBuf::from("/"));
+        assert_eq!(entries[1].parent_id, entries[0].id);
+        assert_eq!(entries[0].device, Device { major: 8, minor: 1 });
+    }
+
+    #[test]
+    fn reject_malformed_lines() {
+        for line in [
+            &b""[..],
+            b"36 35 98:0 /mnt1 /mnt2 rw,noatime",
+            b"36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 ext3 /dev/root rw",
+            b"36 35 98:0 /mnt1 /mnt2 rw,noatime - ext3 /dev/root",
+            b"x 35 98:0 /mnt1 /mnt2 rw - ext3 /dev/root rw",
+            b"36 35 98-0 /mnt1 /mnt2 rw - ext3 /dev/root rw",
+            b"36 35 98: /mnt1 /mnt2 rw - ext3 /dev/root rw",
+            b"36 35 98:0 /mnt\\04 /mnt2 rw - ext3 /dev/root rw",
+            b"36 35 98:0 /mnt\\400 /mnt2 rw - ext3 /dev/root rw",
+            b"36 35 98:0 /mnt\\08x /mnt2 rw - ext3 /dev/root rw",
+        ] {
+            assert!(
+                MountInfoEntry::parse_line(line).is_err(),
+                "accepted {:?}",
+                String::from_utf8_lossy(line),
+            );
+        }
+    }
+}
@@ -50,7 +50,7 @@ impl MountNsInfo {
     /// Retrieve the mount information for a raw file descriptor.
     pub fn get_raw(fd: RawFd) -> io::Result<Self> {
         let (info, rc) = Self::info_ioctl(fd, NS_MNT_GET_INFO)?;
-        io_assert!(rc == 0);
+        io_assert!(rc == 0, "ioctl");
         Ok(info)
     }
 
@@ -64,7 +64,7 @@ impl MountNsInfo {
     /// Get the next mount namespace information and a file descriptor for it.
     pub fn next_raw(fd: RawFd) -> io::Result<(Self, NsFd<Mnt>)> {
         let (info, rc) = Self::info_ioctl(fd, NS_MNT_GET_NEXT)?;
-        io_assert!(rc >= 0);
+        io_assert!(rc >= 0, "ioctl");
         let fd = unsafe { NsFd::from_raw_fd(rc) };
         Ok((info, fd))
     }
@@ -79,7 +79,7 @@ impl MountNsInfo {
     /// Get the previous mount namespace information and a file descriptor for it.
     pub fn previous_raw(fd: RawFd) -> io::Result<(Self, NsFd<Mnt>)> {
         let (info, rc) = Self::info_ioctl(fd, NS_MNT_GET_PREV)?;
-        io_assert!(rc >= 0);
+        io_assert!(rc >= 0, "ioctl");
         let fd = unsafe { NsFd::from_raw_fd(rc) };
         Ok((info, fd))
     }
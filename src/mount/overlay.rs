@@ -0,0 +1,199 @@
+//! Setting up overlay file systems.
+
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use crate::mount::{Fs, FsMount, FsOpen, Mount, MountAttr};
+
+/// A builder for an `overlay` file system mount.
+///
+/// Overlayfs takes its layers as path *strings*, with multiple lower layers separated by colons,
+/// so paths containing colons or backslashes need to be escaped. This builder takes care of that.
+///
+/// ``` rust, no_run
+/// # fn code() -> std::io::Result<()> {
+/// use std::path::Path;
+///
+/// use lisy::mount::{MoveMount, OverlayBuilder};
+///
+/// let mount = OverlayBuilder::new()
+///     .lower_dirs(&[Path::new("/images/app"), Path::new("/images/base")])
+///     .upper_dir("/ct/upper")
+///     .work_dir("/ct/work")
+///     .build()?;
+/// mount.move_mount("/ct/rootfs", MoveMount::empty())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct OverlayBuilder {
+    lower: Vec<PathBuf>,
+    upper: Option<PathBuf>,
+    work: Option<PathBuf>,
+    attr: Option<MountAttr>,
+}
+
+impl OverlayBuilder {
+    /// Start an empty overlay configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the lower (read-only) layers, the topmost first.
+    pub fn lower_dirs(mut self, dirs: &[&Path]) -> Self {
+        self.lower = dirs.iter().map(|dir| dir.to_path_buf()).collect();
+        self
+    }
+
+    /// Set the upper layer, receiving all modifications.
+    ///
+    /// Without an upper layer the overlay is read-only. The upper layer requires a
+    /// [`work_dir`](Self::work_dir()) on the same file system.
+    pub fn upper_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.upper = Some(dir.into());
+        self
+    }
+
+    /// Set the work directory, which must be an empty directory on the same file system as the
+    /// upper layer.
+    pub fn work_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.work = Some(dir.into());
+        self
+    }
+
+    /// Set the mount attributes of the resulting mount.
+    pub fn mount_attr(mut self, attr: MountAttr) -> Self {
+        self.attr = Some(attr);
+        self
+    }
+
+    /// Create the overlay file system and return it as a detached mount.
+    ///
+    /// Missing lower layers, or only one of the upper and work directories being set, are
+    /// rejected with an [`InvalidInput`](io::ErrorKind::InvalidInput) error before the kernel is
+    /// involved. Anything else, such as the upper and work directories residing on different file
+    /// systems, is reported by the kernel, in which case the error message contains the kernel's
+    /// explanation.
+    pub fn build(&self) -> io::Result<Mount> {
+        if self.lower.is_empty() {
+            return Err(invalid_input(
+                "overlay requires at least one lower directory",
+            ));
+        }
+        if self.upper.is_some() != self.work.is_some() {
+            return Err(invalid_input(
+                "overlay upper and work directories must be used together",
+            ));
+        }
+
+        let lower = join_lower(&self.lower);
+        let fs = Fs::open("overlay", FsOpen::CLOEXEC)?;
+        fs.configure(|txn| {
+            txn.set_string("source", "overlay");
+            txn.set_string("lowerdir", &lower);
+            if let (Some(upper), Some(work)) = (&self.upper, &self.work) {
+                txn.set_string("upperdir", escape(upper));
+                txn.set_string("workdir", escape(work));
+            }
+        })?;
+        fs.create()?
+            .mount(FsMount::CLOEXEC, self.attr.unwrap_or(MountAttr::empty()))
+    }
+}
+
+fn invalid_input(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Join the lower directories into the colon separated `lowerdir` option, topmost first.
+fn join_lower(dirs: &[PathBuf]) -> OsString {
+    let mut lower = OsString::new();
+    for (i, dir) in dirs.iter().enumerate() {
+        if i != 0 {
+            lower.push(":");
+        }
+        lower.push(escape(dir));
+    }
+    lower
+}
+
+/// Escape the characters overlayfs treats specially in layer paths with a backslash.
+fn escape(path: &Path) -> OsString {
+    let mut out = Vec::with_capacity(path.as_os_str().len());
+    for &b in path.as_os_str().as_bytes() {
+        if matches!(b, b':' | b'\\') {
+            out.push(b'\\');
+        }
+        out.push(b);
+    }
+    OsString::from_vec(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{TempDir, in_mount_namespace};
+
+    #[test]
+    fn escape_special_characters() {
+        assert_eq!(escape(Path::new("/plain/path")), "/plain/path");
+        assert_eq!(escape(Path::new("/a:b")), "/a\\:b");
+        assert_eq!(escape(Path::new("/a\\b")), "/a\\\\b");
+        assert_eq!(escape(Path::new("/a\\:b::")), "/a\\\\\\:b\\:\\:");
+    }
+
+    #[test]
+    fn join_lower_dirs() {
+        assert_eq!(join_lower(&[PathBuf::from("/a")]), "/a");
+        assert_eq!(
+            join_lower(&[
+                PathBuf::from("/top:most"),
+                PathBuf::from("/mid\\dle"),
+                PathBuf::from("/base"),
+            ]),
+            "/top\\:most:/mid\\\\dle:/base"
+        );
+    }
+
+    #[test]
+    fn mount_with_special_characters() {
+        let tmp = TempDir::new().unwrap();
+        let dir = |name: &str| {
+            let path = tmp.path().join(name);
+            std::fs::create_dir(&path).unwrap();
+            path
+        };
+        let top = dir("low:er\\top");
+        let base = dir("lower:base");
+        let upper = dir("up:per\\");
+        let work = dir("wo\\rk:");
+        let target = dir("target");
+        std::fs::write(top.join("top"), b"").unwrap();
+        std::fs::write(base.join("base"), b"").unwrap();
+
+        in_mount_namespace(|| {
+            let mount = match OverlayBuilder::new()
+                .lower_dirs(&[&top, &base])
+                .upper_dir(&upper)
+                .work_dir(&work)
+                .build()
+            {
+                Ok(mount) => mount,
+                Err(err) if crate::raw_os_error(&err) == Some(libc::ENODEV) => {
+                    eprintln!("skipping test, overlayfs is not available: {err}");
+                    return;
+                }
+                Err(err) => panic!("failed to create overlay: {err}"),
+            };
+            mount
+                .move_mount(&target, crate::mount::MoveMount::empty())
+                .unwrap();
+            assert!(target.join("top").exists());
+            assert!(target.join("base").exists());
+            std::fs::write(target.join("new"), b"").unwrap();
+            assert!(upper.join("new").exists());
+        });
+    }
+}
@@ -0,0 +1,213 @@
+//! The mount propagation relationships of a mount namespace.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::mount::sys::MountPropagation;
+use crate::mount::{ListMounts, MountId, MountNsId, StatMount};
+
+/// The propagation state of a single mount.
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    propagation: MountPropagation,
+    peer_group: u64,
+    master_group: u64,
+    propagate_from: u64,
+}
+
+/// A snapshot of how mount events propagate between the mounts of a namespace, as shown by the
+/// `shared:N`, `master:N` and `propagate_from:N` fields of `/proc/self/mountinfo`.
+///
+/// Shared mounts are grouped into *peer groups*, and events on any member of a group propagate to
+/// all other members. A slave mount has a *master* peer group it receives events from, without
+/// propagating anything back. A mount can be both, in which case it receives events from its
+/// master and shares them with its own peers.
+///
+/// Built by [`propagation_graph`].
+#[derive(Clone, Debug, Default)]
+pub struct PropagationGraph {
+    nodes: BTreeMap<MountId, Node>,
+    peer_groups: BTreeMap<u64, Vec<MountId>>,
+}
+
+/// Build the [`PropagationGraph`] of the mount namespace `ns`, or the current one.
+///
+/// This lists all mounts via `listmount(2)` and queries each one's propagation state via
+/// `statmount(2)`, so it requires kernel 6.9 (6.10 for other namespaces). Mounts vanishing while
+/// the graph is built are skipped.
+pub fn propagation_graph(ns: Option<MountNsId>) -> io::Result<PropagationGraph> {
+    let mut graph = PropagationGraph::default();
+
+    for id in ListMounts::new(MountId::root(), ns) {
+        let id = id?;
+        let mut builder = StatMount::builder()
+            .basic_mount_info(true)
+            .propagate_from(true)
+            .mount_id(id);
+        if let Some(ns) = ns {
+            builder = builder.mount_namespace(ns);
+        }
+        let stat = match builder.stat() {
+            Ok(stat) => stat,
+            Err(err) if crate::raw_os_error(&err) == Some(libc::ENOENT) => continue,
+            Err(err) => return Err(err),
+        };
+
+        let node = Node {
+            propagation: stat.propagation().unwrap_or_else(MountPropagation::empty),
+            peer_group: stat.peer_group_id().unwrap_or(0),
+            master_group: stat.master_group_id().unwrap_or(0),
+            propagate_from: stat.propagate_from().unwrap_or(0),
+        };
+        if node.peer_group != 0 {
+            graph
+                .peer_groups
+                .entry(node.peer_group)
+                .or_default()
+                .push(id);
+        }
+        graph.nodes.insert(id, node);
+    }
+
+    Ok(graph)
+}
+
+impl PropagationGraph {
+    /// Iterate over all the mounts in the graph.
+    pub fn mounts(&self) -> impl Iterator<Item = MountId> + '_ {
+        self.nodes.keys().copied()
+    }
+
+    /// Get the propagation type of a mount, or `None` if it is not part of the graph.
+    pub fn propagation(&self, id: MountId) -> Option<MountPropagation> {
+        Some(self.nodes.get(&id)?.propagation)
+    }
+
+    /// Get the id of the peer group a mount is a member of, if it is shared.
+    pub fn peer_group(&self, id: MountId) -> Option<u64> {
+        self.nodes
+            .get(&id)
+            .map(|node| node.peer_group)
+            .filter(|&g| g != 0)
+    }
+
+    /// Get the id of the peer group a mount receives events from, if it is a slave.
+    pub fn master_group(&self, id: MountId) -> Option<u64> {
+        self.nodes
+            .get(&id)
+            .map(|node| node.master_group)
+            .filter(|&g| g != 0)
+    }
+
+    /// Get the nearest peer group in this namespace a slave mount receives events from, which may
+    /// differ from its [`master_group`](Self::master_group()) if the master is not visible.
+    pub fn propagate_from(&self, id: MountId) -> Option<u64> {
+        self.nodes
+            .get(&id)
+            .map(|node| node.propagate_from)
+            .filter(|&g| g != 0)
+    }
+
+    /// Get the members of a peer group.
+    pub fn group_members(&self, group: u64) -> &[MountId] {
+        self.peer_groups.get(&group).map_or(&[], Vec::as_slice)
+    }
+
+    /// Get the other mounts in the same peer group as `id`.
+    pub fn peers_of(&self, id: MountId) -> Vec<MountId> {
+        self.peer_group(id)
+            .map(|group| {
+                self.group_members(group)
+                    .iter()
+                    .copied()
+                    .filter(|&peer| peer != id)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Get the mounts `id` receives propagation from, that is, the members of its master group.
+    ///
+    /// This is empty if `id` is not a slave, or if the master group has no members in this
+    /// namespace.
+    pub fn masters_of(&self, id: MountId) -> Vec<MountId> {
+        self.master_group(id)
+            .map(|group| self.group_members(group).to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Get the mounts receiving propagation from `id` as slaves, that is, the mounts whose master
+    /// group is the peer group of `id`.
+    pub fn slaves_of(&self, id: MountId) -> Vec<MountId> {
+        let Some(group) = self.peer_group(id) else {
+            return Vec::new();
+        };
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.master_group == group)
+            .map(|(&id, _)| id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mount::{Mount, MoveMount, OpenTree};
+    use crate::test_util::{TempDir, in_mount_namespace, tmpfs};
+
+    #[test]
+    fn shared_and_slave() {
+        let tmp = TempDir::new().unwrap();
+        let [a, b, c] = ["a", "b", "c"].map(|name| tmp.path().join(name));
+        for dir in [&a, &b, &c] {
+            std::fs::create_dir(dir).unwrap();
+        }
+
+        in_mount_namespace(|| {
+            let mount = tmpfs();
+            mount.move_mount(&a, MoveMount::empty()).unwrap();
+            mount
+                .set_propagation(MountPropagation::SHARED, false)
+                .unwrap();
+            for dest in [&b, &c] {
+                Mount::open_tree(&a, OpenTree::CLOEXEC | OpenTree::CLONE, 0)
+                    .unwrap()
+                    .move_mount(dest, MoveMount::empty())
+                    .unwrap();
+            }
+            let slave = Mount::open_tree(&c, OpenTree::CLOEXEC, 0).unwrap();
+            slave
+                .set_propagation(MountPropagation::SLAVE, false)
+                .unwrap();
+
+            let id = |path: &std::path::Path| {
+                let path = std::ffi::CString::new(path.as_os_str().as_encoded_bytes()).unwrap();
+                crate::mount::unique_mount_id_at(libc::AT_FDCWD, &path, 0).unwrap()
+            };
+            let (a, b, c) = (id(&a), id(&b), id(&c));
+
+            let graph = propagation_graph(None).unwrap();
+            let group = graph.peer_group(a).unwrap();
+            assert_eq!(graph.peer_group(b), Some(group));
+            assert_eq!(graph.peers_of(a), [b]);
+            assert_eq!(graph.peer_group(c), None);
+            assert_eq!(graph.master_group(c), Some(group));
+            assert!(
+                graph
+                    .propagation(c)
+                    .unwrap()
+                    .contains(MountPropagation::SLAVE)
+            );
+
+            let mut masters = graph.masters_of(c);
+            masters.sort();
+            let mut expected = vec![a, b];
+            expected.sort();
+            assert_eq!(masters, expected);
+            assert_eq!(graph.slaves_of(a), [c]);
+            assert_eq!(graph.slaves_of(b), [c]);
+            assert!(graph.slaves_of(c).is_empty());
+        });
+    }
+}
@@ -1,33 +1,40 @@
 //! Superblock instances creates from file systems via `Fs::create`.
 
 use std::convert::TryFrom;
-use std::ffi::{CStr, OsStr};
+use std::ffi::{CStr, OsStr, OsString};
+use std::fmt;
 use std::io;
 use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
 use std::os::raw::{c_int, c_long, c_uint};
 use std::os::unix::ffi::OsStrExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::ptr;
 
 use bitflags::bitflags;
 
 use crate::CPath;
 use crate::c_path::io_c_string;
-use crate::error::io_assert;
+use crate::error::{io_assert, io_bail};
 use crate::mount::{Mount, sys};
 
 pub use super::sys::MountAttr;
 
 bitflags! {
     /// Mount flags for `Superblock::mount`.
+    ///
+    /// Note that `FsMount::default()` includes `CLOEXEC`, while `FsMount::empty()` does not.
     pub struct FsMount: c_uint {
         /// Set the close-on-exec flag on the file descriptor.
         const CLOEXEC = 0x0000_0001;
     }
 }
 
+crate::mount::cloexec_flags!(FsMount);
+
 bitflags! {
     /// Flags for `Superblock::fspick`.
+    ///
+    /// Note that `FsPick::default()` includes `CLOEXEC`, while `FsPick::empty()` does not.
     pub struct FsPick: c_uint {
         /// Set the close-on-exec flag on the file descriptor.
         const CLOEXEC          = 0x0000_0001;
@@ -43,6 +50,8 @@ bitflags! {
     }
 }
 
+crate::mount::cloexec_flags!(FsPick);
+
 /// Handle to a file system superblock.
 ///
 /// This is a configured, mountable superblock, created either via `Fs::create` to create a new
@@ -54,7 +63,7 @@ pub struct Superblock {
 
 impl AsFd for Superblock {
     fn as_fd(&self) -> BorrowedFd<'_> {
-        unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
+        self.sb_ref.as_fd()
     }
 }
 
@@ -80,6 +89,12 @@ impl FromRawFd for Superblock {
     }
 }
 
+impl From<Superblock> for OwnedFd {
+    fn from(this: Superblock) -> OwnedFd {
+        this.sb_ref.into()
+    }
+}
+
 impl std::ops::Deref for Superblock {
     type Target = SuperblockRef;
 
@@ -117,23 +132,36 @@ impl Superblock {
     pub fn fspick_at_raw(dfd: RawFd, path: &CStr, fspick: FsPick) -> io::Result<Self> {
         let dfd = dfd.as_raw_fd();
         let rc = unsafe { libc::syscall(sys::SYS_fspick, dfd, path.as_ptr(), fspick.bits()) };
-        io_assert!(rc >= 0);
+        io_assert!(rc >= 0, "fspick");
         Ok(Self {
             sb_ref: unsafe { SuperblockRef::from_raw_fd(rc as RawFd) },
         })
     }
 
+    /// Pick the file system superblock of an open file descriptor, such as a [`Mount`] or a
+    /// directory.
+    ///
+    /// This is the safe counterpart to [`fspick_fd`](Self::fspick_fd()), and like it implies
+    /// [`FsPick::EMPTY_PATH`]. The returned handle can be used to reconfigure the live file
+    /// system via [`reconfigure`](Self::reconfigure()).
+    pub fn fspick_from<F>(fd: &F, fspick: FsPick) -> io::Result<Self>
+    where
+        F: ?Sized + AsFd,
+    {
+        Self::fspick_fd(fd.as_fd().as_raw_fd(), fspick)
+    }
+
     /// Pick a file system superblock directly from an already open file descriptor.
     pub fn fspick_fd(dfd: RawFd, fspick: FsPick) -> io::Result<Self> {
         let rc = unsafe {
             libc::syscall(
                 sys::SYS_fspick,
                 dfd,
-                c"",
+                c"".as_ptr(),
                 fspick.bits() | FsPick::EMPTY_PATH.bits(),
             )
         };
-        io_assert!(rc >= 0);
+        io_assert!(rc >= 0, "fspick");
         Ok(Self {
             sb_ref: unsafe { SuperblockRef::from_raw_fd(rc as RawFd) },
         })
@@ -151,11 +179,17 @@ impl Superblock {
                 mount_attr.bits(),
             )
         };
-        io_assert!(rc >= 0);
+        io_assert!(rc >= 0, "fsmount");
         let fd = unsafe { OwnedFd::from_raw_fd(rc as RawFd) };
         Ok(Mount { fd })
     }
 
+    /// Close the superblock handle, reporting errors from `close(2)` which dropping it would
+    /// ignore.
+    pub fn close(self) -> io::Result<()> {
+        crate::mount::close_fd(self.sb_ref.fd)
+    }
+
     /// Reconfigure an existing superblock.
     pub fn reconfigure(&mut self) -> io::Result<()> {
         let rc = unsafe {
@@ -167,9 +201,18 @@ impl Superblock {
                 0,
             )
         };
-        io_assert!(rc == 0);
+        io_assert!(rc == 0, "fsconfig");
         Ok(())
     }
+
+    /// Duplicate the handle. Both refer to the same file system context.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            sb_ref: SuperblockRef {
+                fd: self.sb_ref.fd.try_clone()?,
+            },
+        })
+    }
 }
 
 #[repr(C)]
@@ -203,7 +246,7 @@ pub struct SuperblockRef {
 
 impl AsFd for SuperblockRef {
     fn as_fd(&self) -> BorrowedFd<'_> {
-        unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
+        self.fd.as_fd()
     }
 }
 
@@ -229,7 +272,17 @@ impl FromRawFd for SuperblockRef {
     }
 }
 
+impl From<SuperblockRef> for OwnedFd {
+    fn from(this: SuperblockRef) -> OwnedFd {
+        this.fd
+    }
+}
+
 impl SuperblockRef {
+    /// The maximum size of a binary value accepted by `fsconfig(2)`, see
+    /// [`set_blob`](Self::set_blob()).
+    pub const MAX_BLOB_SIZE: usize = 1024 * 1024;
+
     /// Set a flag, such as `noacl` for ext4.
     pub fn set_flag(&self, flag: &str) -> Result<(), io::Error> {
         let flag = io_c_string(flag)?;
@@ -242,7 +295,7 @@ impl SuperblockRef {
                 0,
             )
         };
-        io_assert!(rc == 0);
+        io_assert!(rc == 0, "fsconfig");
         Ok(())
     }
 
@@ -262,10 +315,90 @@ impl SuperblockRef {
                 0,
             )
         };
-        io_assert!(rc == 0);
+        io_assert!(rc == 0, "fsconfig");
+        Ok(())
+    }
+
+    /// Apply a `mount -o` style option string such as `size=1G,mode=0755,noswap`.
+    ///
+    /// Options are separated by commas, `key=value` options are passed to
+    /// [`set_string`](Self::set_string()) and everything else to [`set_flag`](Self::set_flag()).
+    /// Values containing commas can be put in double quotes, as in
+    /// `context="system_u:object_r:tmp_t:s0:c1,c2"`. The quotes are removed.
+    ///
+    /// Note that options such as `ro`, `nosuid` or `noatime` are *mount attributes* rather than
+    /// file system options in the new mount API and must be passed as [`MountAttr`] to
+    /// [`Superblock::mount`] or via [`MountSetAttr`](crate::mount::MountSetAttr) instead. These,
+    /// as well as operations such as `bind` or `remount`, are rejected with an error, before any
+    /// of the options are applied.
+    pub fn apply_mount_options(&self, opts: &str) -> Result<(), io::Error> {
+        let options = split_mount_options(opts)?;
+
+        for (key, _) in &options {
+            if let Some(hint) = misplaced_mount_option(key) {
+                io_bail!("'{key}' is not a file system option, {hint}");
+            }
+        }
+
+        for (key, value) in options {
+            match value {
+                Some(value) => self.set_string(&key, value),
+                None => self.set_flag(&key),
+            }
+            .map_err(|err| {
+                crate::error::context(err, format!("failed to apply mount option '{key}'"))
+            })?;
+        }
+
         Ok(())
     }
 
+    /// Set the SELinux `context` option, labeling all files of the mount with `ctx`.
+    ///
+    /// This is meant for file systems without extended attribute support.
+    pub fn set_selinux_context(&self, ctx: &str) -> Result<(), io::Error> {
+        self.set_string("context", ctx)
+    }
+
+    /// Set the SELinux `fscontext` option, the context of the file system (superblock) itself.
+    pub fn set_fs_context(&self, ctx: &str) -> Result<(), io::Error> {
+        self.set_string("fscontext", ctx)
+    }
+
+    /// Set the SELinux `defcontext` option, the context used for unlabeled files.
+    pub fn set_def_context(&self, ctx: &str) -> Result<(), io::Error> {
+        self.set_string("defcontext", ctx)
+    }
+
+    /// Set the SELinux `rootcontext` option, the context of the file system's root inode.
+    pub fn set_root_context(&self, ctx: &str) -> Result<(), io::Error> {
+        self.set_string("rootcontext", ctx)
+    }
+
+    /// Set the `mode` option, the permission bits of the file system's root directory.
+    ///
+    /// The value is passed in octal. This is understood by `tmpfs`, `ramfs` and `hugetlbfs`, but
+    /// not universally: `devpts`, for instance, uses `mode` for newly allocated ptys instead, and
+    /// most disk based file systems reject it.
+    pub fn set_root_mode(&self, mode: u32) -> Result<(), io::Error> {
+        self.set_string("mode", format!("{mode:o}"))
+    }
+
+    /// Set the `uid` option, the owner of the file system's root directory.
+    ///
+    /// Honored by `tmpfs` and `hugetlbfs`. Note that on `devpts` this is the owner of new ptys,
+    /// and file systems such as `vfat` use it as the owner of *all* files.
+    pub fn set_root_uid(&self, uid: libc::uid_t) -> Result<(), io::Error> {
+        self.set_string("uid", uid.to_string())
+    }
+
+    /// Set the `gid` option, the group of the file system's root directory.
+    ///
+    /// The same caveats as for [`set_root_uid`](SuperblockRef::set_root_uid) apply.
+    pub fn set_root_gid(&self, gid: libc::gid_t) -> Result<(), io::Error> {
+        self.set_string("gid", gid.to_string())
+    }
+
     /// Set a path option, like the `source` device node to mount.
     pub fn set_path_empty_at<P>(&self, key: &str, value: P, fd: RawFd) -> Result<(), io::Error>
     where
@@ -282,7 +415,7 @@ impl SuperblockRef {
                 fd,
             )
         };
-        io_assert!(rc == 0);
+        io_assert!(rc == 0, "fsconfig");
         Ok(())
     }
 
@@ -303,7 +436,7 @@ impl SuperblockRef {
                 fd,
             )
         };
-        io_assert!(rc == 0);
+        io_assert!(rc == 0, "fsconfig");
         Ok(())
     }
 
@@ -332,12 +465,29 @@ impl SuperblockRef {
                 fd,
             )
         };
-        io_assert!(rc == 0);
+        io_assert!(rc == 0, "fsconfig");
         Ok(())
     }
 
     /// Set a binary blob.
+    ///
+    /// The kernel accepts between 1 byte and [`MAX_BLOB_SIZE`](Self::MAX_BLOB_SIZE) bytes, other
+    /// sizes are rejected here with an [`InvalidInput`](io::ErrorKind::InvalidInput) error rather
+    /// than with a bare `EINVAL` from the kernel. Individual file systems may impose lower limits
+    /// for their options. There is no way to pass larger values in multiple pieces: setting the
+    /// same key again replaces the value.
     pub fn set_blob(&self, key: &str, blob: &[u8]) -> Result<(), io::Error> {
+        if blob.is_empty() || blob.len() > Self::MAX_BLOB_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "binary value for '{key}' has {} bytes, must be between 1 and {} bytes",
+                    blob.len(),
+                    Self::MAX_BLOB_SIZE,
+                ),
+            ));
+        }
+
         let key = io_c_string(key)?;
         let size = c_int::try_from(blob.len()).map_err(io::Error::other)?;
         let rc = unsafe {
@@ -349,7 +499,360 @@ impl SuperblockRef {
                 size,
             )
         };
-        io_assert!(rc == 0);
+        io_assert!(rc == 0, "fsconfig");
+        Ok(())
+    }
+}
+
+impl SuperblockRef {
+    /// Drain the messages the file system driver logged for this context.
+    ///
+    /// Drivers report details about failed `fsconfig(2)` calls this way, which are otherwise only
+    /// visible as an errno. The messages are prefixed with `e ` for errors, `w ` for warnings and
+    /// `i ` for informational messages. Reading them removes them from the context.
+    pub fn messages(&self) -> io::Result<Vec<String>> {
+        let mut messages = Vec::new();
+        let mut buf = [0u8; 1024];
+        loop {
+            let rc = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if rc < 0 {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::ENODATA) => break,
+                    Some(libc::EINTR) => continue,
+                    _ => return Err(crate::error::syscall_error("read", err)),
+                }
+            }
+            if rc == 0 {
+                break;
+            }
+            let msg = &buf[..rc as usize];
+            let msg = msg.strip_suffix(b"\n").unwrap_or(msg);
+            messages.push(String::from_utf8_lossy(msg).into_owned());
+        }
+        Ok(messages)
+    }
+
+    /// Apply a sequence of configuration operations recorded via an [`FsConfigTxn`].
+    ///
+    /// The operations are applied in order, stopping at the first failure. The error then names
+    /// the failed operation and includes the messages logged by the driver (see
+    /// [`messages`](Self::messages())). Note that `fsconfig(2)` has no way to undo changes, so the
+    /// operations before the failed one stay applied, and the context should usually be
+    /// discarded.
+    ///
+    /// ``` rust, no_run
+    /// # fn code() -> std::io::Result<()> {
+    /// use lisy::mount::{Fs, FsOpen};
+    ///
+    /// let fs = Fs::open("tmpfs", FsOpen::CLOEXEC)?;
+    /// fs.configure(|txn| {
+    ///     txn.set_string("size", "64M").set_string("mode", "0755").set_flag("noswap");
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn configure<'a, F>(&self, ops: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut FsConfigTxn<'a>),
+    {
+        let mut txn = FsConfigTxn { ops: Vec::new() };
+        ops(&mut txn);
+
+        // Don't attribute stale messages to the failing operation.
+        let _ = self.messages();
+
+        for (index, op) in txn.ops.iter().enumerate() {
+            let result = match op {
+                FsConfigOp::Flag(key) => self.set_flag(key),
+                FsConfigOp::String(key, value) => self.set_string(key, value),
+                FsConfigOp::Blob(key, value) => self.set_blob(key, value),
+                FsConfigOp::Path(key, value) => self.set_path(key, value),
+                FsConfigOp::Fd(key, fd) => self.set_fd(key, fd.as_raw_fd()),
+            };
+
+            if let Err(err) = result {
+                let mut msg = format!("fsconfig operation {} ({op}) failed", index + 1);
+                let messages = self.messages().unwrap_or_default();
+                if !messages.is_empty() {
+                    msg = format!("{msg} [{}]", messages.join("; "));
+                }
+                return Err(crate::error::context(err, msg));
+            }
+        }
+
         Ok(())
     }
 }
+
+/// A recorded `fsconfig(2)` operation.
+#[derive(Clone, Debug)]
+enum FsConfigOp<'a> {
+    Flag(String),
+    String(String, OsString),
+    Blob(String, Vec<u8>),
+    Path(String, PathBuf),
+    Fd(String, BorrowedFd<'a>),
+}
+
+impl fmt::Display for FsConfigOp<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Flag(key) => write!(f, "flag '{key}'"),
+            Self::String(key, value) => write!(f, "'{key}' = {value:?}"),
+            Self::Blob(key, value) => write!(f, "'{key}' = <{} bytes>", value.len()),
+            Self::Path(key, value) => write!(f, "'{key}' = path {value:?}"),
+            Self::Fd(key, fd) => write!(f, "'{key}' = fd {}", fd.as_raw_fd()),
+        }
+    }
+}
+
+/// A list of configuration operations to apply via [`SuperblockRef::configure`].
+///
+/// The methods mirror the setters of [`SuperblockRef`], but only record the operation.
+#[derive(Clone, Debug)]
+pub struct FsConfigTxn<'a> {
+    ops: Vec<FsConfigOp<'a>>,
+}
+
+impl<'a> FsConfigTxn<'a> {
+    /// Record a [`set_flag`](SuperblockRef::set_flag()) operation.
+    pub fn set_flag(&mut self, flag: &str) -> &mut Self {
+        self.ops.push(FsConfigOp::Flag(flag.to_string()));
+        self
+    }
+
+    /// Record a [`set_string`](SuperblockRef::set_string()) operation.
+    pub fn set_string<S>(&mut self, key: &str, value: S) -> &mut Self
+    where
+        S: AsRef<OsStr>,
+    {
+        self.ops.push(FsConfigOp::String(
+            key.to_string(),
+            value.as_ref().to_os_string(),
+        ));
+        self
+    }
+
+    /// Record a [`set_blob`](SuperblockRef::set_blob()) operation.
+    pub fn set_blob(&mut self, key: &str, blob: &[u8]) -> &mut Self {
+        self.ops
+            .push(FsConfigOp::Blob(key.to_string(), blob.to_vec()));
+        self
+    }
+
+    /// Record a [`set_path`](SuperblockRef::set_path()) operation.
+    pub fn set_path<P>(&mut self, key: &str, value: P) -> &mut Self
+    where
+        P: AsRef<Path>,
+    {
+        self.ops.push(FsConfigOp::Path(
+            key.to_string(),
+            value.as_ref().to_path_buf(),
+        ));
+        self
+    }
+
+    /// Record a [`set_fd`](SuperblockRef::set_fd()) operation. The file descriptor is borrowed
+    /// until the operations were applied.
+    pub fn set_fd(&mut self, key: &str, fd: BorrowedFd<'a>) -> &mut Self {
+        self.ops.push(FsConfigOp::Fd(key.to_string(), fd));
+        self
+    }
+}
+
+/// Split a `mount -o` style option string into keys and optional values.
+fn split_mount_options(opts: &str) -> io::Result<Vec<(String, Option<String>)>> {
+    let mut options = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in opts.chars().chain(std::iter::once(',')) {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                if !current.is_empty() {
+                    options.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if in_quotes {
+        io_bail!("unterminated quote in mount options");
+    }
+
+    Ok(options
+        .into_iter()
+        .map(|option| match option.split_once('=') {
+            Some((key, value)) => (key.to_string(), Some(value.to_string())),
+            None => (option, None),
+        })
+        .collect())
+}
+
+/// If `key` is a per-mount attribute or a mount operation rather than a file system option,
+/// return a hint where it belongs.
+fn misplaced_mount_option(key: &str) -> Option<&'static str> {
+    match key {
+        "ro" | "rw" | "nosuid" | "suid" | "nodev" | "dev" | "noexec" | "exec" | "noatime"
+        | "atime" | "relatime" | "norelatime" | "strictatime" | "nostrictatime" | "nodiratime"
+        | "diratime" | "nosymfollow" | "symfollow" => Some("use `MountAttr` instead"),
+        "bind" | "rbind" | "remount" | "move" | "defaults" => {
+            Some("it is an operation of `mount(8)`")
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opt(key: &str, value: Option<&str>) -> (String, Option<String>) {
+        (key.to_string(), value.map(str::to_string))
+    }
+
+    #[test]
+    fn split_options() {
+        assert_eq!(split_mount_options("").unwrap(), []);
+        assert_eq!(
+            split_mount_options(",size=1M,,mode=0755,").unwrap(),
+            [opt("size", Some("1M")), opt("mode", Some("0755"))],
+        );
+        assert_eq!(
+            split_mount_options("lowerdir=/a,key=a=b,inode64").unwrap(),
+            [
+                opt("lowerdir", Some("/a")),
+                opt("key", Some("a=b")),
+                opt("inode64", None),
+            ],
+        );
+        assert_eq!(
+            split_mount_options("context=\"a,b\",empty=").unwrap(),
+            [opt("context", Some("a,b")), opt("empty", Some(""))],
+        );
+        assert!(split_mount_options("context=\"a,b").is_err());
+    }
+
+    #[test]
+    fn misplaced_options() {
+        for key in [
+            "ro",
+            "rw",
+            "nosuid",
+            "nodev",
+            "noexec",
+            "relatime",
+            "nosymfollow",
+        ] {
+            assert_eq!(misplaced_mount_option(key), Some("use `MountAttr` instead"));
+        }
+        for key in ["bind", "remount", "defaults"] {
+            assert_eq!(
+                misplaced_mount_option(key),
+                Some("it is an operation of `mount(8)`"),
+            );
+        }
+        for key in ["size", "mode", "inode64", "user_xattr", "errors"] {
+            assert_eq!(misplaced_mount_option(key), None);
+        }
+    }
+
+    #[test]
+    fn configure_keeps_error() {
+        let fs = match crate::mount::Fs::open("tmpfs", crate::mount::FsOpen::CLOEXEC) {
+            Ok(fs) => fs,
+            Err(err) if crate::raw_os_error(&err) == Some(libc::EPERM) => {
+                eprintln!("skipping test, cannot open a file system context: {err}");
+                return;
+            }
+            Err(err) => panic!("failed to open tmpfs: {err}"),
+        };
+
+        let file = std::fs::File::open("/").unwrap();
+        let err = fs
+            .configure(|txn| {
+                txn.set_string("mode", "0755")
+                    .set_fd("lisy-no-such-option", file.as_fd());
+            })
+            .unwrap_err();
+        assert_eq!(crate::raw_os_error(&err), Some(libc::EINVAL));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(
+            err.to_string()
+                .starts_with("fsconfig operation 2 ('lisy-no-such-option' = fd "),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn apply_mount_options_keeps_error() {
+        let fs = match crate::mount::Fs::open("tmpfs", crate::mount::FsOpen::CLOEXEC) {
+            Ok(fs) => fs,
+            Err(err) if crate::raw_os_error(&err) == Some(libc::EPERM) => {
+                eprintln!("skipping test, cannot open a file system context: {err}");
+                return;
+            }
+            Err(err) => panic!("failed to open tmpfs: {err}"),
+        };
+
+        let err = fs
+            .apply_mount_options("mode=0755,lisy-no-such-option")
+            .unwrap_err();
+        assert_eq!(crate::raw_os_error(&err), Some(libc::EINVAL));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(
+            err.to_string()
+                .starts_with("failed to apply mount option 'lisy-no-such-option': "),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn set_blob_sizes() {
+        let fs = match crate::mount::Fs::open("tmpfs", crate::mount::FsOpen::CLOEXEC) {
+            Ok(fs) => fs,
+            Err(err) if crate::raw_os_error(&err) == Some(libc::EPERM) => {
+                eprintln!("skipping test, cannot open a file system context: {err}");
+                return;
+            }
+            Err(err) => panic!("failed to open tmpfs: {err}"),
+        };
+
+        let blob = vec![0u8; SuperblockRef::MAX_BLOB_SIZE + 1];
+        for blob in [&blob[..0], &blob[..]] {
+            let err = fs.set_blob("lisy-no-such-option", blob).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            // rejected before calling into the kernel
+            assert_eq!(crate::raw_os_error(&err), None, "{err}");
+        }
+
+        let blob = &blob[..SuperblockRef::MAX_BLOB_SIZE];
+        let err = fs.set_blob("lisy-no-such-option", blob).unwrap_err();
+        assert_eq!(crate::raw_os_error(&err), Some(libc::EINVAL), "{err}");
+    }
+
+    #[test]
+    fn fspick_from_mount() {
+        crate::test_util::in_mount_namespace(|| {
+            let mount = crate::test_util::tmpfs();
+            let blocks = || {
+                let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+                assert_eq!(unsafe { libc::fstatvfs(mount.as_raw_fd(), &mut stat) }, 0);
+                stat.f_blocks * stat.f_frsize
+            };
+
+            let mut sb = Superblock::fspick_from(&mount, FsPick::CLOEXEC).unwrap();
+            sb.set_string("size", "1M").unwrap();
+            sb.reconfigure().unwrap();
+            assert_eq!(blocks(), 1024 * 1024);
+        });
+    }
+}
@@ -40,6 +40,10 @@ pub use syscalls::*;
 
 bitflags! {
     /// Mount attributes for `Superblock::mount` or Mount::setattr.
+    ///
+    /// Note that `RELATIME`, `NOATIME` and `STRICTATIME` form a single *field* rather than
+    /// independent flags, with `RELATIME` being zero. See
+    /// [`AtimeMode`](crate::mount::AtimeMode).
     #[derive(Clone, Copy, Debug)]
     pub struct MountAttr: std::os::raw::c_uint {
         /// Read-only flag.
@@ -133,6 +137,10 @@ bitflags! {
 
 bitflags! {
     /// Mount propagation flags.
+    ///
+    /// The bits are the `MS_*` flags used by `mount(2)` and `mount_setattr(2)`: `UNBINDABLE` is
+    /// `MS_UNBINDABLE`, `PRIVATE` is `MS_PRIVATE`, `SLAVE` is `MS_SLAVE` and `SHARED` is
+    /// `MS_SHARED`. See [`to_ms_flag`](MountPropagation::to_ms_flag()).
     #[derive(Clone, Copy, Debug)]
     pub struct MountPropagation: u64 {
         /// An unbindable mount.
@@ -145,3 +153,21 @@ bitflags! {
         const SHARED     = 1<<20;
     }
 }
+
+impl MountPropagation {
+    /// Get the `MS_*` value for [`MountSetAttr::propagation`](crate::mount::MountSetAttr::propagation()).
+    ///
+    /// This allows restoring the propagation reported by `statmount(2)`. Note that a mount can be
+    /// both `SLAVE` and `SHARED` (receiving events from one peer group and propagating them to
+    /// another), but `mount_setattr(2)` only accepts a single type at a time and fails with
+    /// `EINVAL` otherwise. Such a state has to be restored in two steps, `SLAVE` first.
+    pub const fn to_ms_flag(self) -> u64 {
+        self.bits()
+    }
+}
+
+impl From<MountPropagation> for u64 {
+    fn from(propagation: MountPropagation) -> u64 {
+        propagation.to_ms_flag()
+    }
+}
@@ -0,0 +1,141 @@
+//! Walking the mount tree in depth-first order.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use crate::mount::sys::StatMountFlags;
+use crate::mount::{ListMounts, MountId, MountNsId, StatMount};
+
+/// Iterate over the mounts of the namespace `ns` (or the current one) in depth-first order,
+/// yielding each mount together with its depth, like the tree output of `findmnt`.
+///
+/// All mounts are listed via `listmount(2)`, and each one's parent id is queried with
+/// `statmount(2)` up front, which requires kernel 6.9 (6.10 for other namespaces). The tree is then
+/// walked lazily from this map. Mounts vanishing in the meantime are left out, and children are
+/// yielded in the order the kernel lists them.
+///
+/// The roots, at depth `0`, are the mounts whose parent is not part of the listing, usually just
+/// the namespace's root mount. A mount is never yielded twice, so a (theoretical) cycle in the
+/// parent relationships cannot lead to an endless iteration; mounts only reachable through a
+/// cycle are yielded as additional roots.
+pub fn iter_tree(ns: Option<MountNsId>) -> io::Result<impl Iterator<Item = (usize, MountId)>> {
+    let mut order = Vec::new();
+    let mut parents = BTreeMap::new();
+    for id in ListMounts::new(MountId::root(), ns) {
+        let id = id?;
+        let mut builder = StatMount::builder()
+            .basic_superblock_info(false)
+            .basic_mount_info(true)
+            .mount_id(id);
+        if let Some(ns) = ns {
+            builder = builder.mount_namespace(ns);
+        }
+        let parent = match builder.stat() {
+            Ok(stat) => stat.parent_id(),
+            Err(err) if crate::raw_os_error(&err) == Some(libc::ENOENT) => continue,
+            Err(err) => return Err(err),
+        };
+        order.push(id);
+        parents.insert(id, parent);
+    }
+
+    let mut roots = Vec::new();
+    let mut children: BTreeMap<MountId, Vec<MountId>> = BTreeMap::new();
+    for &id in &order {
+        match parents[&id] {
+            Some(parent) if parent != id && parents.contains_key(&parent) => {
+                children.entry(parent).or_default().push(id)
+            }
+            _ => roots.push(id),
+        }
+    }
+
+    Ok(TreeIter {
+        order: order.into_iter(),
+        roots: roots.into_iter(),
+        children,
+        seen: BTreeSet::new(),
+        stack: Vec::new(),
+    })
+}
+
+struct TreeIter {
+    /// All mounts, to pick up the ones only reachable through a cycle once the roots are done.
+    order: std::vec::IntoIter<MountId>,
+    roots: std::vec::IntoIter<MountId>,
+    children: BTreeMap<MountId, Vec<MountId>>,
+    seen: BTreeSet<MountId>,
+    stack: Vec<(usize, MountId)>,
+}
+
+impl Iterator for TreeIter {
+    type Item = (usize, MountId);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (depth, id) = match self.stack.pop() {
+                Some(entry) => entry,
+                None => (0, self.roots.next().or_else(|| self.order.next())?),
+            };
+            if !self.seen.insert(id) {
+                continue;
+            }
+
+            if let Some(children) = self.children.remove(&id) {
+                self.stack
+                    .extend(children.into_iter().rev().map(|child| (depth + 1, child)));
+            }
+            return Some((depth, id));
+        }
+    }
+}
+
+/// List `root` and all the mounts below it with their depth relative to `root`, parents before
+/// their children.
+///
+/// `listmount(2)` yields all the descendants of a mount at once, so this is a single listing plus
+/// one `statmount(2)` call per mount requesting `what` and [`StatMountFlags::MNT_BASIC`], the
+/// depths being derived from the parent ids. Mounts vanishing in the meantime are left out, along
+/// with the mounts below them.
+pub(crate) fn list_subtree(
+    root: MountId,
+    what: StatMountFlags,
+) -> io::Result<Vec<(usize, Box<StatMount>)>> {
+    let what = what | StatMountFlags::MNT_BASIC;
+
+    let mut mounts = vec![root.stat(what)?];
+    for id in ListMounts::new(root, None) {
+        let id = match id {
+            Ok(id) => id,
+            Err(err) if crate::raw_os_error(&err) == Some(libc::ENOENT) => break,
+            Err(err) => return Err(err),
+        };
+        match id.stat(what) {
+            Ok(stat) => mounts.push(stat),
+            Err(err) if crate::raw_os_error(&err) == Some(libc::ENOENT) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    let parents: BTreeMap<MountId, MountId> = mounts
+        .iter()
+        .filter_map(|stat| Some((stat.id()?, stat.parent_id()?)))
+        .collect();
+    let depth = |stat: &StatMount| {
+        let mut id = stat.id()?;
+        let mut depth = 0;
+        // the bound guards against (theoretical) cycles
+        while id != root && depth <= parents.len() {
+            id = *parents.get(&id)?;
+            depth += 1;
+        }
+        (id == root).then_some(depth)
+    };
+
+    let mut mounts: Vec<_> = mounts
+        .into_iter()
+        .filter_map(|stat| Some((depth(&stat)?, stat)))
+        .collect();
+    mounts.sort_by_key(|&(depth, _)| depth);
+    Ok(mounts)
+}
@@ -0,0 +1,228 @@
+//! Tearing down whole mount trees.
+
+use std::ffi::{CStr, OsStr, c_int};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use bitflags::bitflags;
+
+use crate::CPath;
+use crate::error::io_assert;
+use crate::mount::MountId;
+use crate::mount::sys::StatMountFlags;
+
+bitflags! {
+    /// Flags for [`unmount_tree`].
+    ///
+    /// Except for [`SKIP_BUSY`](UmountFlags::SKIP_BUSY), these are the `umount2(2)` flags and are
+    /// passed on for every unmount.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+    pub struct UmountFlags: c_int {
+        /// Abort pending requests before unmounting (`MNT_FORCE`). Only some network file systems
+        /// support this.
+        const FORCE     = libc::MNT_FORCE;
+        /// Detach the mounts right away, but clean them up only once they are no longer busy
+        /// (`MNT_DETACH`).
+        const DETACH    = libc::MNT_DETACH;
+        /// Mark the mounts as expired instead of unmounting them, unless they already were
+        /// (`MNT_EXPIRE`).
+        const EXPIRE    = libc::MNT_EXPIRE;
+        /// Do not follow a final symlink in the path (`UMOUNT_NOFOLLOW`).
+        const NOFOLLOW  = libc::UMOUNT_NOFOLLOW;
+        /// Leave busy mounts in place and continue with the rest of the tree, rather than
+        /// aborting at the first `EBUSY`. This is not passed to the kernel.
+        const SKIP_BUSY = 1 << 30;
+    }
+}
+
+/// Unmount the mount at `path` along with all the mounts below it, the deepest ones first.
+///
+/// `path` must be a mount point, otherwise this fails with an
+/// [`InvalidInput`](io::ErrorKind::InvalidInput) error rather than unmounting whatever mount
+/// `path` happens to live on.
+///
+/// The tree is walked via `listmount(2)` up front, and the mounts are then unmounted by the
+/// paths `statmount(2)` reports, children before their parents. Mounts stacked on top of each
+/// other are handled as well, since the upper one is a child of the one it covers. Before each
+/// unmount, the path is checked to still refer to the expected mount, and mounts which vanish in
+/// the meantime are skipped. Note that the mount points are resolved relative to the current root
+/// directory, so mounts outside of it cannot be unmounted.
+///
+/// If a mount is busy, this stops with the `EBUSY` error, leaving the rest of the tree mounted.
+/// With [`UmountFlags::SKIP_BUSY`] the busy mounts (and consequently their parents) are left in
+/// place instead, everything else is unmounted, and a
+/// [`ResourceBusy`](io::ErrorKind::ResourceBusy) error listing the remaining mount points is
+/// returned at the end. [`UmountFlags::DETACH`] avoids the problem altogether.
+///
+/// This requires kernel 6.8 for `listmount(2)` and 6.9 for looking up unique mount ids.
+pub fn unmount_tree<P>(path: &P, flags: UmountFlags) -> io::Result<()>
+where
+    P: ?Sized + CPath,
+{
+    path.c_path(|path| unmount_tree_do(path, flags))?
+}
+
+fn unmount_tree_do(path: &CStr, flags: UmountFlags) -> io::Result<()> {
+    let mut at_flags = libc::AT_NO_AUTOMOUNT;
+    if flags.contains(UmountFlags::NOFOLLOW) {
+        at_flags |= libc::AT_SYMLINK_NOFOLLOW;
+    }
+    let root = match crate::mount::mount_root_at(libc::AT_FDCWD, path, at_flags)? {
+        (id, Some(true)) => id,
+        (_, Some(false)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{path:?} is not a mount point"),
+            ));
+        }
+        (_, None) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "kernel does not report STATX_ATTR_MOUNT_ROOT",
+            ));
+        }
+    };
+
+    // parents come before their children, so this is the deepest mounts first
+    let mut mounts = crate::mount::tree::list_subtree(root, StatMountFlags::MNT_BASIC)?;
+    mounts.reverse();
+
+    let umount_flags = (flags - UmountFlags::SKIP_BUSY).bits();
+    let mut busy = Vec::new();
+    for (depth, stat) in mounts {
+        // unwrap: `list_subtree` only returns mounts with an id
+        let id = stat.id().unwrap();
+        if depth == 0 {
+            continue;
+        }
+        let Some(point) = mount_point(id)? else {
+            continue;
+        };
+        let result = point.c_path(|point| {
+            unmount_exact(
+                point,
+                id,
+                libc::AT_SYMLINK_NOFOLLOW,
+                umount_flags | libc::UMOUNT_NOFOLLOW,
+            )
+        })?;
+        handle_busy(result, flags, &mut busy, point)?;
+    }
+
+    let result = unmount_exact(path, root, at_flags, umount_flags);
+    let path = PathBuf::from(OsStr::from_bytes(path.to_bytes()));
+    handle_busy(result, flags, &mut busy, path)?;
+
+    if !busy.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::ResourceBusy,
+            format!("mounts left in place because they are busy: {busy:?}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Look up the mount point of `id`, or `None` if it was unmounted in the meantime.
+fn mount_point(id: MountId) -> io::Result<Option<PathBuf>> {
+    match id.stat(StatMountFlags::MNT_POINT) {
+        Ok(stat) => Ok(stat
+            .mount_point()
+            .map(|point| PathBuf::from(OsStr::from_bytes(point.to_bytes())))),
+        Err(err) if crate::raw_os_error(&err) == Some(libc::ENOENT) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Unmount `point` after making sure it still refers to the mount `id`.
+///
+/// A mount covered by another one counts as busy, as this happens when the covering mount was
+/// skipped for being busy itself.
+fn unmount_exact(point: &CStr, id: MountId, at_flags: c_int, flags: c_int) -> io::Result<()> {
+    let found =
+        crate::mount::unique_mount_id_at(libc::AT_FDCWD, point, at_flags | libc::AT_NO_AUTOMOUNT)?;
+    if found != id {
+        return Err(io::Error::new(
+            io::ErrorKind::ResourceBusy,
+            format!("mount {id:?} at {point:?} is covered by mount {found:?}"),
+        ));
+    }
+    let rc = unsafe { libc::umount2(point.as_ptr(), flags) };
+    io_assert!(rc == 0, "umount2");
+    Ok(())
+}
+
+/// Record an `EBUSY` failure if busy mounts are to be skipped, otherwise pass on the result.
+fn handle_busy(
+    result: io::Result<()>,
+    flags: UmountFlags,
+    busy: &mut Vec<PathBuf>,
+    point: PathBuf,
+) -> io::Result<()> {
+    match result {
+        Err(err)
+            if flags.contains(UmountFlags::SKIP_BUSY)
+                && err.kind() == io::ErrorKind::ResourceBusy =>
+        {
+            busy.push(point);
+            Ok(())
+        }
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::mount::MoveMount;
+    use crate::test_util::{TempDir, in_mount_namespace, tmpfs};
+
+    fn is_mount_root(path: &Path) -> bool {
+        let path = std::ffi::CString::new(path.as_os_str().as_bytes()).unwrap();
+        crate::mount::mount_root_at(libc::AT_FDCWD, &path, 0)
+            .unwrap()
+            .1
+            .unwrap()
+    }
+
+    #[test]
+    fn nested_tmpfs() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().to_path_buf();
+        let [a, b, c] = ["a", "a/b", "c"].map(|dir| root.join(dir));
+
+        in_mount_namespace(|| {
+            let mount_all = || {
+                tmpfs().move_mount(&root, MoveMount::empty()).unwrap();
+                for dir in [&a, &b, &c] {
+                    std::fs::create_dir(dir).unwrap();
+                    tmpfs().move_mount(dir, MoveMount::empty()).unwrap();
+                }
+            };
+
+            mount_all();
+            unmount_tree(&root, UmountFlags::empty()).unwrap();
+            assert!(!is_mount_root(&root));
+
+            // a busy mount keeps itself and its parents in place, each reported once
+            mount_all();
+            let file = std::fs::File::create(b.join("file")).unwrap();
+            let err = unmount_tree(&root, UmountFlags::empty()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::ResourceBusy, "{err}");
+            assert!(is_mount_root(&c), "stopped at the first busy mount");
+
+            let err = unmount_tree(&root, UmountFlags::SKIP_BUSY).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::ResourceBusy, "{err}");
+            let busy = format!("{:?}", [&b, &a, &root]);
+            assert!(err.to_string().ends_with(&busy), "{err}");
+            assert!(!is_mount_root(&c));
+            assert!(is_mount_root(&b));
+
+            drop(file);
+            unmount_tree(&root, UmountFlags::empty()).unwrap();
+            assert!(!is_mount_root(&root));
+        });
+    }
+}
@@ -0,0 +1,82 @@
+//! Polling for mounts to appear or disappear.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::CPath;
+use crate::fs::is_mount_point;
+
+/// The first polling interval, doubled after every check.
+const MIN_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The polling interval is capped to this.
+const MAX_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Wait until something is mounted at `path`.
+///
+/// This polls [`is_mount_point`] starting at an interval of 10ms, doubling it after every check up
+/// to a maximum of 500ms, until `path` is a mount root or the `timeout` elapses. A non-existent
+/// `path` counts as not being mounted.
+///
+/// A `timeout` too large to compute a deadline from, such as [`Duration::MAX`], waits forever.
+///
+/// Returns `true` if the mount appeared and `false` on timeout.
+pub fn wait_for_mount<P>(path: &P, timeout: Duration) -> io::Result<bool>
+where
+    P: ?Sized + CPath,
+{
+    poll(timeout, || match is_mount_point(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        other => other,
+    })
+}
+
+/// Wait until `path` is no longer a mount root, see [`wait_for_mount`] for the polling details.
+///
+/// If `path` disappears entirely, it counts as unmounted.
+///
+/// Returns `true` if the mount disappeared and `false` on timeout.
+pub fn wait_for_unmount<P>(path: &P, timeout: Duration) -> io::Result<bool>
+where
+    P: ?Sized + CPath,
+{
+    poll(timeout, || match is_mount_point(path) {
+        Ok(mounted) => Ok(!mounted),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(true),
+        Err(err) => Err(err),
+    })
+}
+
+fn poll<F>(timeout: Duration, mut check: F) -> io::Result<bool>
+where
+    F: FnMut() -> io::Result<bool>,
+{
+    let deadline = Instant::now().checked_add(timeout);
+    let mut interval = MIN_INTERVAL;
+    loop {
+        if check()? {
+            return Ok(true);
+        }
+
+        let now = Instant::now();
+        match deadline {
+            Some(deadline) if now >= deadline => return Ok(false),
+            Some(deadline) => std::thread::sleep(interval.min(deadline - now)),
+            None => std::thread::sleep(interval),
+        }
+        interval = (interval * 2).min(MAX_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_timeout() {
+        let tmp = crate::test_util::TempDir::new().unwrap();
+        let missing = tmp.path().join("missing");
+        assert!(wait_for_unmount(&missing, Duration::MAX).unwrap());
+        assert!(!wait_for_mount(&missing, Duration::ZERO).unwrap());
+    }
+}
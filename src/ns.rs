@@ -1,12 +1,17 @@
 //! Marker types for namespace types.
 
 use std::ffi::CStr;
+use std::fmt;
 use std::io;
 use std::marker::PhantomData;
 use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
 use std::os::raw::c_int;
 
+use crate::CPath;
+use crate::error::io_assert;
+use crate::mount::ListMounts;
 use crate::mount::ns::MountNsInfo;
+use crate::mount_types::MountId;
 use crate::open::OpenHow;
 
 /// Marker trait for namespace types. A namespace type has at least an associated procfs name, and
@@ -87,12 +92,46 @@ impl UnshareForChildren for Time {}
 
 impl UnshareForChildren for Pid {}
 
+/// Marks a namespace type as nested, so that each namespace has a parent of the same type which
+/// can be queried via `NS_GET_PARENT`.
+pub trait Hierarchical {}
+
+impl Hierarchical for Pid {}
+impl Hierarchical for User {}
+
+const NS_GET_PARENT: c_int = crate::ioctl::io(0xb7, 2);
+const NS_GET_NSTYPE: c_int = crate::ioctl::io(0xb7, 3);
+
+/// Get the procfs name of a namespace type from its `CLONE_*` constant, for error messages.
+fn type_name(ty: c_int) -> &'static str {
+    match ty {
+        libc::CLONE_NEWCGROUP => "cgroup",
+        libc::CLONE_NEWIPC => "ipc",
+        libc::CLONE_NEWNS => "mnt",
+        libc::CLONE_NEWNET => "net",
+        libc::CLONE_NEWPID => "pid",
+        CLONE_NEWTIME => "time",
+        libc::CLONE_NEWUSER => "user",
+        libc::CLONE_NEWUTS => "uts",
+        _ => "unknown",
+    }
+}
+
 /// A typed namespace file descriptor.
 pub struct NsFd<K: Kind> {
     fd: OwnedFd,
     _kind: PhantomData<K>,
 }
 
+impl<K: Kind> fmt::Debug for NsFd<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NsFd")
+            .field("kind", &K::PROCFS_NAME)
+            .field("fd", &self.fd)
+            .finish()
+    }
+}
+
 impl<K: Kind> AsRawFd for NsFd<K> {
     fn as_raw_fd(&self) -> RawFd {
         self.fd.as_raw_fd()
@@ -120,6 +159,12 @@ impl<K: Kind> FromRawFd for NsFd<K> {
     }
 }
 
+impl<K: Kind> From<NsFd<K>> for OwnedFd {
+    fn from(this: NsFd<K>) -> OwnedFd {
+        this.fd
+    }
+}
+
 impl<K: Kind> NsFd<K> {
     /// Open this process' namespace file descriptor of kind `K`.
     pub fn current() -> io::Result<Self> {
@@ -128,6 +173,93 @@ impl<K: Kind> NsFd<K> {
             _kind: PhantomData,
         })
     }
+
+    /// Open a namespace file, such as `/proc/<pid>/ns/net` or a bind mount of one, making sure it
+    /// actually is a namespace of kind `K`.
+    ///
+    /// Files of a different namespace type are rejected with an
+    /// [`InvalidInput`](io::ErrorKind::InvalidInput) error naming both types, and files which are
+    /// not namespaces at all fail with `ENOTTY`.
+    pub fn open<P: ?Sized + CPath>(path: &P) -> io::Result<Self> {
+        let fd = OpenHow::new_read().open(path)?;
+        let ty = unsafe { libc::ioctl(fd.as_raw_fd(), NS_GET_NSTYPE as _) };
+        io_assert!(ty >= 0, "ioctl");
+        if ty != K::TYPE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "expected a {} namespace, but found a {} namespace",
+                    type_name(K::TYPE),
+                    type_name(ty),
+                ),
+            ));
+        }
+        Ok(Self {
+            fd,
+            _kind: PhantomData,
+        })
+    }
+
+    /// Duplicate the namespace file descriptor.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            fd: self.fd.try_clone()?,
+            _kind: PhantomData,
+        })
+    }
+
+    /// Move the calling thread into this namespace via `setns(2)`.
+    ///
+    /// The usual `setns(2)` restrictions apply, for instance a multi-threaded process cannot join
+    /// a user namespace, and for pid and time namespaces only children created afterwards are
+    /// affected (see [`UnshareForChildren`]).
+    pub fn enter(&self) -> io::Result<()> {
+        let rc = unsafe { libc::setns(self.fd.as_raw_fd(), K::TYPE) };
+        io_assert!(rc == 0, "setns");
+        Ok(())
+    }
+}
+
+/// Join the namespace of kind `K` referred to by the file at `path`.
+///
+/// This opens the file via [`NsFd::open`], which checks that it actually is a `K` namespace, and
+/// only then enters it with [`NsFd::enter`], so a mislabeled path cannot make the thread end up
+/// in a namespace of the wrong type.
+///
+/// ``` rust, no_run
+/// # fn code() -> std::io::Result<()> {
+/// use lisy::ns::{self, Net};
+///
+/// ns::enter_path::<Net, _>("/run/netns/backend")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn enter_path<K: Kind, P: ?Sized + CPath>(path: &P) -> io::Result<()> {
+    NsFd::<K>::open(path)?.enter()
+}
+
+/// Run `f` in a new mount namespace, confining the mounts it performs.
+///
+/// Since `unshare(2)` of a mount namespace only affects the calling thread (which must not share
+/// its file system information with other threads, see `CLONE_FS`), `f` is run on a separate,
+/// freshly spawned thread, and this blocks until it finished. This way the namespaces of the
+/// calling thread and the rest of the process stay untouched regardless of how many threads there
+/// are, but `f` cannot rely on thread local state of the caller. Processes spawned by `f` inherit
+/// the new namespace.
+///
+/// Before running `f`, all mounts are made slaves (like `mount --make-rslave /`), so that mount
+/// events still propagate *into* the namespace, but nothing done inside propagates back out. Once
+/// the thread exits, the namespace and its mounts are released, unless they are still referenced,
+/// for example by a file descriptor returned from `f`.
+///
+/// This requires `CAP_SYS_ADMIN`. Unprivileged callers need to enter a user namespace first.
+#[cfg(feature = "mount")]
+pub fn with_mount_namespace<F, T>(f: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send,
+    T: Send,
+{
+    crate::mount::in_new_namespace(f)
 }
 
 impl NsFd<Mnt> {
@@ -136,6 +268,17 @@ impl NsFd<Mnt> {
         MountNsInfo::get_raw(self.as_raw_fd())
     }
 
+    /// List the mounts of this namespace, starting at its root.
+    ///
+    /// Listing the mounts of another process' namespace requires `CAP_SYS_ADMIN` in the user
+    /// namespace owning it. Note that the returned ids need to be stat'ed with
+    /// [`MountId::stat_ns`](crate::mount::MountId::stat_ns()) for namespaces other than the
+    /// current one.
+    pub fn list_mounts(&self) -> io::Result<ListMounts> {
+        let info = self.mount_info()?;
+        Ok(ListMounts::new(MountId::root(), Some(info.mnt_ns_id)))
+    }
+
     /// Get the next mount namespace information and a file descriptor for it.
     pub fn next_mount_info(&self) -> io::Result<(MountNsInfo, Self)> {
         MountNsInfo::next_raw(self.as_raw_fd())
@@ -146,3 +289,93 @@ impl NsFd<Mnt> {
         MountNsInfo::previous_raw(self.as_raw_fd())
     }
 }
+
+impl<K: Kind + Hierarchical> NsFd<K> {
+    /// Get the parent namespace.
+    ///
+    /// Fails with `EPERM` if this is the initial namespace, or the parent lies outside of the
+    /// caller's own namespace.
+    pub fn parent(&self) -> io::Result<Self> {
+        parent_raw(self.as_fd())
+    }
+
+    /// Iterate over the ancestors of this namespace, starting with its parent.
+    ///
+    /// `NS_GET_PARENT` only exists for pid and user namespaces. The iteration stops once the
+    /// initial namespace or the root of the caller's own view is reached (the kernel reports both
+    /// as `EPERM`), so for a namespace created by the caller, the last item is the caller's own
+    /// namespace.
+    pub fn hierarchy(&self) -> Hierarchy<'_, K> {
+        Hierarchy {
+            start: self.as_fd(),
+            current: None,
+            done: false,
+        }
+    }
+}
+
+fn parent_raw<K: Kind>(fd: BorrowedFd) -> io::Result<NsFd<K>> {
+    let rc = unsafe { libc::ioctl(fd.as_raw_fd(), NS_GET_PARENT as _) };
+    io_assert!(rc >= 0, "ioctl");
+    Ok(unsafe { NsFd::from_raw_fd(rc) })
+}
+
+/// Iterator over the ancestors of a namespace, see [`NsFd::hierarchy`].
+pub struct Hierarchy<'a, K: Kind> {
+    start: BorrowedFd<'a>,
+    current: Option<NsFd<K>>,
+    done: bool,
+}
+
+impl<K: Kind> Iterator for Hierarchy<'_, K> {
+    type Item = io::Result<NsFd<K>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let at = match &self.current {
+            Some(current) => current.as_fd(),
+            None => self.start,
+        };
+        let result = parent_raw::<K>(at).and_then(|parent| {
+            let item = parent.try_clone()?;
+            self.current = Some(parent);
+            Ok(item)
+        });
+
+        match result {
+            Ok(item) => Some(Ok(item)),
+            Err(err) if crate::raw_os_error(&err) == Some(libc::EPERM) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fstat(fd: RawFd) -> libc::stat {
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        assert_eq!(unsafe { libc::fstat(fd, &raw mut stat) }, 0);
+        stat
+    }
+
+    #[test]
+    fn try_clone() {
+        let ns = NsFd::<Net>::current().unwrap();
+        let clone = ns.try_clone().unwrap();
+        assert_ne!(ns.as_raw_fd(), clone.as_raw_fd());
+
+        let (a, b) = (fstat(ns.as_raw_fd()), fstat(clone.as_raw_fd()));
+        assert_eq!((a.st_dev, a.st_ino), (b.st_dev, b.st_ino));
+    }
+}
@@ -1,11 +1,19 @@
 //! Higher level `openat2` interface.
 
-use std::ffi::CStr;
+use std::error::Error as StdError;
+use std::ffi::{CStr, OsStr, c_int};
+use std::fmt;
 use std::fs::File;
 use std::io;
 use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
 
 use crate::CPath;
+use crate::error::{io_bail, io_bail_last, io_format_err};
+
+mod sandbox;
+pub use sandbox::SandboxRoot;
 
 /// Directory / base file descriptor which enforces that the path provided to a `*at()` functions
 /// must bee an absolute path.
@@ -66,6 +74,59 @@ impl RawOpenHow {
     }
 }
 
+/// The access mode of a file descriptor, used with [`OpenHow::reopen_mode`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum AccessMode {
+    /// `O_RDONLY`
+    ReadOnly,
+    /// `O_WRONLY`
+    WriteOnly,
+    /// `O_RDWR`
+    ReadWrite,
+}
+
+impl AccessMode {
+    /// The corresponding `O_*` flag.
+    pub const fn flags(self) -> c_int {
+        match self {
+            Self::ReadOnly => libc::O_RDONLY,
+            Self::WriteOnly => libc::O_WRONLY,
+            Self::ReadWrite => libc::O_RDWR,
+        }
+    }
+}
+
+/// An invalid flag combination passed to [`OpenHow::try_from_flags`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OpenHowError {
+    /// `O_RDONLY`, `O_WRONLY` and `O_RDWR` were combined.
+    InvalidAccessMode,
+
+    /// Bits which are not known `O_*` flags.
+    UnknownFlags(u64),
+
+    /// Two flags which cannot be used together.
+    Conflict(&'static str, &'static str),
+}
+
+impl StdError for OpenHowError {}
+
+impl fmt::Display for OpenHowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidAccessMode => f.write_str("conflicting access mode flags"),
+            Self::UnknownFlags(flags) => write!(f, "unknown open flags: {flags:#o}"),
+            Self::Conflict(a, b) => write!(f, "{a} cannot be combined with {b}"),
+        }
+    }
+}
+
+impl From<OpenHowError> for io::Error {
+    fn from(err: OpenHowError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
 /// A "builder" style `openat2(2)` interface.
 #[derive(Clone, Copy, Debug)]
 pub struct OpenHow<'a> {
@@ -127,6 +188,78 @@ impl OpenHow<'static> {
         how.flags |= libc::O_DIRECTORY as u64;
         Self { how, fd: None }
     }
+
+    /// Create an `OpenHow` with exactly the `O_*` flags in `flags`, checking them for mistakes the
+    /// kernel would only report as `EINVAL`.
+    ///
+    /// Nothing is implied, so `O_CLOEXEC` needs to be included explicitly if desired. The checks
+    /// are:
+    ///
+    /// - The access mode must be one of `O_RDONLY`, `O_WRONLY` or `O_RDWR`.
+    /// - No unknown bits may be set.
+    /// - `O_PATH` may only be combined with `O_DIRECTORY`, `O_NOFOLLOW` and `O_CLOEXEC`.
+    /// - `O_TMPFILE` requires write access and excludes `O_CREAT`.
+    /// - `O_CREAT` and `O_DIRECTORY` are mutually exclusive.
+    ///
+    /// Use [`flags`](OpenHow::flags()) to bypass these checks.
+    pub fn try_from_flags(flags: u64) -> Result<Self, OpenHowError> {
+        const fn f(flag: c_int) -> u64 {
+            flag as u64
+        }
+
+        const VALID: u64 = f(libc::O_ACCMODE)
+            | f(libc::O_CREAT)
+            | f(libc::O_EXCL)
+            | f(libc::O_NOCTTY)
+            | f(libc::O_TRUNC)
+            | f(libc::O_APPEND)
+            | f(libc::O_NONBLOCK)
+            | f(libc::O_DSYNC)
+            | f(libc::O_ASYNC)
+            | f(libc::O_DIRECT)
+            | f(libc::O_LARGEFILE)
+            | f(libc::O_DIRECTORY)
+            | f(libc::O_NOFOLLOW)
+            | f(libc::O_NOATIME)
+            | f(libc::O_CLOEXEC)
+            | f(libc::O_PATH)
+            | f(libc::O_TMPFILE)
+            | f(libc::O_SYNC);
+        const PATH_FLAGS: u64 =
+            f(libc::O_PATH) | f(libc::O_DIRECTORY) | f(libc::O_NOFOLLOW) | f(libc::O_CLOEXEC);
+
+        let unknown = flags & !VALID;
+        if unknown != 0 {
+            return Err(OpenHowError::UnknownFlags(unknown));
+        }
+
+        let access = flags & f(libc::O_ACCMODE);
+        if access == f(libc::O_ACCMODE) {
+            return Err(OpenHowError::InvalidAccessMode);
+        }
+
+        if flags & f(libc::O_PATH) != 0 && flags & !PATH_FLAGS != 0 {
+            return Err(OpenHowError::Conflict(
+                "O_PATH",
+                "flags other than O_DIRECTORY, O_NOFOLLOW and O_CLOEXEC",
+            ));
+        }
+
+        if flags & f(libc::O_TMPFILE) == f(libc::O_TMPFILE) {
+            if access == f(libc::O_RDONLY) {
+                return Err(OpenHowError::Conflict("O_TMPFILE", "O_RDONLY"));
+            }
+            if flags & f(libc::O_CREAT) != 0 {
+                return Err(OpenHowError::Conflict("O_TMPFILE", "O_CREAT"));
+            }
+        } else if flags & f(libc::O_CREAT) != 0 && flags & f(libc::O_DIRECTORY) != 0 {
+            return Err(OpenHowError::Conflict("O_CREAT", "O_DIRECTORY"));
+        }
+
+        let mut how = RawOpenHow::new_empty();
+        how.flags = flags;
+        Ok(Self { how, fd: None })
+    }
 }
 
 impl OpenHow<'_> {
@@ -161,7 +294,7 @@ impl OpenHow<'_> {
     }
 
     /// Set the root/beneath file descriptor.
-    pub fn at_fd<F>(self, fd: &F) -> OpenHow
+    pub fn at_fd<F>(self, fd: &F) -> OpenHow<'_>
     where
         F: ?Sized + AsFd,
     {
@@ -184,12 +317,70 @@ impl OpenHow<'_> {
         }
     }
 
+    /// Strict "beneath" sandbox policy relative to `fd`.
+    ///
+    /// This sets the root/beneath file descriptor to `fd` and enables the following resolve
+    /// flags:
+    ///
+    /// - `RESOLVE_BENEATH`: the path must not escape `fd` (absolute paths and `..` leading out of
+    ///   it fail with `EXDEV`).
+    /// - `RESOLVE_NO_SYMLINKS`: no symlinks at all anywhere in the path.
+    /// - `RESOLVE_NO_MAGICLINKS`: no magic links (eg. `/proc/*/fd/*`).
+    /// - `RESOLVE_NO_XDEV`: no crossing of mount points (including bind mounts).
+    ///
+    /// The individual toggles can still be used afterwards to relax the policy.
+    pub fn sandbox_beneath<F>(self, fd: &F) -> OpenHow<'_>
+    where
+        F: ?Sized + AsFd,
+    {
+        self.resolve_beneath(true)
+            .resolve_no_symlinks(true)
+            .resolve_no_magiclinks(true)
+            .resolve_no_xdev(true)
+            .at_fd(fd)
+    }
+
+    /// Strict "rooted" sandbox policy with `fd` as the root directory.
+    ///
+    /// This sets the root/beneath file descriptor to `fd` and enables the following resolve
+    /// flags:
+    ///
+    /// - `RESOLVE_IN_ROOT`: treat `fd` as the file system root, absolute paths, absolute symlinks
+    ///   and `..` are all resolved relative to and clamped to `fd`.
+    /// - `RESOLVE_NO_MAGICLINKS`: no magic links (eg. `/proc/*/fd/*`).
+    ///
+    /// Contrary to [`sandbox_beneath`](Self::sandbox_beneath()), regular symlinks and mount point
+    /// crossings stay allowed, as they cannot escape the root.
+    ///
+    /// The individual toggles can still be used afterwards to tighten or relax the policy.
+    pub fn sandbox_rooted<F>(self, fd: &F) -> OpenHow<'_>
+    where
+        F: ?Sized + AsFd,
+    {
+        self.resolve_in_root(true)
+            .resolve_no_magiclinks(true)
+            .at_fd(fd)
+    }
+
     /// Disallow magic link resolution (eg. files from `/proc` that magically resolve to specific
     /// resources).
     pub fn resolve_no_magiclinks(self, on: bool) -> Self {
         self.set_resolve(on, libc::RESOLVE_NO_MAGICLINKS)
     }
 
+    /// Explicitly allow magic link resolution by clearing `RESOLVE_NO_MAGICLINKS`.
+    ///
+    /// This is required to reopen files via `/proc/self/fd/N`, which is a magic link, eg. to get
+    /// a readable file descriptor from an `O_PATH` one. Use this after a preset such as
+    /// [`sandbox_beneath`](Self::sandbox_beneath()) to make the intent obvious, instead of
+    /// the equivalent `resolve_no_magiclinks(false)`.
+    ///
+    /// Note that `RESOLVE_NO_SYMLINKS`, `RESOLVE_BENEATH` and `RESOLVE_IN_ROOT` currently also
+    /// prevent magic link resolution.
+    pub fn allow_magiclinks(self) -> Self {
+        self.resolve_no_magiclinks(false)
+    }
+
     /// Disallow resolving symlinks generally *everywhere* in the provided path.
     ///
     /// Note that this is not the same as [`no_final_symlink`](Self::no_final_symlink()).
@@ -287,11 +478,146 @@ impl OpenHow<'_> {
         };
 
         if res < 0 {
-            return Err(io::Error::last_os_error());
+            io_bail_last!("openat2");
         }
         Ok(unsafe { OwnedFd::from_raw_fd(res as RawFd) })
     }
 
+    /// Open the path, emulating some resolve flags on kernels without `openat2(2)`.
+    ///
+    /// This first tries a regular [`open`](Self::open()). If that fails with `ENOSYS`, the path is
+    /// walked component by component via `openat(2)` instead. This emulation is best-effort and
+    /// slower, and only supports the following resolve flags:
+    ///
+    /// - `RESOLVE_BENEATH`: absolute paths and `..` components leading out of the starting
+    ///   directory fail with `EXDEV`. Since symlinks cannot be verified to stay beneath the
+    ///   starting directory, *all* symlinks are rejected.
+    /// - `RESOLVE_NO_SYMLINKS`: all symlinks are rejected (failing with either `ELOOP` or
+    ///   `ENOTDIR`).
+    ///
+    /// All other resolve flags (`RESOLVE_IN_ROOT`, `RESOLVE_NO_XDEV`, `RESOLVE_NO_MAGICLINKS`,
+    /// `RESOLVE_CACHED`) *cannot* be emulated and result in an [`Unsupported`] error.
+    ///
+    /// [`Unsupported`]: io::ErrorKind::Unsupported
+    pub fn open_with_fallback<P>(&self, path: &P) -> io::Result<OwnedFd>
+    where
+        P: ?Sized + CPath,
+    {
+        path.c_path(|path| match self.open_raw(path) {
+            Err(err) if crate::raw_os_error(&err) == Some(libc::ENOSYS) => self.open_emulated(path),
+            other => other,
+        })?
+    }
+
+    /// Emulate `openat2(2)` via `openat(2)`, see [`open_with_fallback`](Self::open_with_fallback).
+    fn open_emulated(&self, path: &CStr) -> io::Result<OwnedFd> {
+        const EMULATED: u64 = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_SYMLINKS;
+
+        if self.how.resolve & !EMULATED != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "resolve flags cannot be emulated without openat2",
+            ));
+        }
+
+        let dirfd = self.fd.map(|fd| fd.as_raw_fd()).unwrap_or(libc::AT_FDCWD);
+        let flags = self.how.flags as c_int;
+        let mode = self.how.mode as libc::mode_t;
+
+        if self.how.resolve == 0 {
+            return sys_openat(dirfd, path, flags, mode);
+        }
+
+        let beneath = self.how.resolve & libc::RESOLVE_BENEATH != 0;
+        let components: Vec<Component> = Path::new(OsStr::from_bytes(path.to_bytes()))
+            .components()
+            .collect();
+
+        let mut depth = 0usize;
+        let mut at_fd = dirfd;
+        let mut at_owned = None;
+        for (i, component) in components.iter().enumerate() {
+            let (flags, mode) = if i + 1 == components.len() {
+                (flags | libc::O_NOFOLLOW, mode)
+            } else {
+                (WALK_FLAGS, 0)
+            };
+
+            let next = match component {
+                Component::Normal(name) => {
+                    depth += 1;
+                    let name = crate::c_path::io_c_os_str(name)?;
+                    sys_openat(at_fd, &name, flags, mode)?
+                }
+                Component::CurDir => sys_openat(at_fd, c".", flags, mode)?,
+                Component::ParentDir => {
+                    if beneath {
+                        depth = depth
+                            .checked_sub(1)
+                            .ok_or_else(|| io::Error::from_raw_os_error(libc::EXDEV))?;
+                    }
+                    sys_openat(at_fd, c"..", flags, mode)?
+                }
+                Component::RootDir if beneath => {
+                    return Err(io::Error::from_raw_os_error(libc::EXDEV));
+                }
+                Component::RootDir => sys_openat(libc::AT_FDCWD, c"/", flags, mode)?,
+                Component::Prefix(_) => io_bail!("invalid path component ({component:?})"),
+            };
+            at_fd = next.as_raw_fd();
+            at_owned = Some(next);
+        }
+
+        match at_owned {
+            Some(fd) => Ok(fd),
+            // let the kernel produce the error for an empty path
+            None => sys_openat(dirfd, path, flags, mode),
+        }
+    }
+
+    /// Reopen a file descriptor with a different access mode via `/proc/thread-self/fd/N`.
+    ///
+    /// The access mode of this builder is replaced by `mode`. The `O_CREAT`, `O_EXCL`, `O_PATH`,
+    /// `O_TRUNC` and `O_NOFOLLOW` flags are dropped, since the magic link must be followed and the
+    /// file's contents must be left alone, and the other flags (such as `O_CLOEXEC` or
+    /// `O_NONBLOCK`) are kept. The resolve flags and the [`at_fd`](Self::at_fd()) are ignored,
+    /// since the path is a magic link in `/proc`, which must be mounted.
+    ///
+    /// This is typically used to downgrade a file descriptor to read-only, or to get a usable file
+    /// descriptor from an `O_PATH` one. Note that the kernel checks the file's permissions again
+    /// (and for an `O_PATH` file descriptor this is the only check), so upgrading from read-only to
+    /// read-write fails if the caller could not have opened the file that way to begin with. This
+    /// case is reported as an error of kind [`PermissionDenied`](io::ErrorKind::PermissionDenied).
+    pub fn reopen_mode<F>(&self, fd: &F, mode: AccessMode) -> io::Result<OwnedFd>
+    where
+        F: ?Sized + AsFd,
+    {
+        const REPLACED: u64 = (libc::O_ACCMODE
+            | libc::O_CREAT
+            | libc::O_EXCL
+            | libc::O_PATH
+            | libc::O_TRUNC
+            | libc::O_NOFOLLOW) as u64;
+
+        let fd = fd.as_fd().as_raw_fd();
+        let how = OpenHow {
+            how: RawOpenHow {
+                flags: (self.how.flags & !REPLACED) | mode.flags() as u64,
+                mode: 0,
+                resolve: 0,
+            },
+            fd: None,
+        };
+
+        match how.open(&format!("/proc/thread-self/fd/{fd}")) {
+            Err(err) if crate::raw_os_error(&err) == Some(libc::EACCES) => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("reopening file descriptor {fd} as {mode:?} was denied: {err}"),
+            )),
+            other => other,
+        }
+    }
+
     /// Open a file.
     pub fn open_file<P>(&self, path: &P) -> io::Result<File>
     where
@@ -300,4 +626,444 @@ impl OpenHow<'_> {
         let fd = self.open(path)?;
         Ok(unsafe { File::from_raw_fd(fd.into_raw_fd()) })
     }
+
+    /// Open the path and `statx(2)` the resulting file descriptor.
+    ///
+    /// The `mask` determines which information to query, any file descriptor or flags it has set
+    /// are replaced. Since the stat happens on the file descriptor via `AT_EMPTY_PATH`, the
+    /// metadata is guaranteed to describe the file that was opened, without resolving the path a
+    /// second time.
+    ///
+    /// ``` rust, no_run
+    /// # fn code() -> std::io::Result<()> {
+    /// use lisy::fs::Stat;
+    /// use lisy::open::OpenHow;
+    ///
+    /// let root = OpenHow::new_directory().open("/srv/container")?;
+    /// let (fd, meta) = OpenHow::new_read()
+    ///     .resolve_in_root(true)
+    ///     .at_fd(&root)
+    ///     .open_and_stat("/etc/passwd", Stat::new().unique_mount_id(true))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "fs")]
+    pub fn open_and_stat<P>(
+        &self,
+        path: &P,
+        mask: crate::fs::Stat<'_>,
+    ) -> io::Result<(OwnedFd, crate::fs::stat::Metadata)>
+    where
+        P: ?Sized + CPath,
+    {
+        let fd = self.open(path)?;
+        let meta = mask.at_fd(&fd).stat_fd()?;
+        Ok((fd, meta))
+    }
+
+    /// Open `path`, stat it and read its entire contents, all through the same file descriptor.
+    ///
+    /// The path is resolved only once, so the [basic stats](crate::fs::Stat::new()) always
+    /// describe the file the contents were read from, which makes them suitable as a cache key.
+    /// Note that the stat happens before reading, so a concurrent writer may still change the
+    /// contents in between. The access mode of this `OpenHow` needs to allow reading.
+    #[cfg(feature = "fs")]
+    pub fn read_with_metadata<P>(
+        &self,
+        path: &P,
+    ) -> io::Result<(Vec<u8>, crate::fs::stat::Metadata)>
+    where
+        P: ?Sized + CPath,
+    {
+        use std::io::Read;
+
+        let (fd, meta) = self.open_and_stat(path, crate::fs::Stat::new())?;
+        let capacity = meta
+            .size()
+            .and_then(|size| usize::try_from(size).ok())
+            .unwrap_or(0);
+        let mut data = Vec::with_capacity(capacity);
+        File::from(fd).read_to_end(&mut data)?;
+        Ok((data, meta))
+    }
+
+    /// Open a file with `O_CREAT`, creating its missing parent directories with `dir_mode` first
+    /// if necessary.
+    ///
+    /// The file is opened as is first. Only if that fails with `ENOENT`, the parents are created
+    /// one by one and the open is retried once. Any other error, such as `EACCES` or `EXDEV`, is
+    /// returned right away, without creating anything.
+    ///
+    /// Each parent is resolved with exactly this `OpenHow`'s resolve flags, relative to its file
+    /// descriptor, just like the final open, and the missing directory is created inside of it
+    /// via `mkdirat(2)`. So `RESOLVE_IN_ROOT`, `RESOLVE_BENEATH`, `RESOLVE_NO_SYMLINKS` and
+    /// `RESOLVE_NO_XDEV` confine the directory creation the same way they confine the file. Note
+    /// that the directories created before a failing retry are left in place.
+    pub fn open_file_mkdir<P>(&self, path: &P, dir_mode: u32) -> io::Result<File>
+    where
+        P: ?Sized + CPath,
+    {
+        path.c_path(|path| self.open_file_mkdir_raw(path, dir_mode))?
+    }
+
+    /// Perform [`open_file_mkdir`](Self::open_file_mkdir()).
+    fn open_file_mkdir_raw(&self, path: &CStr, dir_mode: u32) -> io::Result<File> {
+        let how = self.set_flags(true, libc::O_CREAT as u64);
+        match how.open_raw(path) {
+            Err(err) if crate::raw_os_error(&err) == Some(libc::ENOENT) => (),
+            other => return other.map(File::from),
+        }
+
+        let walk = OpenHow {
+            how: RawOpenHow {
+                flags: (libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC) as u64,
+                mode: 0,
+                resolve: self.how.resolve,
+            },
+            fd: self.fd,
+        };
+        let parent = Path::new(OsStr::from_bytes(path.to_bytes())).parent();
+        let mut prefix = PathBuf::new();
+        for component in parent.into_iter().flat_map(Path::components) {
+            if let Component::Normal(name) = component {
+                let dir = if prefix.as_os_str().is_empty() {
+                    walk.open(c".")?
+                } else {
+                    walk.open(&prefix)?
+                };
+                let name = crate::c_path::io_c_os_str(name)?;
+                let rc = unsafe { libc::mkdirat(dir.as_raw_fd(), name.as_ptr(), dir_mode) };
+                if rc != 0 {
+                    let err = io::Error::last_os_error();
+                    if err.raw_os_error() != Some(libc::EEXIST) {
+                        return Err(crate::error::syscall_error("mkdirat", err));
+                    }
+                }
+            }
+            prefix.push(component);
+        }
+
+        how.open_raw(path).map(File::from)
+    }
+
+    /// Create a new file, failing with `EEXIST` if anything already exists at `path`.
+    ///
+    /// This adds the `O_CREAT | O_EXCL` flags and keeps everything else, including the access mode
+    /// and the resolve flags. With `O_EXCL` the kernel never follows a symlink in the *final*
+    /// component, not even a dangling one, so combined with
+    /// [`resolve_in_root`](Self::resolve_in_root()) or
+    /// [`resolve_beneath`](Self::resolve_beneath()) the created file is guaranteed to be inside
+    /// the root: the intermediate components are confined by the resolve flags, and a symlink
+    /// planted as the final component makes the call fail instead of being followed.
+    pub fn create_new_in<P>(&self, path: &P) -> io::Result<File>
+    where
+        P: ?Sized + CPath,
+    {
+        self.set_flags(true, (libc::O_CREAT | libc::O_EXCL) as u64)
+            .open_file(path)
+    }
+
+    /// Open a file for appending.
+    ///
+    /// This replaces the access mode with `O_WRONLY` and adds the `O_CREAT | O_APPEND` flags. If
+    /// the [`mode`](Self::mode()) is left at its default of `0`, a newly created file gets `0o666`
+    /// (minus the umask), like with [`File::create`].
+    ///
+    /// See [`AppendWriter`] for a writer suitable for multiple processes appending to the same
+    /// file.
+    pub fn open_append<P>(&self, path: &P) -> io::Result<File>
+    where
+        P: ?Sized + CPath,
+    {
+        let flags = (libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND) as u64;
+        let mut how = self
+            .set_flags(false, libc::O_ACCMODE as u64)
+            .set_flags(true, flags);
+        if how.how.mode == 0 {
+            how.how.mode = 0o666;
+        }
+        how.open_file(path)
+    }
+}
+
+/// Flags used to walk through the intermediate directories when emulating `openat2(2)`.
+const WALK_FLAGS: c_int = libc::O_PATH | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+
+/// The raw `openat(2)` call.
+fn sys_openat(dirfd: RawFd, path: &CStr, flags: c_int, mode: libc::mode_t) -> io::Result<OwnedFd> {
+    let fd = unsafe { libc::openat(dirfd, path.as_ptr(), flags, mode) };
+    if fd < 0 {
+        io_bail_last!("openat");
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Read the target of a symlink opened via `O_PATH | O_NOFOLLOW`.
+#[cfg(any(feature = "fs", feature = "mount"))]
+pub(crate) fn read_link_fd(fd: RawFd) -> io::Result<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    use crate::error::io_assert;
+
+    let mut buf = Vec::<u8>::with_capacity(256);
+    loop {
+        let rc = unsafe {
+            libc::readlinkat(
+                fd,
+                c"".as_ptr(),
+                buf.as_mut_ptr() as *mut libc::c_char,
+                buf.capacity(),
+            )
+        };
+        io_assert!(rc >= 0, "readlinkat");
+        let len = rc as usize;
+        if len < buf.capacity() {
+            unsafe { buf.set_len(len) };
+            return Ok(PathBuf::from(OsString::from_vec(buf)));
+        }
+        buf.reserve(buf.capacity());
+    }
+}
+
+/// A writer performing each [`write_all`](io::Write::write_all()) as a single `write(2)` call.
+///
+/// This is meant for files opened with `O_APPEND` (see [`OpenHow::open_append`]), where multiple
+/// processes write to the same file, such as log files. Since the kernel positions and writes the
+/// data with the file's inode locked, each record ends up in one piece at the end of the file.
+///
+/// Note that this only holds if the kernel writes the whole buffer at once. This is the case for
+/// regular files on local file systems, but is only *guaranteed* by POSIX for pipes and writes of
+/// up to `PIPE_BUF` bytes. A short write is reported as an error rather than retried, since the
+/// retry could end up interleaved with writes from other processes.
+#[derive(Debug)]
+pub struct AppendWriter {
+    file: File,
+}
+
+impl AppendWriter {
+    /// Wrap a file. The file should have been opened with `O_APPEND`.
+    pub fn new(file: File) -> Self {
+        Self { file }
+    }
+
+    /// Get the underlying file back.
+    pub fn into_inner(self) -> File {
+        self.file
+    }
+}
+
+impl From<File> for AppendWriter {
+    fn from(file: File) -> Self {
+        Self::new(file)
+    }
+}
+
+impl io::Write for AppendWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        loop {
+            match self.file.write(buf) {
+                Ok(n) if n == buf.len() => return Ok(()),
+                Ok(n) => {
+                    return Err(io_format_err!(
+                        "short write in append mode ({n} of {} bytes)",
+                        buf.len()
+                    ));
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl AsRawFd for AppendWriter {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for AppendWriter {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+impl IntoRawFd for AppendWriter {
+    fn into_raw_fd(self) -> RawFd {
+        self.file.into_raw_fd()
+    }
+}
+
+impl FromRawFd for AppendWriter {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        Self::new(unsafe { File::from_raw_fd(fd) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn create_new_in_does_not_follow_escaping_symlinks() {
+        let tmp = TempDir::new().unwrap();
+        let root_path = tmp.path().join("root");
+        let outside = tmp.path().join("outside");
+        std::fs::create_dir(&root_path).unwrap();
+        std::fs::create_dir(&outside).unwrap();
+        symlink(outside.join("target"), root_path.join("link")).unwrap();
+        symlink(&outside, root_path.join("dir")).unwrap();
+        symlink("../outside", root_path.join("up")).unwrap();
+
+        let root = OpenHow::new_directory().open(&root_path).unwrap();
+        for how in [
+            OpenHow::new_write().resolve_beneath(true),
+            OpenHow::new_write().resolve_in_root(true),
+        ] {
+            let how = how.mode(0o644).at_fd(&root);
+            for path in ["link", "dir/file", "up/file"] {
+                let err = how.create_new_in(path).unwrap_err();
+                assert!(
+                    matches!(
+                        crate::raw_os_error(&err),
+                        Some(libc::EEXIST | libc::EXDEV | libc::ENOENT)
+                    ),
+                    "unexpected error for {path}: {err}"
+                );
+            }
+        }
+
+        assert_eq!(std::fs::read_dir(&outside).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn reopen_mode_keeps_contents() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("file");
+        std::fs::write(&path, b"data").unwrap();
+
+        let fd = OpenHow::new_read().open(&path).unwrap();
+        let how = OpenHow::new_write().truncate(true).no_final_symlink(true);
+        let fd = how.reopen_mode(&fd, AccessMode::ReadWrite).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(format!("/proc/self/fd/{}", fd.as_raw_fd())).unwrap(),
+            "data"
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), b"data");
+    }
+
+    const fn f(flag: c_int) -> u64 {
+        flag as u64
+    }
+
+    #[test]
+    fn try_from_flags_accepts_valid_flags() {
+        for flags in [
+            f(libc::O_RDONLY),
+            f(libc::O_RDWR | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC),
+            f(libc::O_WRONLY | libc::O_APPEND | libc::O_NOFOLLOW | libc::O_SYNC),
+            f(libc::O_PATH | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC),
+            f(libc::O_TMPFILE | libc::O_RDWR | libc::O_EXCL),
+            f(libc::O_DIRECTORY | libc::O_NOATIME),
+        ] {
+            let how = OpenHow::try_from_flags(flags).unwrap();
+            assert_eq!(how.how.flags, flags);
+            assert_eq!(how.how.resolve, 0);
+            assert!(how.fd.is_none());
+        }
+    }
+
+    #[test]
+    fn try_from_flags_rejects_invalid_flags() {
+        let err = |flags| OpenHow::try_from_flags(flags).unwrap_err();
+        assert_eq!(err(f(libc::O_ACCMODE)), OpenHowError::InvalidAccessMode);
+        assert_eq!(err(1 << 40), OpenHowError::UnknownFlags(1 << 40));
+        assert_eq!(
+            err(f(libc::O_RDONLY) | 1 << 40 | 1 << 41),
+            OpenHowError::UnknownFlags(1 << 40 | 1 << 41)
+        );
+        for flags in [
+            libc::O_PATH | libc::O_RDWR,
+            libc::O_PATH | libc::O_CREAT,
+            libc::O_PATH | libc::O_APPEND,
+        ] {
+            assert!(
+                matches!(err(f(flags)), OpenHowError::Conflict("O_PATH", _)),
+                "{flags:#o}"
+            );
+        }
+        assert_eq!(
+            err(f(libc::O_TMPFILE)),
+            OpenHowError::Conflict("O_TMPFILE", "O_RDONLY")
+        );
+        assert_eq!(
+            err(f(libc::O_TMPFILE | libc::O_WRONLY | libc::O_CREAT)),
+            OpenHowError::Conflict("O_TMPFILE", "O_CREAT")
+        );
+        assert_eq!(
+            err(f(libc::O_CREAT | libc::O_DIRECTORY)),
+            OpenHowError::Conflict("O_CREAT", "O_DIRECTORY")
+        );
+
+        let err = io::Error::from(err(f(libc::O_ACCMODE)));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn open_file_mkdir_stays_confined() {
+        let tmp = TempDir::new().unwrap();
+        let root_path = tmp.path().join("root");
+        let outside = tmp.path().join("outside");
+        std::fs::create_dir(&root_path).unwrap();
+        std::fs::create_dir(&outside).unwrap();
+        symlink("../outside", root_path.join("up")).unwrap();
+        symlink("/sub", root_path.join("abs")).unwrap();
+        std::fs::create_dir(root_path.join("sub")).unwrap();
+
+        let root = OpenHow::new_directory().open(&root_path).unwrap();
+        let beneath = OpenHow::new_write().mode(0o644).resolve_beneath(true);
+        beneath
+            .at_fd(&root)
+            .open_file_mkdir("a/b/file", 0o755)
+            .unwrap();
+        assert!(root_path.join("a/b/file").is_file());
+
+        for path in ["up/x/file", "abs/x/file", "/a/file"] {
+            let err = beneath
+                .at_fd(&root)
+                .open_file_mkdir(path, 0o755)
+                .unwrap_err();
+            assert_eq!(
+                crate::raw_os_error(&err),
+                Some(libc::EXDEV),
+                "{path}: {err}"
+            );
+        }
+
+        let in_root = OpenHow::new_write().mode(0o644).resolve_in_root(true);
+        in_root
+            .at_fd(&root)
+            .open_file_mkdir("abs/x/file", 0o755)
+            .unwrap();
+        assert!(root_path.join("sub/x/file").is_file());
+        let err = in_root
+            .at_fd(&root)
+            .open_file_mkdir("up/x/file", 0o755)
+            .unwrap_err();
+        assert_eq!(crate::raw_os_error(&err), Some(libc::ENOENT), "{err}");
+
+        assert_eq!(std::fs::read_dir(&outside).unwrap().count(), 0);
+    }
 }
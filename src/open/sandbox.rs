@@ -0,0 +1,93 @@
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+
+use crate::CPath;
+use crate::open::OpenHow;
+
+/// A cached `O_PATH` directory handle used as the root for repeated opens.
+///
+/// All paths opened through this are resolved via [`OpenHow::sandbox_rooted`], that is, with
+/// `RESOLVE_IN_ROOT | RESOLVE_NO_MAGICLINKS` and this directory as the root. Since the directory is
+/// only opened once, renaming or replacing it afterwards does not affect later opens.
+#[derive(Debug)]
+pub struct SandboxRoot {
+    fd: OwnedFd,
+}
+
+impl SandboxRoot {
+    /// Open a directory as a sandbox root.
+    pub fn open<P>(path: &P) -> io::Result<Self>
+    where
+        P: ?Sized + CPath,
+    {
+        let fd = OpenHow::new_directory()
+            .flags(libc::O_PATH as u64)
+            .open(path)?;
+        Ok(Self { fd })
+    }
+
+    /// Use an already open directory as a sandbox root.
+    pub fn from_fd(fd: OwnedFd) -> Self {
+        Self { fd }
+    }
+
+    /// Open a path inside the root with custom flags.
+    ///
+    /// The sandbox resolve flags are added to `how`, and its file descriptor is replaced.
+    pub fn open_with<P>(&self, how: OpenHow, path: &P) -> io::Result<OwnedFd>
+    where
+        P: ?Sized + CPath,
+    {
+        how.sandbox_rooted(&self.fd).open(path)
+    }
+
+    /// Open a file inside the root for reading.
+    pub fn file<P>(&self, path: &P) -> io::Result<File>
+    where
+        P: ?Sized + CPath,
+    {
+        OpenHow::new_read().sandbox_rooted(&self.fd).open_file(path)
+    }
+
+    /// Open a directory inside the root as a new sandbox root.
+    pub fn subdir<P>(&self, path: &P) -> io::Result<Self>
+    where
+        P: ?Sized + CPath,
+    {
+        let fd = self.open_with(OpenHow::new_directory().flags(libc::O_PATH as u64), path)?;
+        Ok(Self { fd })
+    }
+}
+
+impl AsRawFd for SandboxRoot {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for SandboxRoot {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl IntoRawFd for SandboxRoot {
+    fn into_raw_fd(self) -> RawFd {
+        self.fd.into_raw_fd()
+    }
+}
+
+impl FromRawFd for SandboxRoot {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        }
+    }
+}
+
+impl From<SandboxRoot> for OwnedFd {
+    fn from(this: SandboxRoot) -> OwnedFd {
+        this.fd
+    }
+}
@@ -1,11 +1,17 @@
 //! `pidfds` are handles to processes which can be polled and used to send signals and other
 //! operations, they are much more powerful than numerical PIDs.
 
-use std::ffi::{c_int, c_uint, c_void};
+use std::ffi::{OsString, c_int, c_uint, c_void};
 use std::io;
 use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
-
-use crate::error::io_assert;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::process::CommandExt as _;
+use std::process::{Child, Command};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::error::{io_assert, io_bail, io_format_err};
 use crate::ns::NsFd;
 
 #[rustfmt::skip]
@@ -60,6 +66,12 @@ impl FromRawFd for PidFd {
     }
 }
 
+impl From<PidFd> for OwnedFd {
+    fn from(this: PidFd) -> OwnedFd {
+        this.fd
+    }
+}
+
 impl AsFd for PidFd {
     fn as_fd(&self) -> BorrowedFd<'_> {
         self.fd.as_fd()
@@ -78,7 +90,7 @@ macro_rules! ns_fd_getters {
             pub fn $name(&self) -> io::Result<NsFd<crate::ns::$nsty>> {
                 unsafe {
                     let fd = libc::ioctl(self.as_raw_fd(), $ioctl as u64, 0);
-                    io_assert!(fd >= 0);
+                    io_assert!(fd >= 0, "ioctl");
                     Ok(NsFd::from_raw_fd(fd))
                 }
             }
@@ -90,7 +102,7 @@ impl PidFd {
     /// Open a pidfd for a process via its process ID.
     pub fn open(pid: libc::pid_t, flags: PidFdFlags) -> io::Result<Self> {
         let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, flags.bits()) };
-        io_assert!(fd >= 0);
+        io_assert!(fd >= 0, "pidfd_open");
         Ok(unsafe { Self::from_raw_fd(i32::try_from(fd).unwrap()) })
     }
 
@@ -100,6 +112,141 @@ impl PidFd {
         Self::open(pid, flags)
     }
 
+    /// Get a pid fd for a child process spawned via [`std::process::Command`].
+    ///
+    /// As long as the [`Child`] has not been waited for, the process cannot
+    /// be reaped and its pid cannot be reused, so this is race free *unless* something else reaps
+    /// the child, such as a `waitpid(-1, ...)` call elsewhere in the program or `SIGCHLD` being
+    /// set to `SIG_IGN`. In that case the pid may already belong to a different process.
+    ///
+    /// Use [`CommandExt::spawn_with_pidfd`] to avoid this window entirely.
+    pub fn from_child(child: &Child) -> io::Result<Self> {
+        let pid = libc::pid_t::try_from(child.id()).map_err(io::Error::other)?;
+        Self::open(pid, PidFdFlags::empty())
+    }
+
+    /// Get the process' PID in the current PID namespace.
+    ///
+    /// This uses the `PIDFD_GET_INFO` `ioctl(2)` where available, and falls back to reading
+    /// `/proc/self/fdinfo/<fd>` on kernels before 6.13, which requires procfs to be mounted. Fails
+    /// with `ESRCH` if the process has already been reaped.
+    pub fn pid(&self) -> io::Result<libc::pid_t> {
+        let pid = match self.info_ioctl(GetInfoFlags::PID)? {
+            Some(info) => info.raw.pid as libc::pid_t,
+            None => self.fdinfo()?.pid,
+        };
+        match pid {
+            -1 => Err(io::Error::from_raw_os_error(libc::ESRCH)),
+            0 => io_bail!("process is not visible in the current pid namespace"),
+            pid => Ok(pid),
+        }
+    }
+
+    /// Read the pidfd's `/proc/self/fdinfo/<fd>` entry.
+    ///
+    /// Contrary to [`info`](PidFd::info()), this works on kernels before 6.13 and includes the
+    /// process' pids in nested pid namespaces.
+    pub fn fdinfo(&self) -> io::Result<FdInfo> {
+        let fdinfo = crate::proc::read_file(&format!("self/fdinfo/{}", self.as_raw_fd()))?;
+        let fdinfo = String::from_utf8(fdinfo)
+            .map_err(|_| io_format_err!("pidfd's fdinfo is not valid UTF-8"))?;
+
+        let parse = |value: &str| {
+            value
+                .parse::<libc::pid_t>()
+                .map_err(|_| io_format_err!("invalid pid in pidfd's fdinfo: {value:?}"))
+        };
+
+        let mut pid = None;
+        let mut ns_pids = Vec::new();
+        for line in fdinfo.lines() {
+            if let Some(value) = line.strip_prefix("Pid:") {
+                pid = Some(parse(value.trim())?);
+            } else if let Some(values) = line.strip_prefix("NSpid:") {
+                ns_pids = values
+                    .split_whitespace()
+                    .map(parse)
+                    .collect::<Result<_, _>>()?;
+            }
+        }
+
+        Ok(FdInfo {
+            pid: pid.ok_or_else(|| io_format_err!("no pid found in pidfd's fdinfo"))?,
+            ns_pids,
+        })
+    }
+
+    /// Iterate over the file descriptor numbers the process currently has open.
+    ///
+    /// This reads `/proc/<pid>/fd`, so it requires `/proc` to be mounted for the current PID
+    /// namespace. The numbers can be passed to [`get_fd`](PidFd::get_fd()) to duplicate them.
+    ///
+    /// The directory is opened before verifying the process is still alive, so the pid cannot
+    /// have been reused by the time iteration begins. However, the process may open and close file
+    /// descriptors concurrently, so the list is merely a snapshot.
+    #[cfg(feature = "fs")]
+    pub fn fds(&self) -> io::Result<impl Iterator<Item = io::Result<RawFd>> + use<>> {
+        let pid = self.pid()?;
+        let proc = crate::proc::open_dir_for("listing a process' file descriptors")?;
+        let dir = crate::fs::ReadDir::read_at(&proc, &format!("{pid}/fd"))?;
+        self.send_signal(0)?;
+
+        Ok(dir.map(|entry| {
+            let name = entry?.into_name();
+            name.to_str()
+                .and_then(|name| name.parse::<RawFd>().ok())
+                .ok_or_else(|| io_format_err!("invalid file descriptor entry {name:?}"))
+        }))
+    }
+
+    /// Read and parse the process' `/proc/<pid>/mountinfo` file.
+    ///
+    /// This is meant for older kernels without `statmount(2)` and `listmount(2)` support, and
+    /// requires `/proc` to be mounted for the current PID namespace.
+    #[cfg(feature = "mount")]
+    pub fn mountinfo(&self) -> io::Result<Vec<crate::mount::MountInfoEntry>> {
+        crate::mount::mountinfo::parse(&self.read_proc_file("mountinfo")?)
+    }
+
+    /// Get the process' name from `/proc/<pid>/comm`.
+    ///
+    /// The name is at most 15 bytes, usually the truncated name of the executable. Invalid UTF-8
+    /// is replaced.
+    pub fn comm(&self) -> io::Result<String> {
+        let data = self.read_proc_file("comm")?;
+        let data = data.strip_suffix(b"\n").unwrap_or(&data);
+        Ok(String::from_utf8_lossy(data).into_owned())
+    }
+
+    /// Get the process' command line arguments from `/proc/<pid>/cmdline`.
+    ///
+    /// This is empty for kernel threads and zombies. Note that processes can modify their own
+    /// command line.
+    pub fn cmdline(&self) -> io::Result<Vec<OsString>> {
+        let data = self.read_proc_file("cmdline")?;
+        let data = data.strip_suffix(b"\0").unwrap_or(&data);
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(data
+            .split(|&b| b == 0)
+            .map(|arg| OsString::from_vec(arg.to_vec()))
+            .collect())
+    }
+
+    /// Read a file from the process' `/proc/<pid>` directory.
+    ///
+    /// Like with [`mountinfo`](PidFd::mountinfo()), this requires `/proc` to be mounted for the
+    /// current PID namespace, and the process is verified to still be alive after reading the
+    /// file, so the pid cannot have been reused. The process may still `execve(2)` in between
+    /// calls though, so the results of several calls may not match each other.
+    fn read_proc_file(&self, name: &str) -> io::Result<Vec<u8>> {
+        let pid = self.pid()?;
+        let data = crate::proc::read_file(&format!("{pid}/{name}"))?;
+        self.send_signal(0)?;
+        Ok(data)
+    }
+
     /// Get a file descriptor from the process.
     ///
     /// This is *similar* to opening `/proc/pid/fd/FD`, but is actually more equivalent to a
@@ -107,7 +254,7 @@ impl PidFd {
     pub fn get_fd(&self, fd: RawFd) -> io::Result<OwnedFd> {
         unsafe {
             let fd = libc::syscall(libc::SYS_pidfd_getfd, self.as_raw_fd(), fd, 0);
-            io_assert!(fd >= 0);
+            io_assert!(fd >= 0, "pidfd_getfd");
             Ok(OwnedFd::from_raw_fd(i32::try_from(fd).unwrap()))
         }
     }
@@ -125,7 +272,7 @@ impl PidFd {
                 0,
             )
         };
-        io_assert!(rc == 0);
+        io_assert!(rc == 0, "pidfd_send_signal");
         Ok(())
     }
 
@@ -135,7 +282,7 @@ impl PidFd {
         unsafe {
             let mut info: libc::siginfo_t = std::mem::zeroed();
             let rc = libc::waitid(libc::P_PIDFD, self.as_raw_fd(), &raw mut info, flags.bits());
-            io_assert!(rc == 0);
+            io_assert!(rc == 0, "waitid");
         }
     }
     */
@@ -190,30 +337,354 @@ impl PidFd {
         uts_namespace(ioctls::PIDFD_GET_UTS_NAMESPACE) -> Uts;
     }
 
+    /// Get handles to all of the process' namespaces at once, for instance to compare them with
+    /// the ones of another process.
+    ///
+    /// All of the `PIDFD_GET_*_NAMESPACE` ioctls were added together in kernel 6.11, so on older
+    /// kernels every field is `None`. Otherwise, only namespace types the kernel was built without
+    /// (such as time namespaces without `CONFIG_TIME_NS`) are `None`. Since the namespaces are
+    /// queried one after the other, a process switching namespaces concurrently may produce a
+    /// mix of old and new ones. If the process has already exited, this fails with `ESRCH`.
+    ///
+    /// ``` rust, no_run
+    /// # fn code() -> std::io::Result<()> {
+    /// use lisy::pidfd::{PidFd, PidFdFlags};
+    ///
+    /// let ns = PidFd::this(PidFdFlags::empty())?.all_namespaces()?;
+    /// if let Some(mnt) = &ns.mount {
+    ///     println!("mount namespace {:?}", mnt.mount_info()?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn all_namespaces(&self) -> io::Result<Namespaces> {
+        fn supported<T>(result: io::Result<T>) -> io::Result<Option<T>> {
+            match result {
+                Ok(ns) => Ok(Some(ns)),
+                Err(err)
+                    if matches!(
+                        crate::raw_os_error(&err),
+                        Some(libc::ENOTTY | libc::EOPNOTSUPP)
+                    ) =>
+                {
+                    Ok(None)
+                }
+                Err(err) => Err(err),
+            }
+        }
+
+        Ok(Namespaces {
+            cgroup: supported(self.cgroup_namespace())?,
+            ipc: supported(self.ipc_namespace())?,
+            mount: supported(self.mount_namespace())?,
+            network: supported(self.network_namespace())?,
+            pid: supported(self.pid_namespace())?,
+            pid_for_children: supported(self.pid_namespace_for_children())?,
+            time: supported(self.time_namespace())?,
+            time_for_children: supported(self.time_namespace_for_children())?,
+            user: supported(self.user_namespace())?,
+            uts: supported(self.uts_namespace())?,
+        })
+    }
+
     /// Query information about the process.
     ///
     /// While the kernel provides bitflags for which information to query, all the current ones are
     /// returned even if not requested. For maximum compatibility, these flags should be included
     /// in the request, so this should be called as `fd.info(Default::default())`.
+    ///
+    /// This uses the `PIDFD_GET_INFO` `ioctl(2)` from kernel 6.13. On older kernels, the pid,
+    /// parent pid, thread group id and credentials are instead read from `/proc/<pid>/status`,
+    /// which requires `/proc` to be mounted for the current pid namespace. The cgroup id and exit
+    /// code are not available that way, and the process must not have been reaped yet.
     pub fn info(&self, flags: GetInfoFlags) -> io::Result<Info> {
-        unsafe {
-            let mut info = Info {
-                raw: CPidFdInfo {
-                    mask: flags.bits(),
-                    ..std::mem::zeroed()
-                },
-            };
-            let rc = libc::ioctl(
+        match self.info_ioctl(flags)? {
+            Some(info) => Ok(info),
+            None => self.info_from_proc(),
+        }
+    }
+
+    /// Run the `PIDFD_GET_INFO` ioctl, returning `None` if the kernel does not support it.
+    fn info_ioctl(&self, flags: GetInfoFlags) -> io::Result<Option<Info>> {
+        let mut info = Info {
+            raw: CPidFdInfo {
+                mask: flags.bits(),
+                ..unsafe { std::mem::zeroed() }
+            },
+        };
+        let rc = unsafe {
+            libc::ioctl(
                 self.as_raw_fd(),
                 ioctls::PIDFD_GET_INFO as u64,
                 &raw mut info.raw,
-            );
-            io_assert!(rc == 0);
-            Ok(info)
+            )
+        };
+        if rc == 0 {
+            return Ok(Some(info));
+        }
+
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ENOTTY) {
+            return Err(crate::error::syscall_error("ioctl", err));
+        }
+        Ok(None)
+    }
+
+    /// Fill in the [`Info`] from `/proc/<pid>/status` for kernels without `PIDFD_GET_INFO`.
+    fn info_from_proc(&self) -> io::Result<Info> {
+        let status = self.read_proc_file("status")?;
+        let status = String::from_utf8_lossy(&status);
+
+        let field = |name: &str| -> io::Result<Vec<u32>> {
+            let values = status
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+                .ok_or_else(|| io_format_err!("missing {name:?} in process status"))?;
+            values
+                .split_whitespace()
+                .map(|value| {
+                    value
+                        .parse::<u32>()
+                        .map_err(|_| io_format_err!("invalid {name:?} in process status"))
+                })
+                .collect()
+        };
+        let ids = |name: &str| -> io::Result<[u32; 4]> {
+            field(name)?
+                .try_into()
+                .map_err(|_| io_format_err!("invalid {name:?} in process status"))
+        };
+        let single = |name: &str| -> io::Result<u32> {
+            field(name)?
+                .first()
+                .copied()
+                .ok_or_else(|| io_format_err!("invalid {name:?} in process status"))
+        };
+
+        let [ruid, euid, suid, fsuid] = ids("Uid")?;
+        let [rgid, egid, sgid, fsgid] = ids("Gid")?;
+        Ok(Info {
+            raw: CPidFdInfo {
+                mask: (GetInfoFlags::PID | GetInfoFlags::CREDS).bits(),
+                cgroupid: 0,
+                pid: single("Pid")?,
+                tgid: single("Tgid")?,
+                ppid: single("PPid")?,
+                ruid,
+                rgid,
+                euid,
+                egid,
+                suid,
+                sgid,
+                fsuid,
+                fsgid,
+                exit_code: 0,
+            },
+        })
+    }
+
+    /// Duplicate the pidfd. The new descriptor refers to the same process and has `O_CLOEXEC` set.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            fd: self.fd.try_clone()?,
+        })
+    }
+}
+
+/// Wait for any of the processes referred to by `fds` to exit.
+///
+/// This polls all the pidfds via `ppoll(2)`, a pidfd becomes readable once its process exits.
+/// Returns the index of the first exited process in `fds`, or `None` if the timeout expired. With
+/// no timeout (or one too large to represent), this blocks until a process exits. An empty `fds`
+/// slice is rejected with an [`InvalidInput`](io::ErrorKind::InvalidInput) error, as there would
+/// be nothing to wait for.
+///
+/// Note that the process is *not* reaped by this.
+pub fn wait_any(fds: &[&PidFd], timeout: Option<Duration>) -> io::Result<Option<usize>> {
+    if fds.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no pidfds to wait for",
+        ));
+    }
+
+    let mut pollfds: Vec<libc::pollfd> = fds
+        .iter()
+        .map(|fd| libc::pollfd {
+            fd: fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    loop {
+        let remaining = deadline.map(|deadline| {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            libc::timespec {
+                tv_sec: remaining.as_secs() as libc::time_t,
+                tv_nsec: remaining.subsec_nanos() as _,
+            }
+        });
+
+        let rc = unsafe {
+            libc::ppoll(
+                pollfds.as_mut_ptr(),
+                pollfds.len() as libc::nfds_t,
+                remaining
+                    .as_ref()
+                    .map_or(std::ptr::null(), |ts| ts as *const libc::timespec),
+                std::ptr::null(),
+            )
+        };
+        if rc < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(crate::error::syscall_error("ppoll", err));
+        }
+        if rc == 0 {
+            return Ok(None);
+        }
+        if let Some(index) = pollfds.iter().position(|p| p.revents != 0) {
+            return Ok(Some(index));
+        }
+    }
+}
+
+/// Extension trait for [`Command`] to spawn processes along with a pidfd.
+pub trait CommandExt {
+    /// Spawn the command and return a pidfd for the child along with the [`Child`].
+    ///
+    /// Since `Command` cannot use `CLONE_PIDFD` on stable rust, this registers a `pre_exec` hook
+    /// in which the child opens a pidfd for *itself* and passes it to the parent over a socket
+    /// pair. The child cannot have been reaped at that point, so the pidfd is guaranteed to refer
+    /// to the spawned process, contrary to [`PidFd::from_child`].
+    ///
+    /// The hook stays registered on the command, but only does anything during this call, so the
+    /// command can still be spawned again by other means. Like any `pre_exec` hook, it makes
+    /// `Command` use `fork(2)` rather than `posix_spawn(3)`.
+    fn spawn_with_pidfd(&mut self) -> io::Result<(Child, PidFd)>;
+}
+
+impl CommandExt for Command {
+    fn spawn_with_pidfd(&mut self) -> io::Result<(Child, PidFd)> {
+        let mut socks = [-1 as c_int; 2];
+        let rc = unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+                0,
+                socks.as_mut_ptr(),
+            )
+        };
+        io_assert!(rc == 0, "socketpair");
+        let parent = unsafe { OwnedFd::from_raw_fd(socks[0]) };
+        let child = unsafe { OwnedFd::from_raw_fd(socks[1]) };
+
+        // The hook reads the socket from here, so that it does nothing for later spawns, where the
+        // file descriptor number may be in use for something else.
+        let slot = Arc::new(AtomicI32::new(child.as_raw_fd()));
+        let hook_slot = Arc::clone(&slot);
+        unsafe {
+            self.pre_exec(move || match hook_slot.load(Ordering::Relaxed) {
+                sock if sock < 0 => Ok(()),
+                sock => send_own_pidfd(sock),
+            });
+        }
+        let result = self.spawn();
+        slot.store(-1, Ordering::Relaxed);
+        drop(child);
+        let mut child = result?;
+
+        match recv_fd(&parent) {
+            Ok(fd) => Ok((child, PidFd { fd })),
+            Err(err) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(err)
+            }
         }
     }
 }
 
+/// A control message carrying a single file descriptor.
+#[repr(C)]
+struct FdCmsg {
+    hdr: libc::cmsghdr,
+    fd: c_int,
+}
+
+/// Open a pidfd for the current process and send it over `sock`. Runs in a `pre_exec` hook, so
+/// this must not allocate.
+fn send_own_pidfd(sock: RawFd) -> io::Result<()> {
+    let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, libc::getpid(), 0) };
+    if pidfd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let pidfd = pidfd as c_int;
+
+    let mut byte = 0u8;
+    let mut iov = libc::iovec {
+        iov_base: &mut byte as *mut u8 as *mut c_void,
+        iov_len: 1,
+    };
+    let mut cmsg: FdCmsg = unsafe { std::mem::zeroed() };
+    cmsg.hdr.cmsg_len = unsafe { libc::CMSG_LEN(std::mem::size_of::<c_int>() as c_uint) } as _;
+    cmsg.hdr.cmsg_level = libc::SOL_SOCKET;
+    cmsg.hdr.cmsg_type = libc::SCM_RIGHTS;
+    cmsg.fd = pidfd;
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = &mut cmsg as *mut FdCmsg as *mut c_void;
+    msg.msg_controllen = std::mem::size_of::<FdCmsg>() as _;
+
+    let rc = unsafe { libc::sendmsg(sock, &msg, 0) };
+    let result = if rc < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    };
+    unsafe { libc::close(pidfd) };
+    result
+}
+
+/// Receive the file descriptor sent by [`send_own_pidfd`].
+fn recv_fd(sock: &OwnedFd) -> io::Result<OwnedFd> {
+    let mut byte = 0u8;
+    let mut iov = libc::iovec {
+        iov_base: &mut byte as *mut u8 as *mut c_void,
+        iov_len: 1,
+    };
+    let mut cmsg: FdCmsg = unsafe { std::mem::zeroed() };
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = &mut cmsg as *mut FdCmsg as *mut c_void;
+    msg.msg_controllen = std::mem::size_of::<FdCmsg>() as _;
+
+    let rc = unsafe {
+        libc::recvmsg(
+            sock.as_raw_fd(),
+            &mut msg,
+            libc::MSG_CMSG_CLOEXEC | libc::MSG_DONTWAIT,
+        )
+    };
+    io_assert!(rc >= 0, "recvmsg");
+    let expected_len = unsafe { libc::CMSG_LEN(std::mem::size_of::<c_int>() as c_uint) } as usize;
+    if rc == 0
+        || (msg.msg_controllen as usize) < expected_len
+        || cmsg.hdr.cmsg_len as usize != expected_len
+        || cmsg.hdr.cmsg_level != libc::SOL_SOCKET
+        || cmsg.hdr.cmsg_type != libc::SCM_RIGHTS
+    {
+        io_bail!("child process did not send its pidfd");
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(cmsg.fd) })
+}
+
 bitflags::bitflags! {
     /// Flags for opening a pid file descriptor.
     #[derive(Clone, Copy, Debug)]
@@ -330,6 +801,33 @@ struct CPidFdInfo {
     exit_code: i32,
 }
 
+/// Handles to the namespaces of a process, see [`PidFd::all_namespaces`].
+///
+/// Each field is `None` if the kernel cannot provide the namespace.
+#[derive(Debug)]
+pub struct Namespaces {
+    /// The cgroup namespace.
+    pub cgroup: Option<NsFd<crate::ns::CGroup>>,
+    /// The IPC namespace.
+    pub ipc: Option<NsFd<crate::ns::Ipc>>,
+    /// The mount namespace.
+    pub mount: Option<NsFd<crate::ns::Mnt>>,
+    /// The network namespace.
+    pub network: Option<NsFd<crate::ns::Net>>,
+    /// The PID namespace the process lives in.
+    pub pid: Option<NsFd<crate::ns::Pid>>,
+    /// The PID namespace new children of the process are created in.
+    pub pid_for_children: Option<NsFd<crate::ns::Pid>>,
+    /// The time namespace the process lives in.
+    pub time: Option<NsFd<crate::ns::Time>>,
+    /// The time namespace new children of the process are created in.
+    pub time_for_children: Option<NsFd<crate::ns::Time>>,
+    /// The user namespace.
+    pub user: Option<NsFd<crate::ns::User>>,
+    /// The UTS namespace.
+    pub uts: Option<NsFd<crate::ns::Uts>>,
+}
+
 /// Information about a process retrieved via [`PidFd::info`](PidFd::info()).
 #[derive(Clone, Debug)]
 pub struct Info {
@@ -384,6 +882,22 @@ impl Info {
     }
 }
 
+/// The pidfd specific fields of `/proc/self/fdinfo/<fd>`, see [`PidFd::fdinfo`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FdInfo {
+    /// The pid in the pid namespace of the `/proc` instance used, usually the current one.
+    ///
+    /// This is `-1` if the process has been reaped and `0` if it is not visible in the namespace.
+    pub pid: libc::pid_t,
+
+    /// The pid in each pid namespace the process is visible in, starting with the one of `pid`
+    /// and ending with the process' own, innermost pid namespace.
+    ///
+    /// This is empty if the process is gone or not visible, or the kernel was built without pid
+    /// namespace support.
+    pub ns_pids: Vec<libc::pid_t>,
+}
+
 /// Credentials of a process.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub struct Credentials {
@@ -411,3 +925,56 @@ pub struct Credentials {
     /// The file system group id.
     pub fsgid: libc::gid_t,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use super::*;
+
+    #[test]
+    fn wait_any_timeouts() {
+        let err = wait_any(&[], None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let mut sleeping = Command::new("sleep").arg("10").spawn().unwrap();
+        let mut exiting = Command::new("true").spawn().unwrap();
+        let sleeping_fd = PidFd::from_child(&sleeping).unwrap();
+        let exiting_fd = PidFd::from_child(&exiting).unwrap();
+
+        let timeout = Some(Duration::from_millis(10));
+        assert_eq!(wait_any(&[&sleeping_fd], timeout).unwrap(), None);
+        let forever = Some(Duration::MAX);
+        assert_eq!(
+            wait_any(&[&sleeping_fd, &exiting_fd], forever).unwrap(),
+            Some(1)
+        );
+
+        exiting.wait().unwrap();
+        sleeping.kill().unwrap();
+        sleeping.wait().unwrap();
+    }
+
+    #[test]
+    fn all_namespaces() {
+        if crate::test_util::kernel_version() < (6, 11) {
+            eprintln!("skipping test, the pidfd namespace ioctls require kernel 6.11");
+            return;
+        }
+
+        let ns = PidFd::this(PidFdFlags::empty())
+            .unwrap()
+            .all_namespaces()
+            .unwrap();
+        assert!(format!("{ns:?}").contains("NsFd"));
+        let mount = ns
+            .mount
+            .expect("no mount namespace on kernel 6.11 or later");
+        assert!(ns.user.is_some());
+
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        assert_eq!(unsafe { libc::fstat(mount.as_raw_fd(), &mut stat) }, 0);
+        let ours = std::fs::metadata("/proc/self/ns/mnt").unwrap();
+        assert_eq!((stat.st_dev, stat.st_ino), (ours.dev(), ours.ino()));
+    }
+}
@@ -0,0 +1,172 @@
+//! Runtime detection of kernel features.
+
+use std::ffi::c_long;
+use std::fs::File;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr;
+
+use crate::mount::sys;
+
+/// The result of [`probe`]: which of the newer system calls the running kernel supports.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct KernelSupport {
+    /// `openat2(2)`, kernel 5.6.
+    pub openat2: bool,
+
+    /// `statmount(2)`, kernel 6.8.
+    pub statmount: bool,
+
+    /// `listmount(2)`, kernel 6.8.
+    pub listmount: bool,
+
+    /// `mount_setattr(2)`, kernel 5.12.
+    pub mount_setattr: bool,
+
+    /// `open_tree(2)`, kernel 5.2.
+    pub open_tree: bool,
+
+    /// `fsopen(2)`, kernel 5.2.
+    pub fsopen: bool,
+
+    /// `pidfd_open(2)`, kernel 5.3.
+    pub pidfd_open: bool,
+
+    /// Id mapped mounts, kernel 5.12.
+    ///
+    /// This is probed by passing `MOUNT_ATTR_IDMAP` along with the caller's user namespace from
+    /// `/proc/self/ns/user` to `mount_setattr(2)`, which requires procfs, otherwise this is
+    /// `false`. Note that this only reflects support in the kernel, file systems need to support
+    /// them individually.
+    pub idmapped_mounts: bool,
+}
+
+/// Check whether a system call exists.
+///
+/// The probes call the system calls with invalid parameters, such that they fail without any side
+/// effects. Only `ENOSYS` means the system call is not available.
+fn available(rc: c_long) -> bool {
+    rc == 0 || std::io::Error::last_os_error().raw_os_error() != Some(libc::ENOSYS)
+}
+
+/// Like [`available`] for system calls returning a file descriptor, which is closed again should
+/// the probe unexpectedly succeed.
+fn available_fd(rc: c_long) -> bool {
+    if rc >= 0 {
+        drop(unsafe { OwnedFd::from_raw_fd(rc as _) });
+        return true;
+    }
+    available(rc)
+}
+
+/// Check whether `mount_setattr(2)` knows about `MOUNT_ATTR_IDMAP`.
+///
+/// The attributes are checked before the path is looked up. An unknown attribute fails with
+/// `EINVAL`, while a known one gets as far as checking the user namespace, which is refused with
+/// `EPERM` for the initial one, or the invalid directory file descriptor, failing with `EBADF`.
+pub(crate) fn idmapped_mounts() -> bool {
+    let Ok(userns) = File::open("/proc/self/ns/user") else {
+        return false;
+    };
+    // `struct mount_attr`: attr_set, attr_clr, propagation, userns_fd
+    let attr: [u64; 4] = [
+        u64::from(sys::MountAttr::IDMAP.bits()),
+        0,
+        0,
+        userns.as_raw_fd() as u64,
+    ];
+    let rc = unsafe {
+        libc::syscall(
+            sys::SYS_mount_setattr,
+            -libc::EBADF,
+            c"".as_ptr(),
+            libc::AT_EMPTY_PATH,
+            attr.as_ptr(),
+            std::mem::size_of_val(&attr),
+        )
+    };
+    rc == 0
+        || !matches!(
+            std::io::Error::last_os_error().raw_os_error(),
+            Some(libc::ENOSYS | libc::EINVAL)
+        )
+}
+
+/// Detect which of the newer system calls are supported by the running kernel.
+///
+/// This issues harmless probe system calls with invalid arguments and only checks whether they
+/// fail with `ENOSYS`. Note that a seccomp filter (eg. in containers) may also produce `ENOSYS`.
+pub fn probe() -> KernelSupport {
+    unsafe {
+        // A zero sized `struct open_how` is rejected with `EINVAL`.
+        let openat2 = available(libc::syscall(
+            libc::SYS_openat2,
+            -libc::EBADF,
+            ptr::null::<u8>(),
+            ptr::null::<u8>(),
+            0,
+        ));
+
+        // A null request fails with `EFAULT`.
+        let statmount = available(libc::syscall(
+            sys::SYS_statmount,
+            ptr::null::<u8>(),
+            ptr::null::<u8>(),
+            0,
+            0,
+        ));
+        let listmount = available(libc::syscall(
+            sys::SYS_listmount,
+            ptr::null::<u8>(),
+            ptr::null::<u8>(),
+            0,
+            0,
+        ));
+
+        // A zero sized `struct mount_attr` is rejected with `EINVAL`.
+        let mount_setattr = available(libc::syscall(
+            sys::SYS_mount_setattr,
+            -libc::EBADF,
+            ptr::null::<u8>(),
+            0,
+            ptr::null::<u8>(),
+            0,
+        ));
+
+        // Invalid flags are rejected with `EINVAL`.
+        let open_tree = available_fd(libc::syscall(
+            sys::SYS_open_tree,
+            -libc::EBADF,
+            ptr::null::<u8>(),
+            !0u32,
+        ));
+        let fsopen = available_fd(libc::syscall(sys::SYS_fsopen, ptr::null::<u8>(), !0u32));
+        let pidfd_open = available_fd(libc::syscall(libc::SYS_pidfd_open, 0, !0u32));
+
+        KernelSupport {
+            openat2,
+            statmount,
+            listmount,
+            mount_setattr,
+            open_tree,
+            fsopen,
+            pidfd_open,
+            idmapped_mounts: mount_setattr && idmapped_mounts(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_matches_kernel_version() {
+        let version = crate::test_util::kernel_version();
+        let support = probe();
+        assert_eq!(support.openat2, version >= (5, 6));
+        assert_eq!(support.mount_setattr, version >= (5, 12));
+        if std::path::Path::new("/proc/self/ns/user").exists() {
+            assert_eq!(support.idmapped_mounts, version >= (5, 12));
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Helpers for reading files from `/proc`.
+//!
+//! Some operations in this crate have no syscall or ioctl based equivalent and hard-require procfs
+//! to be mounted at `/proc`:
+//!
+//! * [`Userns::builder`](crate::userns::Userns::builder()) writes the id maps and the `setgroups`
+//!   file of its helper process, and opens the namespace through its `ns/user` link.
+//! * [`PidFd::pid`](crate::pidfd::PidFd::pid()) and everything built on it, such as
+//!   [`PidFd::fds`](crate::pidfd::PidFd::fds()), `mountinfo`, `comm` and `cmdline`, as well as
+//!   [`PidFd::info`](crate::pidfd::PidFd::info()) on kernels without `PIDFD_GET_INFO`.
+//!
+//! When procfs is missing, these fail with a [`NotFound`](io::ErrorKind::NotFound) error saying so
+//! rather than with a bare `ENOENT`. The namespace handles of a [`PidFd`](crate::pidfd::PidFd),
+//! such as its `mount_namespace`, are obtained through `PIDFD_GET_*_NAMESPACE` ioctls instead and
+//! work without `/proc`.
+
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, OwnedFd};
+
+use crate::error::io_assert;
+use crate::open::OpenHow;
+
+/// Read a file from `/proc`, for example `self/status` or `1234/cmdline`.
+///
+/// `rel` is resolved beneath `/proc` with `RESOLVE_BENEATH`, so neither `..` components nor
+/// symlinks can make it escape from there. The symlinks procfs itself provides, such as `self`
+/// and `thread-self`, still work since they point to other directories within `/proc`, but
+/// absolute paths and magic links like `<pid>/cwd` are rejected (with `EXDEV` and `ELOOP`
+/// respectively).
+///
+/// This requires procfs to be mounted at `/proc`. Note that it shows the processes of the pid
+/// namespace it was mounted for, which may not be the current one.
+pub fn read_file(rel: &str) -> io::Result<Vec<u8>> {
+    let proc = open_dir_for(&format!("reading /proc/{rel}"))?;
+    let mut file = OpenHow::new_read()
+        .resolve_beneath(true)
+        .resolve_no_magiclinks(true)
+        .at_fd(&proc)
+        .open_file(rel)?;
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Open the `/proc` directory, making sure it actually is a procfs.
+///
+/// An empty `/proc` directory, as found in minimal containers, would otherwise only show up as a
+/// confusing `ENOENT` once a file inside it is opened. Instead, this fails with a
+/// [`NotFound`](io::ErrorKind::NotFound) error naming `purpose` as the reason procfs is needed.
+pub(crate) fn open_dir_for(purpose: &str) -> io::Result<OwnedFd> {
+    let not_mounted = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("procfs not mounted, required for {purpose}"),
+        )
+    };
+
+    let proc = match OpenHow::new_directory().open(c"/proc") {
+        Ok(proc) => proc,
+        Err(err) if crate::raw_os_error(&err) == Some(libc::ENOENT) => return Err(not_mounted()),
+        Err(err) => return Err(err),
+    };
+
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    let rc = unsafe { libc::fstatfs(proc.as_raw_fd(), &mut stat) };
+    io_assert!(rc == 0, "fstatfs");
+    if stat.f_type != libc::PROC_SUPER_MAGIC {
+        return Err(not_mounted());
+    }
+
+    Ok(proc)
+}
@@ -0,0 +1,82 @@
+//! Helpers for unit tests.
+
+// Which of these are used depends on the enabled features.
+#![allow(dead_code)]
+
+use std::ffi::CStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A directory below [`std::env::temp_dir`] which is removed, along with its contents, when
+/// dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create a new, empty directory.
+    pub fn new() -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "lisy-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+        ));
+        std::fs::create_dir(&path)?;
+        Ok(Self { path })
+    }
+
+    /// The path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Run `f` in a new mount namespace, or skip it if creating one is not permitted.
+#[cfg(feature = "mount")]
+pub fn in_mount_namespace<F>(f: F)
+where
+    F: FnOnce() + Send,
+{
+    match crate::mount::in_new_namespace(|| {
+        f();
+        Ok(())
+    }) {
+        Ok(()) => (),
+        Err(err) if crate::raw_os_error(&err) == Some(libc::EPERM) => {
+            eprintln!("skipping test, cannot create a mount namespace: {err}");
+        }
+        Err(err) => panic!("failed to create a mount namespace: {err}"),
+    }
+}
+
+/// The running kernel's major and minor version.
+pub fn kernel_version() -> (u32, u32) {
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::uname(&mut uts) }, 0);
+    let release = unsafe { CStr::from_ptr(uts.release.as_ptr()) }.to_string_lossy();
+    let mut parts = release
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|part| part.parse().unwrap_or(0));
+    (parts.next().unwrap_or(0), parts.next().unwrap_or(0))
+}
+
+/// Create a new, detached `tmpfs` mount.
+#[cfg(feature = "mount")]
+pub fn tmpfs() -> crate::mount::Mount {
+    use crate::mount::{Fs, FsMount, FsOpen, MountAttr};
+
+    let fs = Fs::open("tmpfs", FsOpen::CLOEXEC).unwrap();
+    fs.create()
+        .unwrap()
+        .mount(FsMount::CLOEXEC, MountAttr::empty())
+        .unwrap()
+}
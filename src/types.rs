@@ -3,6 +3,8 @@
 // This might not be a good module name...
 // But a "device id" is quite a "basic" thing at the system level...
 
+use std::fmt;
+
 /// A device id.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub struct Device {
@@ -11,3 +13,37 @@ pub struct Device {
     /// The minor number.
     pub minor: u32,
 }
+
+impl Device {
+    /// Split a raw `dev_t` into its major and minor numbers.
+    pub fn from_dev_t(dev: libc::dev_t) -> Self {
+        Self {
+            major: libc::major(dev),
+            minor: libc::minor(dev),
+        }
+    }
+
+    /// Combine the major and minor numbers into a raw `dev_t`.
+    pub fn to_dev_t(self) -> libc::dev_t {
+        libc::makedev(self.major, self.minor)
+    }
+}
+
+impl From<libc::dev_t> for Device {
+    fn from(dev: libc::dev_t) -> Self {
+        Self::from_dev_t(dev)
+    }
+}
+
+impl From<Device> for libc::dev_t {
+    fn from(dev: Device) -> Self {
+        dev.to_dev_t()
+    }
+}
+
+/// Formats the device as `major:minor`, like in `/proc/self/mountinfo`.
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.major, self.minor)
+    }
+}
@@ -1,4 +1,9 @@
 //! Module for simplified user namespace creation. Requires kernel >=5.3.
+//!
+//! Writing the id mappings, as well as retrieving the namespace, goes through the helper process'
+//! `/proc/<pid>` files, so procfs must be mounted at `/proc` for the current pid namespace. Without
+//! it, [`Userns::builder`] fails with a [`NotFound`](io::ErrorKind::NotFound) error explaining as
+//! much.
 
 use std::error::Error as StdError;
 use std::ffi::{c_int, c_void};
@@ -6,7 +11,9 @@ use std::fmt;
 use std::io;
 use std::ops::Range;
 use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::sync::{Mutex, PoisonError};
 
+use crate::error::io_bail;
 use crate::open::OpenHow;
 
 mod pipe;
@@ -17,11 +24,31 @@ pub struct Userns {
     fd: OwnedFd,
 }
 
-extern "C" fn userns_process(pipe: *mut c_void) -> c_int {
-    let pipe = unsafe { Box::from_raw(pipe as *mut Pipe) };
-    let Pipe { readable, writable } = *pipe;
+/// The pipes shared with the helper process.
+struct HelperPipes {
+    /// Closing the writable end terminates the helper.
+    release: Pipe,
+
+    /// The helper writes a byte once it is running inside the new namespace.
+    ready: Pipe,
+}
+
+extern "C" fn userns_process(pipes: *mut c_void) -> c_int {
+    let pipes = unsafe { Box::from_raw(pipes as *mut HelperPipes) };
+    let HelperPipes { release, ready } = *pipes;
+    let Pipe { readable, writable } = release;
 
     drop(writable);
+    drop(ready.readable);
+    let _ = unsafe {
+        libc::write(
+            ready.writable.as_raw_fd(),
+            b"1".as_ptr() as *const c_void,
+            1,
+        )
+    };
+    drop(ready.writable);
+
     let mut scratch = [0i8; 8];
     let _ = unsafe {
         libc::read(
@@ -39,6 +66,30 @@ impl Userns {
         Self { fd }
     }
 
+    /// Create a user namespace in which the current user and group are root, and which maps no
+    /// other ids.
+    ///
+    /// This is the "fake root" setup of `unshare --map-root-user`: the helper's `setgroups` file
+    /// is set to `deny` (which an unprivileged caller has to do before mapping group ids anyway),
+    /// the calling process' real uid and gid are mapped to `0`, and the namespace is returned. It
+    /// is suitable for [idmapping](crate::mount::MountSetAttr::idmap()) files owned by the
+    /// current user to root.
+    ///
+    /// No privileges are needed, but the kernel must allow unprivileged user namespaces, which
+    /// some distributions restrict (eg. via the `kernel.unprivileged_userns_clone` or
+    /// `user.max_user_namespaces` sysctls, or AppArmor). Like [`builder`](Userns::builder()), this
+    /// requires procfs to be mounted at `/proc`.
+    pub fn single_root() -> io::Result<Userns> {
+        let uid = unsafe { libc::getuid() };
+        let gid = unsafe { libc::getgid() };
+
+        let builder = Self::builder()?;
+        builder.wait_ready()?;
+        builder.deny_setgroups()?;
+        builder.map(&[IdMapping::new(0..1, uid)], &[IdMapping::new(0..1, gid)])?;
+        builder.into_fd()
+    }
+
     /// Create a builder for a user namespace.
     ///
     /// This spawns a process in the background.
@@ -47,7 +98,10 @@ impl Userns {
 
         // Used to terminate the child process...
         // to deal with glibc's clone implementation we put these on the heap:
-        let mut pipe = Box::new(Pipe::new()?);
+        let mut pipes = Box::new(HelperPipes {
+            release: Pipe::new()?,
+            ready: Pipe::new()?,
+        });
 
         let pid = unsafe {
             const STACK_SIZE: usize = 64 * 1024;
@@ -57,7 +111,7 @@ impl Userns {
                 userns_process,
                 stack.as_ptr().add(STACK_SIZE) as *mut c_void,
                 libc::CLONE_NEWUSER | libc::CLONE_PIDFD | libc::SIGCHLD,
-                &mut *pipe as *mut Pipe as *mut c_void,
+                &mut *pipes as *mut HelperPipes as *mut c_void,
                 &mut pid_fd as *mut c_int as *mut libc::pid_t,
             )
         };
@@ -67,19 +121,28 @@ impl Userns {
         }
         let pid_fd = unsafe { OwnedFd::from_raw_fd(pid_fd) };
 
-        let Pipe { readable, writable } = *pipe;
+        let HelperPipes { release, ready } = *pipes;
+        let Pipe { readable, writable } = release;
         drop(readable);
+        drop(ready.writable);
 
-        let uid_map = OpenHow::new_write().open(&format!("/proc/{pid}/uid_map"))?;
-        let gid_map = OpenHow::new_write().open(&format!("/proc/{pid}/gid_map"))?;
+        let proc = crate::proc::open_dir_for("user namespace setup")?;
+        let uid_map = open_proc_file(&proc, pid, "uid_map")?;
+        let gid_map = open_proc_file(&proc, pid, "gid_map")?;
+        // Opened up front as well, since once the helper exits, its `/proc` files belong to root.
+        let setgroups = open_proc_file(&proc, pid, "setgroups")?;
 
         drop(writable);
 
         Ok(UsernsBuilder {
             pid: Some(pid),
             pid_fd,
+            proc,
             uid_map: Some(uid_map),
             gid_map: Some(gid_map),
+            setgroups: Some(setgroups),
+            ready: ready.readable,
+            is_ready: Mutex::new(false),
         })
     }
 }
@@ -102,6 +165,12 @@ impl FromRawFd for Userns {
     }
 }
 
+impl From<Userns> for OwnedFd {
+    fn from(this: Userns) -> OwnedFd {
+        this.fd
+    }
+}
+
 impl AsFd for Userns {
     fn as_fd(&self) -> BorrowedFd<'_> {
         self.fd.as_fd()
@@ -113,8 +182,12 @@ pub struct UsernsBuilder {
     // must not removed except in the drop handler
     pid: Option<libc::pid_t>,
     pid_fd: OwnedFd,
+    proc: OwnedFd,
     uid_map: Option<OwnedFd>,
     gid_map: Option<OwnedFd>,
+    setgroups: Option<OwnedFd>,
+    ready: OwnedFd,
+    is_ready: Mutex<bool>,
 }
 
 impl Drop for UsernsBuilder {
@@ -196,6 +269,40 @@ impl IdMapping {
                 .map_err(|_| ParseIdMappingError)?,
         })
     }
+
+    /// Parse a line of `/etc/subuid` or `/etc/subgid`, which have the format
+    /// `<name>:<start>:<count>`.
+    ///
+    /// Unlike with [`parse_common`](IdMapping::parse_common()), the numbers are a range of ids in
+    /// the *parent* namespace which the user named by `<name>` (a user name or numeric uid) may
+    /// map. The file does not say where the range should end up inside the namespace, so the
+    /// returned mapping starts at namespace id 0; adjust [`ns_id`](IdMapping::ns_id) as needed.
+    ///
+    /// The name is not checked, filtering the lines for the user in question is up to the caller.
+    pub fn parse_subid_line(line: &str) -> Result<Self, ParseIdMappingError> {
+        let mut parts = line.trim().splitn(3, ':');
+        let name = parts.next().ok_or(ParseIdMappingError)?;
+        if name.is_empty() {
+            return Err(ParseIdMappingError);
+        }
+        let mut number = || {
+            parts
+                .next()
+                .ok_or(ParseIdMappingError)?
+                .parse::<u32>()
+                .map_err(|_| ParseIdMappingError)
+        };
+        let parent_id = number()?;
+        let len = number()?;
+        if len == 0 || parent_id.checked_add(len - 1).is_none() {
+            return Err(ParseIdMappingError);
+        }
+        Ok(Self {
+            ns_id: 0,
+            parent_id,
+            len,
+        })
+    }
 }
 
 impl From<(u32, u32, u32)> for IdMapping {
@@ -209,19 +316,94 @@ impl From<(u32, u32, u32)> for IdMapping {
 }
 
 impl UsernsBuilder {
+    /// Block until the helper process signals that it is running inside the new namespace.
+    ///
+    /// The mapping methods call this before writing the id maps, so it is only needed to control
+    /// exactly when the wait happens. Once the helper reported readiness this returns immediately.
+    /// If the helper died before doing so, an error is returned.
+    pub fn wait_ready(&self) -> io::Result<()> {
+        // Held while reading, so concurrent callers do not race for the single byte and see EOF.
+        let mut is_ready = self.is_ready.lock().unwrap_or_else(PoisonError::into_inner);
+        if *is_ready {
+            return Ok(());
+        }
+
+        let mut byte = 0u8;
+        loop {
+            let rc = unsafe { libc::read(self.ready.as_raw_fd(), &raw mut byte as *mut c_void, 1) };
+            match rc {
+                1 => break,
+                0 => io_bail!("user namespace helper process exited before becoming ready"),
+                _ => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+
+        *is_ready = true;
+        Ok(())
+    }
+
     /// Setup the user id mapping in the namespace, this can only be called once.
     pub fn map_uids(&self, mapping: &[IdMapping]) -> io::Result<()> {
+        self.wait_ready()?;
         // unwrap: we only remove these in the "into_fd" function.
-        Self::map_do(self.uid_map.as_ref().unwrap(), mapping)
+        Self::map_do(self.uid_map.as_ref().unwrap(), "uid_map", mapping)
     }
 
     /// Setup the group id mapping in the namespace, this can only be called once.
     pub fn map_gids(&self, mapping: &[IdMapping]) -> io::Result<()> {
+        self.wait_ready()?;
         // unwrap: we only remove these in the "into_fd" function.
-        Self::map_do(self.gid_map.as_ref().unwrap(), mapping)
+        Self::map_do(self.gid_map.as_ref().unwrap(), "gid_map", mapping)
     }
 
-    fn map_do(fd: &OwnedFd, mapping: &[IdMapping]) -> io::Result<()> {
+    /// Setup both the user and group id mappings in the correct order.
+    ///
+    /// The group ids are mapped first. If this is not permitted, which is the case when lacking
+    /// `CAP_SETGID` in the parent namespace, `setgroups(2)` is denied for the namespace and the
+    /// mapping is retried, as the kernel requires this for unprivileged group mappings. Afterwards
+    /// the user ids are mapped.
+    ///
+    /// Like [`map_uids`](Self::map_uids()) and [`map_gids`](Self::map_gids()), this can only be
+    /// done once.
+    pub fn map(&self, uids: &[IdMapping], gids: &[IdMapping]) -> io::Result<()> {
+        self.wait_ready()?;
+
+        // unwrap: we only remove these in the "into_fd" function.
+        let gid_map = self.gid_map.as_ref().unwrap();
+        let uid_map = self.uid_map.as_ref().unwrap();
+
+        match Self::map_do(gid_map, "gid_map", gids) {
+            Err(err) if crate::raw_os_error(&err) == Some(libc::EPERM) => {
+                self.deny_setgroups()?;
+                Self::map_do(gid_map, "gid_map", gids)?;
+            }
+            other => other?,
+        }
+
+        Self::map_do(uid_map, "uid_map", uids)
+    }
+
+    /// Write `deny` to the process' `setgroups` file.
+    fn deny_setgroups(&self) -> io::Result<()> {
+        // unwrap: we only remove this in the "into_fd" function.
+        let setgroups = self.setgroups.as_ref().unwrap();
+        let rc =
+            unsafe { libc::write(setgroups.as_raw_fd(), b"deny".as_ptr() as *const c_void, 4) };
+        if rc < 0 {
+            return Err(crate::error::syscall_error(
+                "write",
+                io::Error::last_os_error(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn map_do(fd: &OwnedFd, file: &str, mapping: &[IdMapping]) -> io::Result<()> {
         use std::io::Write as _;
 
         let mut data = Vec::new();
@@ -237,19 +419,30 @@ impl UsernsBuilder {
             )
         };
         if rc < 0 {
-            return Err(io::Error::last_os_error());
+            let err = crate::error::syscall_error("write", io::Error::last_os_error());
+            return Err(crate::error::context(
+                err,
+                format!("failed to write {file}"),
+            ));
         }
 
         Ok(())
     }
 
     /// Open the namespace file descriptor and drop the reference to the underlying helper process.
+    ///
+    /// The namespace is opened via `/proc/<pid>/ns/user`. The `PIDFD_GET_USER_NAMESPACE` ioctl is
+    /// no alternative here, since it fails with `ESRCH` once the helper has exited, which it
+    /// usually has by now.
     pub fn into_fd(mut self) -> io::Result<Userns> {
         let pid = self.pid.unwrap(); // we only take this out in the drop handler
-        let fd = OpenHow::new_read().open(&format!("/proc/{pid}/ns/user"))?;
+        let fd = OpenHow::new_read()
+            .at_fd(&self.proc)
+            .open(&format!("{pid}/ns/user"))?;
         // close the file descriptors
         self.uid_map = None;
         self.gid_map = None;
+        self.setgroups = None;
         loop {
             let rc = unsafe { libc::waitpid(pid, std::ptr::null_mut(), 0) };
             if rc < 0 {
@@ -264,6 +457,79 @@ impl UsernsBuilder {
     }
 }
 
+/// Create a user namespace from `/etc/subuid` and `/etc/subgid` style lines.
+///
+/// The lines, which should already be filtered for the user in question, are parsed with
+/// [`IdMapping::parse_subid_line`]. Their ranges are placed back to back inside the namespace, in
+/// order, starting at id 0, so with the lines `user:100000:65536` and `user:300000:1000` the
+/// namespace ids `0..65536` map to `100000..165536` and `65536..66536` to `300000..301000`.
+/// Empty lines are skipped.
+///
+/// Writing mappings of ids other than one's own requires `CAP_SETUID` and `CAP_SETGID` in the
+/// parent namespace, which is what `newuidmap(1)` usually provides. The resulting namespace can be
+/// used for [idmapped mounts](crate::mount::MountSetAttr::idmap()).
+///
+/// ``` rust, no_run
+/// # fn code() -> std::io::Result<()> {
+/// let subuid = std::fs::read_to_string("/etc/subuid")?;
+/// let subgid = std::fs::read_to_string("/etc/subgid")?;
+/// let ours = |line: &&str| line.starts_with("builder:");
+/// let uids: Vec<&str> = subuid.lines().filter(ours).collect();
+/// let gids: Vec<&str> = subgid.lines().filter(ours).collect();
+///
+/// let userns = lisy::userns::from_subid(&uids, &gids)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn from_subid(uid_lines: &[&str], gid_lines: &[&str]) -> io::Result<Userns> {
+    let uids = subid_mappings(uid_lines)?;
+    let gids = subid_mappings(gid_lines)?;
+    let builder = Userns::builder()?;
+    builder.map(&uids, &gids)?;
+    builder.into_fd()
+}
+
+/// Parse subid lines, stacking their ranges inside the namespace.
+fn subid_mappings(lines: &[&str]) -> io::Result<Vec<IdMapping>> {
+    let mut mappings = Vec::new();
+    let mut next_ns_id = 0u32;
+    for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+        let mut mapping = IdMapping::parse_subid_line(line).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid subid line {line:?}"),
+            )
+        })?;
+        mapping.ns_id = next_ns_id;
+        next_ns_id = next_ns_id.checked_add(mapping.len).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "subid ranges exceed the id space of the namespace",
+            )
+        })?;
+        mappings.push(mapping);
+    }
+    Ok(mappings)
+}
+
+/// Open one of the helper process' `/proc/<pid>` files for writing.
+///
+/// The helper is not reaped before the builder is done with it, so if its directory is missing,
+/// the procfs at `/proc` belongs to a different pid namespace.
+fn open_proc_file(proc: &OwnedFd, pid: libc::pid_t, name: &str) -> io::Result<OwnedFd> {
+    match OpenHow::new_write()
+        .at_fd(proc)
+        .open(&format!("{pid}/{name}"))
+    {
+        Err(err) if crate::raw_os_error(&err) == Some(libc::ENOENT) => {
+            io_bail!(
+                "/proc does not show process {pid}, procfs is mounted for another pid namespace"
+            )
+        }
+        other => other,
+    }
+}
+
 /// An error parsing a user/group id mapping.
 #[derive(Debug)]
 pub struct ParseIdMappingError;
@@ -275,3 +541,109 @@ impl fmt::Display for ParseIdMappingError {
 }
 
 impl StdError for ParseIdMappingError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(mapping: &IdMapping) -> (u32, u32, u32) {
+        (mapping.ns_id, mapping.parent_id, mapping.len)
+    }
+
+    #[test]
+    fn wait_ready_concurrently() {
+        let builder = match Userns::builder() {
+            Ok(builder) => builder,
+            Err(err) if matches!(crate::raw_os_error(&err), Some(libc::EPERM | libc::ENOSPC)) => {
+                eprintln!("skipping test, cannot create a user namespace: {err}");
+                return;
+            }
+            Err(err) => panic!("failed to create a user namespace: {err}"),
+        };
+
+        std::thread::scope(|scope| {
+            let waiters: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| builder.wait_ready()))
+                .collect();
+            for waiter in waiters {
+                waiter.join().unwrap().unwrap();
+            }
+        });
+        builder.wait_ready().unwrap();
+    }
+
+    #[test]
+    fn single_root() {
+        let userns = match Userns::single_root() {
+            Ok(userns) => userns,
+            Err(err) if matches!(crate::raw_os_error(&err), Some(libc::EPERM | libc::ENOSPC)) => {
+                eprintln!("skipping test, cannot create a user namespace: {err}");
+                return;
+            }
+            Err(err) => panic!("failed to create a user namespace: {err}"),
+        };
+
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0, "{}", io::Error::last_os_error());
+        if pid == 0 {
+            // Only async-signal-safe calls in the child, report the result via the exit status.
+            let ok = unsafe {
+                libc::setns(userns.as_raw_fd(), libc::CLONE_NEWUSER) == 0
+                    && libc::getuid() == 0
+                    && libc::getgid() == 0
+            };
+            unsafe { libc::_exit(if ok { 0 } else { 1 }) };
+        }
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+    }
+
+    #[test]
+    fn parse_subid_line() {
+        let mapping = IdMapping::parse_subid_line("user:100000:65536").unwrap();
+        assert_eq!(fields(&mapping), (0, 100000, 65536));
+        let mapping = IdMapping::parse_subid_line("  1000:200000:1\n").unwrap();
+        assert_eq!(fields(&mapping), (0, 200000, 1));
+        let mapping = IdMapping::parse_subid_line("user:4294967295:1").unwrap();
+        assert_eq!(fields(&mapping), (0, u32::MAX, 1));
+
+        for line in [
+            "",
+            ":100000:65536",
+            "user",
+            "user:100000",
+            "user:100000:",
+            "user:x:65536",
+            "user:100000:-1",
+            "user:100000:0",
+            "user:100000:65536:1",
+            "user:4294967295:2",
+        ] {
+            assert!(IdMapping::parse_subid_line(line).is_err(), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn subid_mappings_stack() {
+        let mappings =
+            subid_mappings(&["user:100000:65536", "", "user:300000:1000", "user:5:1"]).unwrap();
+        let mappings: Vec<_> = mappings.iter().map(fields).collect();
+        assert_eq!(
+            mappings,
+            [(0, 100000, 65536), (65536, 300000, 1000), (66536, 5, 1)]
+        );
+        assert!(subid_mappings(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn subid_mappings_errors() {
+        let err = subid_mappings(&["user:100000:65536", "user:bad"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // The ranges fit the parent's id space, but not back to back inside the namespace.
+        let err = subid_mappings(&["user:0:4294967295", "user:0:1"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(subid_mappings(&["user:0:4294967295"]).is_ok());
+    }
+}